@@ -1,339 +1,828 @@
-use std::cmp::Ord;
+use std::cmp::{Ord, Ordering};
 use std::mem;
+use std::ops::{Bound, RangeBounds};
+
+/*
+*   A B+tree. Every value sits in a leaf , inner nodes only hold copies of
+*   keys to find the way down , and every leaf is linked to the ones next to
+*   it so a scan goes from leaf to leaf without climbing back up.
+*
+*   Nodes live in `nodes` and point at each other by position , like the
+*   pages of core::paged_btree. A node that goes away is put on `free` and
+*   reused by the next split.
+*
+*   `order` is the minimum degree: a node holds at most 2 * order - 1 keys and
+*   every node but the root at least order - 1.
+*
+*   Unlike the B-tree this replaced , the nodes are not public: there is no
+*   `Node` , `root` or `is_full` any more , everything goes through the methods
+*   and cursors below. Inserting a key that is already there replaces its value
+*   , PagedBTree does the same.
+*/
+
+// where a node is in BTree::nodes
+type NodeId = usize;
+
+#[derive(Clone, Debug)]
+struct Node<K, V> {
+    keys: Vec<K>,
+    // only in leaves , one per key
+    values: Vec<V>,
+    // only in inner nodes , children[i] has the keys below keys[i] and
+    // children[i + 1] the ones from keys[i] on
+    children: Vec<NodeId>,
+    is_leaf: bool,
+    // the leaves before and after this one , only in leaves
+    prev: Option<NodeId>,
+    next: Option<NodeId>,
+}
+
+impl<K: Ord, V> Node<K, V> {
+    fn new(is_leaf: bool) -> Self {
+        Node {
+            keys: Vec::new(),
+            values: Vec::new(),
+            children: Vec::new(),
+            is_leaf,
+            prev: None,
+            next: None,
+        }
+    }
+
+    // the child that has (or would have) `key`
+    fn child_for(&self, key: &K) -> usize {
+        self.keys.partition_point(|k| k <= key)
+    }
+}
 
-#[derive(Clone, Debug, Default)]
-pub struct Node<K, V>
+#[derive(Clone, Debug)]
+pub struct BTree<K, V>
 where
     K: Ord + Clone + Default,
     V: Clone + Default,
 {
-    pub keys: Vec<K>,
-    pub values: Vec<V>,
-    pub children: Vec<Box<Node<K, V>>>,
-    pub is_leaf: bool,
+    nodes: Vec<Node<K, V>>,
+    free: Vec<NodeId>,
+    root: Option<NodeId>,
     pub order: usize,
 }
 
-impl<K, V> Node<K, V>
+impl<K, V> BTree<K, V>
 where
     K: Ord + Clone + Default,
     V: Clone + Default,
 {
-    fn new(is_leaf: bool, order: usize) -> Self {
-        Node {
-            keys: Vec::new(),
-            values: Vec::new(),
-            children: Vec::new(),
-            is_leaf,
+    pub fn new(order: usize) -> Self {
+        assert!(order >= 2, "BTree order must be at least 2");
+        BTree {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: None,
             order,
         }
     }
 
-    pub fn is_full(&self) -> bool {
-        self.keys.len() == (self.order * 2 - 1) as usize
-    }
-
-    fn find_key(&self, key: &K) -> usize {
-        let mut i = 0;
-        while i < self.keys.len() && self.keys[i] < *key {
-            i += 1;
+    /*
+     *   Builds the tree bottom-up from entries that are already in key order ,
+     *   instead of inserting them one by one and splitting from the root all
+     *   the time. Leaves and inner nodes are packed full , only the last two
+     *   of a level share what is left so neither is too small. The keys have
+     *   to come in order , which is only checked in debug builds. A key that
+     *   comes twice keeps the last value , like insert.
+     */
+    pub fn from_sorted_iter(order: usize, entries: impl IntoIterator<Item = (K, V)>) -> Self {
+        let mut tree = BTree::new(order);
+        let max_keys = tree.max_keys();
+
+        let mut leaves = Vec::new();
+        let mut leaf: Node<K, V> = Node::new(true);
+        for (key, value) in entries {
+            if leaf.keys.last() == Some(&key) {
+                *leaf.values.last_mut().expect("Leaf has no values") = value;
+                continue;
+            }
+            debug_assert!(
+                leaf.keys.last().is_none_or(|last| *last < key),
+                "from_sorted_iter needs the keys in order"
+            );
+            if leaf.keys.len() == max_keys {
+                leaves.push(tree.alloc(mem::replace(&mut leaf, Node::new(true))));
+            }
+            leaf.keys.push(key);
+            leaf.values.push(value);
+        }
+        if leaf.keys.is_empty() {
+            return tree;
+        }
+        leaves.push(tree.alloc(leaf));
+
+        if let [.., left, right] = leaves[..] {
+            if tree.nodes[right].keys.len() < tree.min_keys() {
+                let total = tree.nodes[left].keys.len() + tree.nodes[right].keys.len();
+                let keys = tree.nodes[left].keys.split_off(total.div_ceil(2));
+                let values = tree.nodes[left].values.split_off(total.div_ceil(2));
+                let node = &mut tree.nodes[right];
+                node.keys.splice(0..0, keys);
+                node.values.splice(0..0, values);
+            }
+        }
+        for pair in leaves.windows(2) {
+            tree.nodes[pair[0]].next = Some(pair[1]);
+            tree.nodes[pair[1]].prev = Some(pair[0]);
         }
-        i
-    }
-
-    fn borrow_from_prev(&mut self, idx: usize) {
-        let mut sibling = mem::take(&mut self.children[idx - 1]);
-        let child = &mut self.children[idx];
 
-        let last_key = sibling.keys.pop().expect("Sibling has no keys to borrow");
-        let last_value = sibling
-            .values
-            .pop()
-            .expect("Sibling has no values to borrow");
-        child.keys.insert(0, self.keys[idx - 1].clone());
-        child.values.insert(0, self.values[idx - 1].clone());
+        // every node of a level with the first key below it , for the separators above
+        let mut level: Vec<(K, NodeId)> = leaves
+            .into_iter()
+            .map(|id| (tree.nodes[id].keys[0].clone(), id))
+            .collect();
+        while level.len() > 1 {
+            let max_children = max_keys + 1;
+            let mut sizes = vec![max_children; level.len() / max_children];
+            let rest = level.len() % max_children;
+            if rest > 0 {
+                sizes.push(rest);
+            }
+            if let [.., left, right] = sizes[..] {
+                if right < order {
+                    let total = left + right;
+                    let n = sizes.len();
+                    sizes[n - 2] = total.div_ceil(2);
+                    sizes[n - 1] = total / 2;
+                }
+            }
 
-        self.keys[idx - 1] = last_key;
-        self.values[idx - 1] = last_value;
-        if !sibling.is_leaf {
-            if let Some(child_to_move) = sibling.children.pop() {
-                child.children.insert(0, child_to_move);
+            let mut children = level.into_iter();
+            level = Vec::with_capacity(sizes.len());
+            for size in sizes {
+                let mut node = Node::new(false);
+                let (first, id) = children.next().expect("Level has too few nodes");
+                node.children.push(id);
+                for (key, id) in children.by_ref().take(size - 1) {
+                    node.keys.push(key);
+                    node.children.push(id);
+                }
+                level.push((first, tree.alloc(node)));
             }
         }
+        tree.root = level.pop().map(|(_, id)| id);
+        tree
+    }
 
-        self.children[idx - 1] = sibling;
+    fn max_keys(&self) -> usize {
+        self.order * 2 - 1
     }
 
-    fn borrow_from_next(&mut self, idx: usize) {
-        let mut sibling = mem::take(&mut self.children[idx + 1]);
-        let child = &mut self.children[idx];
+    fn min_keys(&self) -> usize {
+        self.order - 1
+    }
 
-        let first_key = sibling.keys.remove(0);
-        let first_value = sibling.values.remove(0);
-        child.keys.push(self.keys[idx].clone());
-        child.values.push(self.values[idx].clone());
+    fn alloc(&mut self, node: Node<K, V>) -> NodeId {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
 
-        self.keys[idx] = first_key;
-        self.values[idx] = first_value;
+    fn release(&mut self, id: NodeId) {
+        self.nodes[id] = Node::new(true);
+        self.free.push(id);
+    }
 
-        if !sibling.is_leaf {
-            child.children.push(sibling.children.remove(0));
+    // the leaf that has (or would have) `key`
+    fn leaf_for(&self, key: &K) -> Option<NodeId> {
+        let mut id = self.root?;
+        while !self.nodes[id].is_leaf {
+            let node = &self.nodes[id];
+            id = node.children[node.child_for(key)];
         }
+        Some(id)
+    }
 
-        self.children[idx + 1] = sibling;
+    pub fn search(&self, key: &K) -> Option<&V> {
+        let leaf = &self.nodes[self.leaf_for(key)?];
+        let idx = leaf.keys.binary_search(key).ok()?;
+        Some(&leaf.values[idx])
     }
 
-    fn merge(&mut self, idx: usize) {
-        let mut sibling = self.children.remove(idx + 1);
-        let child = &mut self.children[idx];
+    pub fn delete(&mut self, key: &K) {
+        let root = match self.root {
+            Some(root) => root,
+            None => return,
+        };
+        self.delete_from(root, key);
+
+        let node = &self.nodes[root];
+        if node.keys.is_empty() {
+            self.root = if node.is_leaf {
+                None
+            } else {
+                Some(node.children[0])
+            };
+            self.release(root);
+        }
+    }
 
-        child.keys.push(self.keys.remove(idx));
-        child.values.push(self.values.remove(idx));
+    // removes `key` below `id` , a child that got too small borrows or merges
+    fn delete_from(&mut self, id: NodeId, key: &K) {
+        if self.nodes[id].is_leaf {
+            let leaf = &mut self.nodes[id];
+            if let Ok(idx) = leaf.keys.binary_search(key) {
+                leaf.keys.remove(idx);
+                leaf.values.remove(idx);
+            }
+            return;
+        }
 
-        child.keys.extend(sibling.keys.clone());
-        child.values.extend(sibling.values.clone());
+        let idx = self.nodes[id].child_for(key);
+        let child = self.nodes[id].children[idx];
+        self.delete_from(child, key);
+        if self.nodes[child].keys.len() < self.min_keys() {
+            self.fill(id, idx);
+        }
+    }
 
-        if !child.is_leaf {
-            child.children.extend(sibling.children.drain(..));
+    // children[idx] of `parent` has too few keys
+    fn fill(&mut self, parent: NodeId, idx: usize) {
+        let children = &self.nodes[parent].children;
+        let left = idx.checked_sub(1).map(|i| children[i]);
+        let right = children.get(idx + 1).copied();
+        let can_lend = |id: &NodeId| self.nodes[*id].keys.len() > self.min_keys();
+
+        if let Some(left) = left.filter(can_lend) {
+            self.borrow_from_prev(parent, idx, left);
+        } else if let Some(right) = right.filter(can_lend) {
+            self.borrow_from_next(parent, idx, right);
+        } else if left.is_some() {
+            self.merge(parent, idx - 1);
+        } else {
+            self.merge(parent, idx);
         }
     }
 
-    fn fill(&mut self, idx: usize) {
-        if idx > 0 && self.children[idx - 1].keys.len() >= self.order {
-            self.borrow_from_prev(idx);
-        } else if idx < self.children.len() - 1 && self.children[idx + 1].keys.len() >= self.order {
-            self.borrow_from_next(idx);
+    fn borrow_from_prev(&mut self, parent: NodeId, idx: usize, left: NodeId) {
+        let child = self.nodes[parent].children[idx];
+        let sibling = &mut self.nodes[left];
+        let key = sibling.keys.pop().expect("Sibling has no keys to borrow");
+        if sibling.is_leaf {
+            let value = sibling
+                .values
+                .pop()
+                .expect("Sibling has no values to borrow");
+            let node = &mut self.nodes[child];
+            node.keys.insert(0, key.clone());
+            node.values.insert(0, value);
+            // the child starts with the borrowed key now
+            self.nodes[parent].keys[idx - 1] = key;
         } else {
-            if idx < self.children.len() - 1 {
-                self.merge(idx);
-            } else {
-                self.merge(idx - 1);
-            }
+            let moved = sibling.children.pop().expect("Sibling has no children");
+            let separator = mem::replace(&mut self.nodes[parent].keys[idx - 1], key);
+            let node = &mut self.nodes[child];
+            node.keys.insert(0, separator);
+            node.children.insert(0, moved);
         }
     }
 
-    fn delete_internal_node(&mut self, key: &K, idx: usize) {
-        if self.children[idx].keys.len() >= self.order {
-            let pred_key = self.get_predecessor_key(idx);
-            let pred_value = self.get_predecessor_value(idx);
-            self.keys[idx] = pred_key.clone();
-            self.values[idx] = pred_value.clone();
-            self.children[idx].delete(&pred_key);
-        } else if self.children[idx + 1].keys.len() >= self.order {
-            let succ_key = self.get_successor_key(idx);
-            let succ_value = self.get_successor_value(idx);
-            self.keys[idx] = succ_key.clone();
-            self.values[idx] = succ_value.clone();
-            self.children[idx + 1].delete(&succ_key);
+    fn borrow_from_next(&mut self, parent: NodeId, idx: usize, right: NodeId) {
+        let child = self.nodes[parent].children[idx];
+        let sibling = &mut self.nodes[right];
+        let key = sibling.keys.remove(0);
+        if sibling.is_leaf {
+            let value = sibling.values.remove(0);
+            // the sibling starts with its next key now
+            let first = sibling.keys[0].clone();
+            let node = &mut self.nodes[child];
+            node.keys.push(key);
+            node.values.push(value);
+            self.nodes[parent].keys[idx] = first;
         } else {
-            self.merge(idx);
-            self.children[idx].delete(key);
+            let moved = sibling.children.remove(0);
+            let separator = mem::replace(&mut self.nodes[parent].keys[idx], key);
+            let node = &mut self.nodes[child];
+            node.keys.push(separator);
+            node.children.push(moved);
         }
     }
 
-    fn get_predecessor_key(&self, idx: usize) -> K {
-        let mut current = &self.children[idx];
-        while !current.is_leaf {
-            current = &current.children[current.children.len() - 1];
+    // children[idx + 1] of `parent` goes into children[idx]
+    fn merge(&mut self, parent: NodeId, idx: usize) {
+        let separator = self.nodes[parent].keys.remove(idx);
+        let right = self.nodes[parent].children.remove(idx + 1);
+        let left = self.nodes[parent].children[idx];
+        let right_node = mem::replace(&mut self.nodes[right], Node::new(true));
+
+        let node = &mut self.nodes[left];
+        if node.is_leaf {
+            // the separator is only a copy of the right leaf's first key
+            node.keys.extend(right_node.keys);
+            node.values.extend(right_node.values);
+            node.next = right_node.next;
+            if let Some(next) = right_node.next {
+                self.nodes[next].prev = Some(left);
+            }
+        } else {
+            node.keys.push(separator);
+            node.keys.extend(right_node.keys);
+            node.children.extend(right_node.children);
         }
-        current.keys[current.keys.len() - 1].clone()
+        self.release(right);
     }
 
-    fn get_predecessor_value(&self, idx: usize) -> V {
-        let mut current = &self.children[idx];
-        while !current.is_leaf {
-            current = &current.children[current.children.len() - 1];
+    // a key that is already there gets the new value
+    pub fn insert(&mut self, key: K, value: V) {
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                let mut leaf = Node::new(true);
+                leaf.keys.push(key);
+                leaf.values.push(value);
+                self.root = Some(self.alloc(leaf));
+                return;
+            }
+        };
+        if let Some((separator, right)) = self.insert_into(root, key, value) {
+            let mut new_root = Node::new(false);
+            new_root.keys.push(separator);
+            new_root.children = vec![root, right];
+            self.root = Some(self.alloc(new_root));
         }
-        current.values[current.values.len() - 1].clone()
     }
 
-    fn get_successor_key(&self, idx: usize) -> K {
-        let mut current = &self.children[idx + 1];
-        while !current.is_leaf {
-            current = &current.children[0];
+    /*
+     *   Inserts below `id` and splits the node when it got too big. Gives back
+     *   the key the new right half starts at and where it is , for the parent.
+     */
+    fn insert_into(&mut self, id: NodeId, key: K, value: V) -> Option<(K, NodeId)> {
+        if self.nodes[id].is_leaf {
+            let leaf = &mut self.nodes[id];
+            match leaf.keys.binary_search(&key) {
+                Ok(idx) => {
+                    leaf.values[idx] = value;
+                    return None;
+                }
+                Err(idx) => {
+                    leaf.keys.insert(idx, key);
+                    leaf.values.insert(idx, value);
+                }
+            }
+            if leaf.keys.len() <= self.max_keys() {
+                return None;
+            }
+            return Some(self.split_leaf(id));
         }
-        current.keys[0].clone()
+
+        let idx = self.nodes[id].child_for(&key);
+        let child = self.nodes[id].children[idx];
+        let (separator, right) = self.insert_into(child, key, value)?;
+        let node = &mut self.nodes[id];
+        node.keys.insert(idx, separator);
+        node.children.insert(idx + 1, right);
+        if node.keys.len() <= self.max_keys() {
+            return None;
+        }
+        Some(self.split_inner(id))
     }
 
-    fn get_successor_value(&self, idx: usize) -> V {
-        let mut current = &self.children[idx + 1];
-        while !current.is_leaf {
-            current = &current.children[0];
+    // the upper half goes into a new leaf linked in right after this one
+    fn split_leaf(&mut self, id: NodeId) -> (K, NodeId) {
+        let leaf = &mut self.nodes[id];
+        let mid = leaf.keys.len() / 2;
+        let mut right = Node::new(true);
+        right.keys = leaf.keys.split_off(mid);
+        right.values = leaf.values.split_off(mid);
+        right.prev = Some(id);
+        right.next = leaf.next;
+        let separator = right.keys[0].clone();
+
+        let right_id = self.alloc(right);
+        if let Some(next) = self.nodes[right_id].next {
+            self.nodes[next].prev = Some(right_id);
         }
-        current.values[0].clone()
+        self.nodes[id].next = Some(right_id);
+        (separator, right_id)
     }
 
-    fn delete(&mut self, key: &K) {
-        let mut idx = self.find_key(key);
+    // the middle key moves up to the parent , it stays in neither half
+    fn split_inner(&mut self, id: NodeId) -> (K, NodeId) {
+        let node = &mut self.nodes[id];
+        let mid = node.keys.len() / 2;
+        let mut right = Node::new(false);
+        right.keys = node.keys.split_off(mid + 1);
+        right.children = node.children.split_off(mid + 1);
+        let separator = node.keys.pop().expect("Node has no middle key");
+        (separator, self.alloc(right))
+    }
 
-        if idx < self.keys.len() && self.keys[idx] == *key {
-            if self.is_leaf {
-                self.keys.remove(idx);
-                self.values.remove(idx);
-            } else {
-                self.delete_internal_node(key, idx);
-            }
-        } else {
-            if self.is_leaf {
-                return;
-            }
+    pub fn values_in_order(&self) -> Vec<V> {
+        self.iter().map(|(_, value)| value.clone()).collect()
+    }
 
-            let flag = idx == self.keys.len();
+    // same walk as values_in_order , with the key next to every value
+    pub fn entries_in_order(&self) -> Vec<(K, V)> {
+        self.iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
 
-            if self.children[idx].keys.len() < self.order {
-                self.fill(idx);
-            }
+    /*
+     *   The entries whose key is inside a range , in key order. `cmp` tells
+     *   where a key is compared to the range , Less before it , Equal inside
+     *   and Greater after it (like binary_search_by). Only the leaves that
+     *   have some of the range are read.
+     */
+    pub fn entries_in_range(&self, cmp: impl Fn(&K) -> Ordering) -> Vec<(K, V)> {
+        let mut cursor = self.cursor();
+        cursor.seek_by(|key| cmp(key));
+        cursor
+            .take_while(|(key, _)| cmp(key) == Ordering::Equal)
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
 
-            if flag && idx > self.keys.len() {
-                idx -= 1;
-            }
+    // a cursor before the first entry
+    pub fn cursor(&self) -> Cursor<'_, K, V> {
+        let mut cursor = Cursor {
+            tree: self,
+            leaf: None,
+            index: 0,
+        };
+        cursor.move_to_start();
+        cursor
+    }
 
-            self.children[idx].delete(key);
+    /*
+     *   The entries with a key in `range` in key order , from both ends:
+     *
+     *   for (key, value) in tree.range(10..=20) { ... }
+     *   for (key, value) in tree.range(..).rev() { ... }
+     */
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        let mut front = self.cursor();
+        match range.start_bound() {
+            Bound::Included(start) => front.seek(start),
+            Bound::Excluded(start) => {
+                front.seek(start);
+                if front.peek_next().is_some_and(|(key, _)| key == start) {
+                    front.next();
+                }
+            }
+            Bound::Unbounded => {}
+        }
+        let mut back = self.cursor();
+        match range.end_bound() {
+            Bound::Included(end) => {
+                back.seek(end);
+                if back.peek_next().is_some_and(|(key, _)| key == end) {
+                    back.next();
+                }
+            }
+            Bound::Excluded(end) => back.seek(end),
+            Bound::Unbounded => back.move_to_end(),
+        }
+        Range {
+            front,
+            back,
+            done: false,
         }
     }
 
-    fn split_child(&mut self, i: usize) {
-        let t = self.order;
-        let child = self.children[i].as_mut();
+    pub fn iter(&self) -> Range<'_, K, V> {
+        self.range(..)
+    }
 
-        let mut new_child = Node::new(child.is_leaf, child.order);
+    // the largest key is at the end of the last leaf
+    pub fn last_key(&self) -> Option<&K> {
+        self.nodes[self.last_leaf()?].keys.last()
+    }
 
-        new_child.keys = child.keys.split_off(t);
-        new_child.values = child.values.split_off(t);
+    fn first_leaf(&self) -> Option<NodeId> {
+        let mut id = self.root?;
+        while !self.nodes[id].is_leaf {
+            id = self.nodes[id].children[0];
+        }
+        Some(id)
+    }
 
-        if !child.is_leaf {
-            new_child.children = child.children.split_off(t);
+    fn last_leaf(&self) -> Option<NodeId> {
+        let mut id = self.root?;
+        while !self.nodes[id].is_leaf {
+            id = *self.nodes[id].children.last()?;
         }
+        Some(id)
+    }
+}
 
-        self.keys
-            .insert(i, child.keys.pop().expect("Child has no keys to move"));
-        self.values
-            .insert(i, child.values.pop().expect("Child has no values to move"));
+/*
+*   A position in a BTree that borrows it. The cursor sits between two
+*   entries (or before the first , or after the last): next() gives back the
+*   entry after it and moves past it , prev() the entry before it and moves
+*   back past it.
+*
+*   It is a leaf and a gap in it , gap i is just before keys[i]. Past either
+*   end of the leaf it follows the links to the leaf next to it.
+*/
+#[derive(Clone, Debug)]
+pub struct Cursor<'a, K, V>
+where
+    K: Ord + Clone + Default,
+    V: Clone + Default,
+{
+    tree: &'a BTree<K, V>,
+    leaf: Option<NodeId>,
+    index: usize,
+}
 
-        self.children.insert(i + 1, Box::new(new_child));
+impl<'a, K, V> Cursor<'a, K, V>
+where
+    K: Ord + Clone + Default,
+    V: Clone + Default,
+{
+    // before the first entry
+    pub fn move_to_start(&mut self) {
+        self.leaf = self.tree.first_leaf();
+        self.index = 0;
     }
 
-    fn search(&self, key: &K) -> Option<&V> {
-        let idx = self.find_key(key);
+    // after the last entry
+    pub fn move_to_end(&mut self) {
+        self.leaf = self.tree.last_leaf();
+        self.index = self.leaf.map_or(0, |leaf| self.tree.nodes[leaf].keys.len());
+    }
 
-        if idx < self.keys.len() && self.keys[idx] == *key {
-            return Some(&self.values[idx]);
-        }
+    // before the first entry whose key is not less than `key`
+    pub fn seek(&mut self, key: &K) {
+        self.seek_by(|other| other.cmp(key));
+    }
 
-        if self.is_leaf {
-            return None;
+    /*
+     *   Before the first entry for which `cmp` is not Less , like seek() with
+     *   a key that only exists as a comparison (see BTree::entries_in_range).
+     */
+    pub fn seek_by(&mut self, cmp: impl Fn(&K) -> Ordering) {
+        let nodes = &self.tree.nodes;
+        let mut id = match self.tree.root {
+            Some(root) => root,
+            None => return,
+        };
+        while !nodes[id].is_leaf {
+            let node = &nodes[id];
+            id = node.children[node.keys.partition_point(|key| cmp(key) == Ordering::Less)];
         }
+        self.leaf = Some(id);
+        self.index = nodes[id]
+            .keys
+            .partition_point(|key| cmp(key) == Ordering::Less);
+    }
 
-        self.children[idx].search(key)
+    // the entry next() would give back , without moving
+    pub fn peek_next(&self) -> Option<(&'a K, &'a V)> {
+        self.clone().next()
     }
 
-    fn insert_non_full(&mut self, key: K, value: V) {
-        let mut i = self.keys.len();
+    pub fn peek_prev(&self) -> Option<(&'a K, &'a V)> {
+        self.clone().prev()
+    }
 
-        if self.is_leaf {
-            while i > 0 && key < self.keys[i - 1] {
-                i -= 1;
-            }
+    pub fn prev(&mut self) -> Option<(&'a K, &'a V)> {
+        let nodes = &self.tree.nodes;
+        let (mut leaf, mut index) = (self.leaf?, self.index);
+        // the start of a leaf is the same place as the end of the one before
+        while index == 0 {
+            leaf = nodes[leaf].prev?;
+            index = nodes[leaf].keys.len();
+        }
+        self.leaf = Some(leaf);
+        self.index = index - 1;
+        let node = &nodes[leaf];
+        Some((&node.keys[index - 1], &node.values[index - 1]))
+    }
+}
 
-            self.keys.insert(i, key);
-            self.values.insert(i, value);
-        } else {
-            while i > 0 && key < self.keys[i - 1] {
-                i -= 1;
-            }
+impl<'a, K, V> Iterator for Cursor<'a, K, V>
+where
+    K: Ord + Clone + Default,
+    V: Clone + Default,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let nodes = &self.tree.nodes;
+        let (mut leaf, mut index) = (self.leaf?, self.index);
+        while index == nodes[leaf].keys.len() {
+            leaf = nodes[leaf].next?;
+            index = 0;
+        }
+        self.leaf = Some(leaf);
+        self.index = index + 1;
+        let node = &nodes[leaf];
+        Some((&node.keys[index], &node.values[index]))
+    }
+}
 
-            if self.children[i].is_full() {
-                self.split_child(i);
+// see BTree::range , the two ends are cursors that stop where they meet
+#[derive(Clone, Debug)]
+pub struct Range<'a, K, V>
+where
+    K: Ord + Clone + Default,
+    V: Clone + Default,
+{
+    front: Cursor<'a, K, V>,
+    back: Cursor<'a, K, V>,
+    done: bool,
+}
 
-                if key > self.keys[i] {
-                    i += 1;
-                }
-            }
+impl<'a, K, V> Iterator for Range<'a, K, V>
+where
+    K: Ord + Clone + Default,
+    V: Clone + Default,
+{
+    type Item = (&'a K, &'a V);
 
-            self.children[i].insert_non_full(key, value);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let back = self.back.peek_next().map(|(key, _)| key);
+        match self.front.next() {
+            Some((key, value)) if back.is_none_or(|back| key < back) => Some((key, value)),
+            _ => {
+                self.done = true;
+                None
+            }
         }
     }
 }
 
-#[derive(Debug)]
-pub struct BTree<K, V>
+impl<'a, K, V> DoubleEndedIterator for Range<'a, K, V>
 where
     K: Ord + Clone + Default,
     V: Clone + Default,
 {
-    pub root: Option<Box<Node<K, V>>>,
-    pub order: usize,
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let front = self.front.peek_prev().map(|(key, _)| key);
+        match self.back.prev() {
+            Some((key, value)) if front.is_none_or(|front| key > front) => Some((key, value)),
+            _ => {
+                self.done = true;
+                None
+            }
+        }
+    }
 }
 
-impl<K, V> BTree<K, V>
-where
-    K: Ord + Clone + Default + std::fmt::Debug,
-    V: Clone + Default + std::fmt::Debug,
-{
-    pub fn new(order: usize) -> Self {
-        assert!(order >= 2, "BTree order must be at least 2");
-        BTree { root: None, order }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    // xorshift , the same numbers on every run
+    fn numbers(seed: u64) -> impl FnMut(u64) -> u64 {
+        let mut state = seed;
+        move |n| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        }
     }
 
-    pub fn search(&self, key: &K) -> Option<&V> {
-        self.root.as_ref().and_then(|r| r.search(key))
+    fn assert_same(tree: &BTree<i64, i64>, map: &BTreeMap<i64, i64>) {
+        let expected: Vec<_> = map.iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(tree.entries_in_order(), expected);
+        let backwards: Vec<_> = tree.iter().rev().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(backwards, expected.into_iter().rev().collect::<Vec<_>>());
+        assert_eq!(tree.last_key(), map.keys().next_back());
     }
 
-    pub fn delete(&mut self, key: &K) {
-        println!("got key of {:?}", key);
-        if let Some(mut root_node) = self.root.take() {
-            root_node.delete(key);
-
-            if root_node.keys.is_empty() && !root_node.is_leaf {
-                self.root = Some(root_node.children.remove(0));
-            } else if root_node.keys.is_empty() && root_node.is_leaf {
-                self.root = None;
-            } else {
-                self.root = Some(root_node);
+    #[test]
+    fn insert_replaces_a_key_that_is_there() {
+        let mut tree = BTree::new(2);
+        for round in 0..3 {
+            for key in 0..100 {
+                tree.insert(key, key * 10 + round);
             }
         }
+        assert_eq!(tree.values_in_order().len(), 100);
+        assert_eq!(tree.search(&7), Some(&72));
     }
 
-    pub fn insert(&mut self, key: K, value: V) {
-        if let Some(ref mut root) = self.root {
-            if root.is_full() {
-                let mut new_root = Node::new(false, self.order);
-                new_root.children.push(mem::take(root));
-                new_root.split_child(0);
-
-                let i = if new_root.keys[0] < key { 1 } else { 0 };
-                new_root.children[i].insert_non_full(key, value);
-                self.root = Some(Box::new(new_root));
-            } else {
-                root.insert_non_full(key, value);
+    #[test]
+    fn matches_a_btreemap_through_inserts_and_deletes() {
+        for order in [2, 3, 5] {
+            let mut next = numbers(order as u64 * 7919);
+            let mut tree = BTree::new(order);
+            let mut map = BTreeMap::new();
+            for step in 0..5000 {
+                let key = next(600) as i64;
+                if next(3) == 0 {
+                    tree.delete(&key);
+                    map.remove(&key);
+                } else {
+                    tree.insert(key, step);
+                    map.insert(key, step);
+                }
+                if step % 250 == 0 {
+                    assert_same(&tree, &map);
+                }
             }
-        } else {
-            let mut root = Node::new(true, self.order);
-            root.keys.push(key);
-            root.values.push(value);
-            self.root = Some(Box::new(root));
+            for key in 0..600 {
+                assert_eq!(tree.search(&key), map.get(&key));
+            }
+            let keys: Vec<_> = map.keys().copied().collect();
+            for key in keys {
+                tree.delete(&key);
+                map.remove(&key);
+            }
+            assert_same(&tree, &map);
+            assert!(tree.cursor().next().is_none());
         }
     }
 
-    pub fn values_in_order(&self) -> Vec<V> {
-        let mut result = Vec::new();
-        if let Some(ref root) = self.root {
-            Self::collect_values_in_order(root, &mut result);
+    #[test]
+    fn leaves_are_linked_both_ways() {
+        let mut tree = BTree::new(2);
+        for key in (0..500).rev() {
+            tree.insert(key, key);
+        }
+        let mut cursor = tree.cursor();
+        assert_eq!(cursor.peek_prev(), None);
+        assert_eq!(cursor.by_ref().count(), 500);
+        let mut count = 0;
+        while let Some((key, _)) = cursor.prev() {
+            assert_eq!(*key, 499 - count);
+            count += 1;
         }
-        result
+        assert_eq!(count, 500);
     }
 
-    fn collect_values_in_order(node: &Node<K, V>, result: &mut Vec<V>) {
-        if node.is_leaf {
-            result.extend(node.values.iter().cloned());
-        } else {
-            for i in 0..node.keys.len() {
-                Self::collect_values_in_order(&node.children[i], result);
-                result.push(node.values[i].clone());
-            }
-            if !node.children.is_empty() {
-                Self::collect_values_in_order(&node.children[node.children.len() - 1], result);
+    #[test]
+    fn bulk_load_gives_a_tree_that_keeps_working() {
+        for order in [2, 3, 4] {
+            for count in (0..60).chain([200, 1001]) {
+                let entries = (0..count).map(|key| (key * 2, key));
+                let mut tree = BTree::from_sorted_iter(order, entries.clone());
+                let mut map: BTreeMap<i64, i64> = entries.collect();
+                assert_same(&tree, &map);
+                for key in -1..count * 2 + 1 {
+                    assert_eq!(tree.search(&key), map.get(&key));
+                }
+                let mut next = numbers(count as u64 + 1);
+                for step in 0..count * 2 {
+                    let key = next(count as u64 * 2 + 5) as i64;
+                    if step % 2 == 0 {
+                        tree.insert(key, step);
+                        map.insert(key, step);
+                    } else {
+                        tree.delete(&key);
+                        map.remove(&key);
+                    }
+                }
+                assert_same(&tree, &map);
             }
         }
     }
+
+    #[test]
+    fn bulk_load_keeps_the_last_value_of_a_key() {
+        let tree = BTree::from_sorted_iter(2, vec![(1, 1), (1, 2), (2, 3), (2, 4), (3, 5)]);
+        assert_eq!(tree.entries_in_order(), vec![(1, 2), (2, 4), (3, 5)]);
+    }
+
+    #[test]
+    fn ranges_match_a_btreemap() {
+        let mut tree = BTree::new(3);
+        let mut map = BTreeMap::new();
+        for key in (0..400).step_by(3) {
+            tree.insert(key, key);
+            map.insert(key, key);
+        }
+        for (low, high) in [(-5, 10), (30, 30), (31, 32), (100, 250), (390, 500)] {
+            let expected: Vec<_> = map.range(low..=high).map(|(k, _)| *k).collect();
+            let found: Vec<_> = tree.range(low..=high).map(|(k, _)| *k).collect();
+            assert_eq!(found, expected);
+            let expected: Vec<_> = map.range(low..high).rev().map(|(k, _)| *k).collect();
+            let found: Vec<_> = tree.range(low..high).rev().map(|(k, _)| *k).collect();
+            assert_eq!(found, expected);
+            let in_range = tree.entries_in_range(|key| {
+                if *key < low {
+                    Ordering::Less
+                } else if *key > high {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                }
+            });
+            let expected: Vec<_> = map.range(low..=high).map(|(k, v)| (*k, *v)).collect();
+            assert_eq!(in_range, expected);
+        }
+    }
 }
@@ -0,0 +1,185 @@
+use crate::error::Error;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/*
+*   Tiny binary format used to put things into pages.
+*
+*   integers are little endian, strings and vectors are prefixed by their
+*   length as a u32, enums are prefixed by a u8 tag.
+*/
+
+pub trait Codec: Sized {
+    fn encode(&self, buf: &mut Vec<u8>);
+    fn decode(reader: &mut Reader) -> Result<Self, Error>;
+}
+
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.pos + len > self.buf.len() {
+            return Err(Error::Io(format!(
+                "Corrupted data , wanted {} bytes at offset {} but only {} are left",
+                len,
+                self.pos,
+                self.buf.len() - self.pos
+            )));
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut result = [0u8; N];
+        result.copy_from_slice(self.read_bytes(N)?);
+        Ok(result)
+    }
+}
+
+pub fn encode_to_vec<T: Codec>(value: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    value.encode(&mut buf);
+    buf
+}
+
+pub fn decode_from_slice<T: Codec>(buf: &[u8]) -> Result<T, Error> {
+    let mut reader = Reader::new(buf);
+    T::decode(&mut reader)
+}
+
+impl Codec for u8 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self);
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(reader.read_array::<1>()?[0])
+    }
+}
+
+impl Codec for u32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(u32::from_le_bytes(reader.read_array()?))
+    }
+}
+
+impl Codec for u64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(u64::from_le_bytes(reader.read_array()?))
+    }
+}
+
+impl Codec for i64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(i64::from_le_bytes(reader.read_array()?))
+    }
+}
+
+impl Codec for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(u8::decode(reader)? != 0)
+    }
+}
+
+// a bool tag , then the value when there is one
+impl<T: Codec> Codec for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.is_some().encode(buf);
+        if let Some(value) = self {
+            value.encode(buf);
+        }
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        match bool::decode(reader)? {
+            true => Ok(Some(T::decode(reader)?)),
+            false => Ok(None),
+        }
+    }
+}
+
+impl Codec for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u32).encode(buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        let len = u32::decode(reader)? as usize;
+        String::from_utf8(reader.read_bytes(len)?.to_vec()).map_err(|e| Error::Io(e.to_string()))
+    }
+}
+
+impl<T: Codec> Codec for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u32).encode(buf);
+        for item in self {
+            item.encode(buf);
+        }
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        let len = u32::decode(reader)? as usize;
+        let mut result = Vec::with_capacity(len);
+        for _ in 0..len {
+            result.push(T::decode(reader)?);
+        }
+        Ok(result)
+    }
+}
+
+impl<A: Codec, B: Codec> Codec for (A, B) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok((A::decode(reader)?, B::decode(reader)?))
+    }
+}
+
+impl<K: Codec + Eq + Hash, V: Codec> Codec for HashMap<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u32).encode(buf);
+        for (key, value) in self {
+            key.encode(buf);
+            value.encode(buf);
+        }
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        let len = u32::decode(reader)? as usize;
+        let mut result = HashMap::with_capacity(len);
+        for _ in 0..len {
+            let key = K::decode(reader)?;
+            result.insert(key, V::decode(reader)?);
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,519 @@
+use crate::core::db::DataType;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*
+*   Dates and times , everything is UTC and counted from 1970-01-01:
+*
+*   DATE        days
+*   TIME        milliseconds since midnight
+*   TIMESTAMP   milliseconds
+*
+*   Text is ISO-8601 , `YYYY-MM-DD` , `HH:MM[:SS[.SSS]]` or both with a space
+*   or a 'T' between them , optionally followed by `Z` or `+HH:MM`. The date
+*   functions work like sqlite's , bad input gives NULL instead of an error.
+*/
+
+pub const MS_PER_DAY: i64 = 86_400_000;
+// the julian day of 1970-01-01 00:00
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2440587.5;
+// what the date functions work with , like sqlite julian day 0 up to the end of 9999-12-31
+const MIN_MS: i64 = -210_866_760_000_000;
+const MAX_MS: i64 = 253_402_300_799_999;
+
+// what a piece of text held , a time without a date is on 2000-01-01 like in sqlite
+struct Moment {
+    ms: i64,
+    has_date: bool,
+    has_time: bool,
+}
+
+pub fn parse_date(s: &str) -> Option<i64> {
+    match parse(s)? {
+        Moment {
+            ms,
+            has_date: true,
+            has_time: false,
+        } => Some(ms.div_euclid(MS_PER_DAY)),
+        _ => None,
+    }
+}
+
+pub fn parse_time(s: &str) -> Option<i64> {
+    match parse(s)? {
+        Moment {
+            ms,
+            has_date: false,
+            has_time: true,
+        } => Some(ms.rem_euclid(MS_PER_DAY)),
+        _ => None,
+    }
+}
+
+// a date alone is midnight
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    match parse(s)? {
+        Moment {
+            ms, has_date: true, ..
+        } => Some(ms),
+        _ => None,
+    }
+}
+
+/*
+*   Text read as the same kind of value as `like` , so '2024-01-05' compares
+*   with a DATE as a date and not as text.
+*/
+pub fn text_as(s: &str, like: &DataType) -> Option<DataType> {
+    match like {
+        DataType::Date(_) | DataType::Timestamp(_) => parse_timestamp(s).map(DataType::Timestamp),
+        DataType::Time(_) => parse_time(s).map(DataType::Time),
+        _ => None,
+    }
+}
+
+pub fn format_date(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// the milliseconds only when there are some
+pub fn format_time(ms: i64) -> String {
+    let ms = ms.rem_euclid(MS_PER_DAY);
+    let time = format!(
+        "{:02}:{:02}:{:02}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60
+    );
+    match ms % 1000 {
+        0 => time,
+        millis => format!("{}.{:03}", time, millis),
+    }
+}
+
+pub fn format_timestamp(ms: i64) -> String {
+    format!(
+        "{} {}",
+        format_date(ms.div_euclid(MS_PER_DAY)),
+        format_time(ms)
+    )
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/*
+*   date(value , modifier ...)       DATE
+*   time(value , modifier ...)       TIME
+*   datetime(value , modifier ...)   TIMESTAMP
+*   julianday(value , modifier ...)  REAL
+*   strftime(format , value , modifier ...)
+*
+*   No value means now. A number is a julian day , or seconds since 1970 when
+*   the first modifier is 'unixepoch'. The other modifiers are '[+-]N days'
+*   (hours , minutes , seconds , months , years) , 'start of day' (month ,
+*   year) , 'weekday N' , and 'utc' / 'localtime' which do nothing since
+*   everything is UTC.
+*/
+pub fn call(name: &str, args: &[DataType]) -> DataType {
+    let (format, args) = match name {
+        "strftime" => match &args[0] {
+            DataType::Null => return DataType::Null,
+            format => (format.to_string(), &args[1..]),
+        },
+        _ => (String::new(), args),
+    };
+    let ms = match moment_of(args) {
+        Some(ms) => ms,
+        None => return DataType::Null,
+    };
+    match name {
+        // whole seconds like sqlite
+        "date" => DataType::Date(ms.div_euclid(MS_PER_DAY)),
+        "time" => DataType::Time(ms.div_euclid(1000).rem_euclid(MS_PER_DAY / 1000) * 1000),
+        "datetime" => DataType::Timestamp(ms.div_euclid(1000) * 1000),
+        "julianday" => DataType::Real(julian_day(ms)),
+        _ => strftime(&format, ms).map_or(DataType::Null, DataType::Text),
+    }
+}
+
+fn moment_of(args: &[DataType]) -> Option<i64> {
+    let mut modifiers = args.iter().skip(1).peekable();
+    let mut ms = match args.first() {
+        None => now(),
+        Some(DataType::Text(s)) if s.trim().eq_ignore_ascii_case("now") => now(),
+        Some(DataType::Text(s)) => parse(s)?.ms,
+        Some(value @ (DataType::Int(_) | DataType::Real(_))) => {
+            let number = match value {
+                DataType::Int(n) => *n as f64,
+                DataType::Real(n) => *n,
+                _ => unreachable!(),
+            };
+            let unixepoch = matches!(modifiers.peek(),
+                Some(DataType::Text(m)) if m.trim().eq_ignore_ascii_case("unixepoch"));
+            if unixepoch {
+                modifiers.next();
+                ms_of(number * 1000.0)?
+            } else {
+                ms_of((number - UNIX_EPOCH_JULIAN_DAY) * MS_PER_DAY as f64)?
+            }
+        }
+        Some(DataType::Date(days)) => days.checked_mul(MS_PER_DAY)?,
+        Some(DataType::Time(ms)) => days_from_civil(2000, 1, 1) * MS_PER_DAY + ms,
+        Some(DataType::Timestamp(ms)) => *ms,
+        Some(_) => return None,
+    };
+    in_range(ms)?;
+    for modifier in modifiers {
+        match modifier {
+            DataType::Text(modifier) => ms = in_range(apply_modifier(ms, modifier)?)?,
+            _ => return None,
+        }
+    }
+    Some(ms)
+}
+
+fn in_range(ms: i64) -> Option<i64> {
+    (MIN_MS..=MAX_MS).contains(&ms).then_some(ms)
+}
+
+// a number of milliseconds that came out of float math , None for NaN , inf and the like
+fn ms_of(ms: f64) -> Option<i64> {
+    let ms = ms.round();
+    (ms >= MIN_MS as f64 && ms <= MAX_MS as f64).then_some(ms as i64)
+}
+
+fn apply_modifier(ms: i64, modifier: &str) -> Option<i64> {
+    let modifier = modifier.trim().to_lowercase();
+    let days = ms.div_euclid(MS_PER_DAY);
+    let (year, month, _) = civil_from_days(days);
+    match modifier.as_str() {
+        "start of day" => return Some(days * MS_PER_DAY),
+        "start of month" => return Some(days_from_civil(year, month, 1) * MS_PER_DAY),
+        "start of year" => return Some(days_from_civil(year, 1, 1) * MS_PER_DAY),
+        "utc" | "localtime" => return Some(ms),
+        _ => {}
+    }
+
+    // the next day that is weekday N , 0 is sunday , today if it already is
+    if let Some(weekday) = modifier.strip_prefix("weekday ") {
+        let weekday: i64 = weekday.trim().parse().ok()?;
+        if !(0..=6).contains(&weekday) {
+            return None;
+        }
+        return ms.checked_add((weekday - weekday_of(days)).rem_euclid(7) * MS_PER_DAY);
+    }
+
+    let (amount, unit) = modifier.split_once(' ')?;
+    let amount: f64 = amount.parse().ok()?;
+    if !amount.is_finite() {
+        return None;
+    }
+    let unit_ms = match unit.trim().trim_end_matches('s') {
+        "day" => MS_PER_DAY,
+        "hour" => 3_600_000,
+        "minute" => 60_000,
+        "second" => 1000,
+        "month" => return add_months(ms, amount as i64),
+        "year" => return add_months(ms, (amount as i64).checked_mul(12)?),
+        _ => return None,
+    };
+    // anything this far away is out of range anyway , `as` would saturate
+    let offset = amount * unit_ms as f64;
+    if offset.abs() > (MAX_MS - MIN_MS) as f64 {
+        return None;
+    }
+    ms.checked_add(offset.round() as i64)
+}
+
+// 2024-01-31 + 1 month is 2024-02-31 , which is 2024-03-02 , same as sqlite
+fn add_months(ms: i64, months: i64) -> Option<i64> {
+    let days = ms.div_euclid(MS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    let total = (year * 12 + month - 1).checked_add(months)?;
+    let year = total.div_euclid(12);
+    if !(-4713..=9999).contains(&year) {
+        return None;
+    }
+    let days = days_from_civil(year, total.rem_euclid(12) + 1, 1) + day - 1;
+    days.checked_mul(MS_PER_DAY)?
+        .checked_add(ms.rem_euclid(MS_PER_DAY))
+}
+
+fn julian_day(ms: i64) -> f64 {
+    ms as f64 / MS_PER_DAY as f64 + UNIX_EPOCH_JULIAN_DAY
+}
+
+// 0 is sunday , 1970-01-01 was a thursday
+fn weekday_of(days: i64) -> i64 {
+    (days + 4).rem_euclid(7)
+}
+
+/*
+*   %d day  %f seconds with milliseconds  %F %Y-%m-%d  %H hour  %j day of year
+*   %J julian day  %m month  %M minute  %s seconds since 1970  %S seconds
+*   %T %H:%M:%S  %w weekday (0 is sunday)  %W week of year  %Y year  %% %
+*/
+fn strftime(format: &str, ms: i64) -> Option<String> {
+    let days = ms.div_euclid(MS_PER_DAY);
+    let time = ms.rem_euclid(MS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    let (hour, minute, second) = (time / 3_600_000, time / 60_000 % 60, time / 1000 % 60);
+    let day_of_year = days - days_from_civil(year, 1, 1);
+
+    let mut result = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        let part = match chars.next()? {
+            'd' => format!("{:02}", day),
+            'f' => format!("{:02}.{:03}", second, time % 1000),
+            'F' => format_date(days),
+            'H' => format!("{:02}", hour),
+            'j' => format!("{:03}", day_of_year + 1),
+            'J' => DataType::Real(julian_day(ms)).to_string(),
+            'm' => format!("{:02}", month),
+            'M' => format!("{:02}", minute),
+            's' => ms.div_euclid(1000).to_string(),
+            'S' => format!("{:02}", second),
+            'T' => format!("{:02}:{:02}:{:02}", hour, minute, second),
+            'w' => weekday_of(days).to_string(),
+            // weeks start on monday , days before the first monday are week 00
+            'W' => format!("{:02}", (day_of_year + 7 - (weekday_of(days) + 6) % 7) / 7),
+            'Y' => format!("{:04}", year),
+            '%' => "%".to_string(),
+            _ => return None,
+        };
+        result.push_str(&part);
+    }
+    Some(result)
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.peek() == Some(byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    // exactly `count` digits
+    fn number(&mut self, count: usize) -> Option<i64> {
+        let digits = self.bytes.get(self.pos..self.pos + count)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        self.pos += count;
+        Some(
+            digits
+                .iter()
+                .fold(0, |n, digit| n * 10 + (digit - b'0') as i64),
+        )
+    }
+}
+
+fn parse(s: &str) -> Option<Moment> {
+    let mut cursor = Cursor {
+        bytes: s.trim().as_bytes(),
+        pos: 0,
+    };
+
+    let is_date = cursor.bytes.get(4) == Some(&b'-');
+    let days = if is_date {
+        let year = cursor.number(4)?;
+        cursor.eat(b'-');
+        let month = cursor.number(2)?;
+        if !cursor.eat(b'-') {
+            return None;
+        }
+        let day = cursor.number(2)?;
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+        days_from_civil(year, month, day)
+    } else {
+        days_from_civil(2000, 1, 1)
+    };
+
+    let has_time = if is_date {
+        (cursor.eat(b' ') || cursor.eat(b'T')) && cursor.peek().is_some()
+    } else {
+        true
+    };
+    let mut ms = days * MS_PER_DAY;
+    if has_time {
+        ms += parse_time_of_day(&mut cursor)?;
+        ms -= parse_offset(&mut cursor)?;
+    }
+
+    if cursor.pos != cursor.bytes.len() {
+        return None;
+    }
+    Some(Moment {
+        ms,
+        has_date: is_date,
+        has_time,
+    })
+}
+
+// HH:MM[:SS[.SSS]] , digits past the milliseconds are dropped
+fn parse_time_of_day(cursor: &mut Cursor) -> Option<i64> {
+    let hour = cursor.number(2)?;
+    if !cursor.eat(b':') {
+        return None;
+    }
+    let minute = cursor.number(2)?;
+    let mut second = 0;
+    let mut millis = 0;
+    if cursor.eat(b':') {
+        second = cursor.number(2)?;
+        if cursor.eat(b'.') {
+            let mut scale = 100;
+            while let Some(digit @ b'0'..=b'9') = cursor.peek() {
+                millis += (digit - b'0') as i64 * scale;
+                scale /= 10;
+                cursor.pos += 1;
+            }
+        }
+    }
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    Some(((hour * 60 + minute) * 60 + second) * 1000 + millis)
+}
+
+// Z or [+-]HH:MM , what to take away to get to UTC
+fn parse_offset(cursor: &mut Cursor) -> Option<i64> {
+    if cursor.eat(b'Z') || cursor.eat(b'z') {
+        return Some(0);
+    }
+    let sign = match cursor.peek() {
+        Some(b'+') => 1,
+        Some(b'-') => -1,
+        _ => return Some(0),
+    };
+    cursor.pos += 1;
+    let hours = cursor.number(2)?;
+    if !cursor.eat(b':') {
+        return None;
+    }
+    let minutes = cursor.number(2)?;
+    Some(sign * (hours * 60 + minutes) * 60_000)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+// days since 1970-01-01 , works for any year (proleptic gregorian calendar)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> DataType {
+        DataType::Text(s.to_string())
+    }
+
+    #[test]
+    fn modifiers_move_the_moment() {
+        let day = call("date", &[text("2024-01-31"), text("+1 month")]);
+        assert_eq!(day.to_string(), "2024-03-02");
+        let moment = call("datetime", &[text("2024-02-29 10:00"), text("-1 year")]);
+        assert_eq!(moment.to_string(), "2023-03-01 10:00:00");
+        let day = call("date", &[text("2024-01-03"), text("weekday 0")]);
+        assert_eq!(day.to_string(), "2024-01-07");
+        let moment = call("datetime", &[DataType::Int(86400), text("unixepoch")]);
+        assert_eq!(moment.to_string(), "1970-01-02 00:00:00");
+    }
+
+    #[test]
+    fn overflowing_modifiers_give_null() {
+        let cases: Vec<Vec<DataType>> = vec![
+            vec![DataType::Real(1e300), text("+1 day")],
+            vec![text("2024-01-01"), text("+1e300 days")],
+            vec![text("2024-01-01"), text("-1e300 seconds")],
+            vec![text("2024-01-01"), text("1e18 years")],
+            vec![text("2024-01-01"), text("-9e18 months")],
+            vec![text("2024-01-01"), text("inf days")],
+            vec![text("2024-01-01"), text("NaN hours")],
+            vec![DataType::Int(i64::MAX), text("unixepoch")],
+            vec![DataType::Real(f64::NAN)],
+            vec![DataType::Date(i64::MAX), text("+1 day")],
+            vec![DataType::Timestamp(i64::MIN), text("start of month")],
+        ];
+        for args in cases {
+            for name in ["date", "datetime", "julianday"] {
+                assert_eq!(call(name, &args), DataType::Null, "{}({:?})", name, args);
+            }
+        }
+    }
+
+    #[test]
+    fn moments_past_the_range_give_null() {
+        assert_eq!(
+            call("date", &[text("9999-12-31"), text("+1 day")]),
+            DataType::Null
+        );
+        assert_eq!(
+            call("date", &[text("9999-12-01"), text("+30 days")]).to_string(),
+            "9999-12-31".to_string()
+        );
+        let day = call("date", &[text("0000-01-01"), text("-4713 years")]);
+        assert_eq!(day, DataType::Null);
+        let first = call("julianday", &[DataType::Real(0.0)]);
+        assert_eq!(first, DataType::Real(0.0));
+    }
+}
@@ -1,38 +1,174 @@
-use crate::core::btree::BTree;
+use crate::core::btree::{self, BTree};
+use crate::core::codec::{decode_from_slice, Codec, Reader};
+use crate::core::datetime::{self, MS_PER_DAY};
+use crate::core::eval::{eval, eval_constant, is_constant, is_true};
+use crate::core::paged_btree::{PagedBTree, PagedCursor};
+use crate::core::pager::{PageNum, Pager};
+use crate::core::rows::Rows;
+use crate::core::statement::{literal, Statement};
+use crate::error::Error;
 use crate::parser::lexer::Lexer;
 use crate::parser::parser::{
-    CreateTableQuery, DeleteQuery, Expr, InsertQuery, Parser, Query, SelectQuery, WhereClause,
+    AlterTableAction, AlterTableQuery, BinaryOp, CreateIndexQuery, CreateTableQuery, DeleteQuery,
+    DropIndexQuery, DropTableQuery, Expr, InsertQuery, Parser, PragmaQuery, Query, SelectItem,
+    SelectQuery, TableConstraint, TransactionStmt, UpdateQuery,
 };
-use std::collections::{HashMap, HashSet};
-use std::i64;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
 use std::process::exit;
+use std::rc::Rc;
+
+// big enough that a node of small rows fills most of a page
+const PAGED_BTREE_ORDER: usize = 16;
+
+// (table name , (schema , (root page of the rows , root pages of the indexes)))
+type CatalogEntry = (String, (Schema, (PageNum, Vec<PageNum>)));
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub enum ColumnType {
     Int,
     String,
     Date,
+    Real,
+    Boolean,
+    Blob,
+    Time,
+    Timestamp,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Constraints {
     Unique,
     NotNull,
     Null,
     PrimaryKey,
-    ForeignKey,
+    // REFERENCES on a single column
+    ForeignKey(Reference),
+    // what an INSERT that leaves the column out puts in it
+    Default(Expr),
+    // CHECK on a single column
+    Check(Check),
+}
+
+// what happens to the rows pointing at a parent row that goes away or changes its key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReferentialAction {
+    // fails once the statement is done if a row still points at nothing
+    NoAction,
+    // fails before the parent row is touched
+    Restrict,
+    Cascade,
+    SetNull,
+}
+
+/*
+*   REFERENCES table (columns) [ON DELETE action] [ON UPDATE action]
+*
+*   No columns means the primary key of the table. The columns have to be its
+*   primary key or UNIQUE , so a value points at one row at most.
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Reference {
+    pub table: String,
+    pub columns: Vec<String>,
+    pub on_delete: ReferentialAction,
+    pub on_update: ReferentialAction,
+}
+
+// columns of a table that point at the rows of a parent table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKey {
+    columns: Vec<String>,
+    reference: Reference,
+}
+
+impl ForeignKey {
+    fn error(&self, table: &str) -> Error {
+        Error::Constraint(format!(
+            "FOREIGN KEY Constraint Failed on Column {}.{}",
+            table,
+            self.columns.join(", ")
+        ))
+    }
+}
+
+/*
+*   [CONSTRAINT name] CHECK (expr) on a column or the table. A row for which
+*   expr is false is refused , NULL lets it through like in sqlite.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: Option<String>,
+    pub expr: Expr,
+}
+
+impl Check {
+    // named after the expression when it has no name
+    fn error(&self) -> Error {
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => self.expr.to_string(),
+        };
+        Error::Constraint(format!("CHECK Constraint Failed: {}", name))
+    }
+}
+
+// true if `expr` reads the column
+fn mentions(expr: &Expr, column: &str) -> bool {
+    match expr {
+        Expr::Ident(name) => name == column,
+        Expr::Binary { left, right, .. } => mentions(left, column) || mentions(right, column),
+        Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Nested(expr) => {
+            mentions(expr, column)
+        }
+        Expr::Function { args, .. } => args.iter().any(|arg| mentions(arg, column)),
+        Expr::Between {
+            expr, low, high, ..
+        } => mentions(expr, column) || mentions(low, column) || mentions(high, column),
+        _ => false,
+    }
+}
+
+fn rename_ident(expr: &mut Expr, from: &str, to: &str) {
+    match expr {
+        Expr::Ident(name) if name == from => *name = to.to_string(),
+        Expr::Binary { left, right, .. } => {
+            rename_ident(left, from, to);
+            rename_ident(right, from, to);
+        }
+        Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Nested(expr) => {
+            rename_ident(expr, from, to)
+        }
+        Expr::Function { args, .. } => {
+            for arg in args {
+                rename_ident(arg, from, to);
+            }
+        }
+        Expr::Between {
+            expr, low, high, ..
+        } => {
+            rename_ident(expr, from, to);
+            rename_ident(low, from, to);
+            rename_ident(high, from, to);
+        }
+        _ => {}
+    }
 }
 
-#[derive(Debug, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Column {
     name: String,
     column_type: ColumnType,
     constraints: Vec<Constraints>,
-    order: u8,
+    // 1 for the first column , a u8 ran out at 256 columns
+    order: u32,
 }
 
 impl Column {
-    fn new(name: &str, column_type: ColumnType, constraints: &[Constraints], order: u8) -> Self {
+    fn new(name: &str, column_type: ColumnType, constraints: &[Constraints], order: u32) -> Self {
         Self {
             name: name.to_string(),
             column_type,
@@ -40,438 +176,3723 @@ impl Column {
             order,
         }
     }
+
+    // NULL without a DEFAULT
+    fn default_value(&self) -> Result<DataType, Error> {
+        let expr = self
+            .constraints
+            .iter()
+            .find_map(|constraint| match constraint {
+                Constraints::Default(expr) => Some(expr),
+                _ => None,
+            });
+        let value = match expr {
+            Some(expr) if is_constant(expr) => eval_constant(expr)?,
+            Some(expr) => {
+                return Err(Error::Schema(format!(
+                    "Default of column {} is not constant , got {}",
+                    self.name, expr
+                )))
+            }
+            None => return Ok(DataType::Null),
+        };
+        // CURRENT_TIMESTAMP in a TEXT column is its text , same as sqlite
+        let value = match value {
+            value if self.column_type == ColumnType::String && value.is_temporal() => {
+                DataType::Text(value.to_string())
+            }
+            value => value,
+        };
+        Schema::validate_column(self, value.clone()).ok_or_else(|| {
+            Error::Schema(format!(
+                "Invalid default for column {} expected {:?} got {}",
+                self.name, self.column_type, value
+            ))
+        })
+    }
+}
+
+/*
+*   An index maps the values of its columns to the key of the row that has
+*   them , rows with a NULL in one of the columns are left out. Many rows can
+*   have the same values in an index that is not UNIQUE , there the row key
+*   goes after the values so every row still gets its own entry. The BTree of
+*   an index lives next to the rows in Table::indexes.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    name: String,
+    columns: Vec<String>,
+    unique: bool,
+}
+
+impl Index {
+    pub fn new(name: &str, columns: Vec<String>, unique: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            columns,
+            unique,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn is_unique(&self) -> bool {
+        self.unique
+    }
+
+    fn unique_error(&self) -> Error {
+        Error::Constraint(format!(
+            "UNIQUE Constraint Failed on Column {}",
+            self.columns.join(", ")
+        ))
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Schema {
-    columns: HashSet<Column>,
-    curr_order: u8,
+    // in the order they were declared , a column is at `order - 1`
+    columns: Vec<Column>,
+    // the columns the rows are keyed by , in key order. Empty when the table
+    // has no PRIMARY KEY and the rows are keyed by an implicit rowid instead
+    primary_key: Vec<String>,
+    indexes: Vec<Index>,
+    foreign_keys: Vec<ForeignKey>,
+    checks: Vec<Check>,
 }
 
-#[derive(Debug, Clone, Eq, Ord, PartialEq, PartialOrd)]
+#[derive(Debug, Clone)]
 pub enum DataType {
+    Null,
     Int(i64),
     Text(String),
+    Real(f64),
+    Boolean(bool),
+    Blob(Vec<u8>),
+    // days since 1970-01-01 , see core::datetime
+    Date(i64),
+    // milliseconds since midnight
+    Time(i64),
+    // milliseconds since 1970-01-01 00:00 UTC
+    Timestamp(i64),
 }
 
-impl Default for DataType {
-    fn default() -> Self {
-        DataType::Int(0)
+/*
+*   Values compare like in sqlite: NULL first , then numbers (INT , REAL and
+*   BOOLEAN by their value , so 1 = 1.0 = TRUE) , then text and then blobs
+*   byte by byte. Dates and timestamps sit between numbers and text and
+*   compare by the moment they are (a DATE is its midnight) , times come after
+*   them. It is a total order , any value can be a BTree key.
+*/
+impl Ord for DataType {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (DataType::Real(a), DataType::Real(b)) => a
+                .partial_cmp(b)
+                .unwrap_or_else(|| b.is_nan().cmp(&a.is_nan())),
+            (DataType::Real(a), _) if other.class() == 1 => {
+                compare_int_real(other.int_value(), *a).reverse()
+            }
+            (_, DataType::Real(b)) if self.class() == 1 => compare_int_real(self.int_value(), *b),
+            (DataType::Text(a), DataType::Text(b)) => a.cmp(b),
+            (DataType::Blob(a), DataType::Blob(b)) => a.cmp(b),
+            (DataType::Time(a), DataType::Time(b)) => a.cmp(b),
+            _ if self.class() == 2 && other.class() == 2 => self.ms_value().cmp(&other.ms_value()),
+            _ if self.class() == 1 && other.class() == 1 => {
+                self.int_value().cmp(&other.int_value())
+            }
+            _ => self.class().cmp(&other.class()),
+        }
     }
 }
 
-impl DataType {
-    pub fn get_value(&self) -> &dyn std::fmt::Display {
+impl PartialOrd for DataType {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for DataType {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DataType {}
+
+// exact even where the i64 doesnt fit in a f64 , NaN goes before every number
+fn compare_int_real(int: i64, real: f64) -> Ordering {
+    match (int as f64).partial_cmp(&real) {
+        Some(Ordering::Equal) => (int as i128).cmp(&(real as i128)),
+        Some(ordering) => ordering,
+        None => Ordering::Greater,
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            DataType::Int(val) => val,
-            DataType::Text(val) => val,
+            DataType::Null => write!(f, "NULL"),
+            DataType::Int(val) => write!(f, "{}", val),
+            DataType::Text(val) => write!(f, "{}", val),
+            // 3.0 and not 3 , so it still reads as a REAL , and 1e300 instead of 300 digits
+            DataType::Real(val) if val.fract() == 0.0 && val.abs() < 1e16 => {
+                write!(f, "{:.1}", val)
+            }
+            DataType::Real(val) if val.abs() >= 1e16 => write!(f, "{:e}", val),
+            DataType::Real(val) => write!(f, "{}", val),
+            DataType::Boolean(true) => write!(f, "TRUE"),
+            DataType::Boolean(false) => write!(f, "FALSE"),
+            DataType::Blob(bytes) => {
+                write!(f, "x'")?;
+                for byte in bytes {
+                    write!(f, "{:02X}", byte)?;
+                }
+                write!(f, "'")
+            }
+            DataType::Date(days) => write!(f, "{}", datetime::format_date(*days)),
+            DataType::Time(ms) => write!(f, "{}", datetime::format_time(*ms)),
+            DataType::Timestamp(ms) => write!(f, "{}", datetime::format_timestamp(*ms)),
         }
     }
 }
 
-impl Schema {
-    pub fn new() -> Self {
-        Self {
-            columns: HashSet::new(),
-            curr_order: 1,
-        }
+impl Default for DataType {
+    fn default() -> Self {
+        DataType::Int(0)
     }
+}
 
-    pub fn add_column(
-        &mut self,
-        name: String,
-        column_type: ColumnType,
-        constraints: Vec<Constraints>,
-    ) {
-        let new_column = Column::new(&name, column_type, &constraints, self.curr_order);
-        self.columns.insert(new_column);
-        self.curr_order += 1;
+impl From<i64> for DataType {
+    fn from(value: i64) -> Self {
+        DataType::Int(value)
     }
+}
 
-    pub fn get_column(&self, name: &str) -> Option<&Column> {
-        self.columns.iter().find(|col| col.name == name)
+impl From<String> for DataType {
+    fn from(value: String) -> Self {
+        DataType::Text(value)
     }
+}
 
-    pub fn validate_column(schema_col: &Column, val: &Expr) -> Option<DataType> {
-        match schema_col.column_type {
-            ColumnType::String => match val {
-                Expr::Ident(ref s) => Some(DataType::Text(s.to_string())),
-                _ => None,
-            },
-            ColumnType::Int => match val {
-                Expr::Number(n) => Some(DataType::Int(*n)),
-                _ => None,
-            },
-            _ => None,
-        }
+impl From<&str> for DataType {
+    fn from(value: &str) -> Self {
+        DataType::Text(value.to_string())
     }
+}
 
-    pub fn validate_insert_row(
-        &self,
-        columns: &Vec<String>,
-        values: &Vec<Expr>,
-    ) -> Result<Vec<DataType>, String> {
-        let mut result: Vec<DataType> = vec![];
-        for (i, col) in columns.iter().enumerate() {
-            match self.get_column(&col) {
-                Some(schema_col) => match Self::validate_column(schema_col, &values[i]) {
-                    Some(datatype) => result.push(datatype),
-                    None => {
-                        return Err(format!(
-                            "Invalid type for column {} expected {:?} got {:?}",
-                            col, schema_col.column_type, values[i]
-                        ));
-                    }
-                },
-                None => return Err(format!("Did you just create the column: {}", &col)),
-            }
+// NaN is NULL , same as sqlite
+impl From<f64> for DataType {
+    fn from(value: f64) -> Self {
+        if value.is_nan() {
+            DataType::Null
+        } else {
+            DataType::Real(value)
         }
+    }
+}
 
-        // checkf for missing keys and then test one of them has a NOTNULL constraints ,if
-        // so , start crying
-        let missing_keys: Vec<String> = self
-            .columns
-            .iter()
-            .filter_map(|col| {
-                if columns.contains(&col.name) {
-                    None
-                } else {
-                    Some(col.name.clone())
-                }
-            })
-            .collect();
+impl From<bool> for DataType {
+    fn from(value: bool) -> Self {
+        DataType::Boolean(value)
+    }
+}
 
-        for missing_key in &missing_keys {
-            match self.get_column(&missing_key) {
-                Some(col) => {
-                    if col.constraints.contains(&Constraints::NotNull) {
-                        return Err(format!(
-                            "NOT NULL Constraint Failed on Column {}",
-                            &missing_key
-                        ));
-                    }
-                }
-                None => {}
-            }
-        }
-        Ok(result)
+impl From<Vec<u8>> for DataType {
+    fn from(value: Vec<u8>) -> Self {
+        DataType::Blob(value)
     }
+}
 
-    pub fn validate_row(&self, values: &Vec<String>) -> Result<(), String> {
-        for col in &self.columns {
-            if let Some(value) = values.get((col.order - 1) as usize) {
-                match col.column_type {
-                    ColumnType::Int => {
-                        value.parse::<i64>().expect("invalid int value");
-                    }
-                    ColumnType::String => (),
-                    ColumnType::Date => (),
-                }
-            } else {
-                return Err(format!("Missing column: {}", &col.name));
-            }
-        }
-        Ok(())
+impl From<&[u8]> for DataType {
+    fn from(value: &[u8]) -> Self {
+        DataType::Blob(value.to_vec())
     }
 }
 
-#[derive(Debug)]
-pub struct Table {
-    schema: Schema,
-    rows: BTree<DataType, HashMap<String, DataType>>,
+// None is NULL
+impl<T: Into<DataType>> From<Option<T>> for DataType {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(DataType::Null, Into::into)
+    }
 }
 
-impl Table {
-    pub fn new() -> Self {
-        Self {
-            schema: Schema::new(),
-            rows: BTree::new(2),
-        }
+impl DataType {
+    pub fn is_null(&self) -> bool {
+        matches!(self, DataType::Null)
     }
 
-    pub fn insert_row(&mut self, columns: Vec<String>, values_: Vec<Expr>) -> Result<(), String> {
-        let values = self.schema.validate_insert_row(&columns, &values_)?;
-        let id = columns
-            .iter()
-            .position(|s| s == "id")
-            .ok_or_else(|| "expected an 'id' column".to_string())?;
-        /*
-        let id = row
-            .get("id")
-            .ok_or_else(|| "missing primary key".to_string())?
-            .clone();
-        */
-        if self.rows.search(&values[id]).is_some() {
-            return Err("Duplicate primary key".to_string());
-        }
-
-        let mut rows = HashMap::new();
-        for i in 0..columns.len() {
-            rows.insert(columns[i].clone(), values[i].clone());
-        }
-        self.rows.insert(values[id].clone(), rows);
-        Ok(())
+    // which group of values this sorts with , see Ord
+    fn class(&self) -> u8 {
+        match self {
+            DataType::Null => 0,
+            DataType::Int(_) | DataType::Real(_) | DataType::Boolean(_) => 1,
+            DataType::Date(_) | DataType::Timestamp(_) => 2,
+            DataType::Time(_) => 3,
+            DataType::Text(_) => 4,
+            DataType::Blob(_) => 5,
+        }
     }
 
-    /*
-    pub fn delete_row(&mut self, query: &DeleteQuery) -> Result<(), String> {
-        if let Some(q) = &query.where_clause {
-            let _left_value = match q.left {
-                Expr::Ident(ref s) if s != "id" => return Err("expected id".to_string()),
-                Expr::Ident(ref s) => s,
-                _ => return Err("wtf is this ".to_string()),
-            };
+    pub fn is_temporal(&self) -> bool {
+        matches!(
+            self,
+            DataType::Date(_) | DataType::Time(_) | DataType::Timestamp(_)
+        )
+    }
 
-            let right_value = match &q.right {
-                Expr::Ident(n) => n,
-                _ => return Err("expected a number".to_string()),
-            };
+    // only for DATE and TIMESTAMP
+    fn ms_value(&self) -> i64 {
+        match self {
+            DataType::Date(days) => days * MS_PER_DAY,
+            DataType::Timestamp(ms) => *ms,
+            _ => 0,
+        }
+    }
 
-            println!("{}", right_value);
-            println!("{:?}", self.rows.search(right_value));
-            self.rows.delete(&right_value);
+    // only for INT and BOOLEAN
+    fn int_value(&self) -> i64 {
+        match self {
+            DataType::Int(val) => *val,
+            DataType::Boolean(val) => *val as i64,
+            _ => 0,
         }
-        Ok(())
     }
-    */
+}
 
-    fn delete_row(&mut self, query: DeleteQuery) -> Result<(), String> {
-        if let Some(c) = query.where_clause {
-            match c.left {
-                Expr::Ident(ref s) if s != "id" => {
-                    return Err("Buddy you have high expecations".to_string());
-                }
-                _ => {}
-            }
-            let right_value = match c.right {
-                Expr::Number(ref s) => Some(DataType::Int(*s)),
-                _ => return Err("man cmon , dont you know the id is a number??????".to_string()),
-            }
-            .expect("expected a valid value in right");
+impl Codec for ColumnType {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            ColumnType::Int => 0,
+            ColumnType::String => 1,
+            ColumnType::Date => 2,
+            ColumnType::Real => 3,
+            ColumnType::Boolean => 4,
+            ColumnType::Blob => 5,
+            ColumnType::Time => 6,
+            ColumnType::Timestamp => 7,
+        };
+        tag.encode(buf);
+    }
 
-            self.rows.delete(&right_value);
-        } else {
-            return Err(
-                "Expected a where clause , do you want me to delete the whole table??????"
-                    .to_string(),
-            );
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        match u8::decode(reader)? {
+            0 => Ok(ColumnType::Int),
+            1 => Ok(ColumnType::String),
+            2 => Ok(ColumnType::Date),
+            3 => Ok(ColumnType::Real),
+            4 => Ok(ColumnType::Boolean),
+            5 => Ok(ColumnType::Blob),
+            6 => Ok(ColumnType::Time),
+            7 => Ok(ColumnType::Timestamp),
+            tag => Err(Error::Io(format!("Unknown column type tag {}", tag))),
         }
-        Ok(())
     }
+}
 
-    fn get_row_from_where_clause(
-        &self,
-        clause: &Option<WhereClause>,
-    ) -> Result<HashMap<String, DataType>, String> {
-        if let Some(c) = clause {
-            let where_clause = c.clone();
-            match &where_clause.left {
-                Expr::Ident(ref s) if s != "id" => {
-                    return Err("Buddy you have high expecations".to_string());
-                }
-                _ => {}
-            };
-
-            let right_value = match where_clause.right {
-                Expr::Ident(ref s) => Some(DataType::Text(s.to_string())),
-                Expr::Number(ref s) => Some(DataType::Int(*s)),
-            }
-            .expect("expected a valid value in right");
-            let row = self.rows.search(&right_value);
-            Ok(row.ok_or_else(|| "Couldnt find a row").cloned()?)
-        } else {
-            Err("No Where Clause".to_string())
+impl Codec for Constraints {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            Constraints::Unique => 0,
+            Constraints::NotNull => 1,
+            Constraints::Null => 2,
+            Constraints::PrimaryKey => 3,
+            Constraints::ForeignKey(_) => 4,
+            Constraints::Default(_) => 5,
+            Constraints::Check(_) => 6,
+        };
+        tag.encode(buf);
+        match self {
+            Constraints::ForeignKey(reference) => reference.encode(buf),
+            Constraints::Default(expr) => expr.encode(buf),
+            Constraints::Check(check) => check.encode(buf),
+            _ => {}
         }
     }
 
-    pub fn select_rows(&self, query: &SelectQuery) -> Option<Vec<HashMap<String, DataType>>> {
-        if let Ok(row) = self.get_row_from_where_clause(&query.where_clause) {
-            let result: HashMap<String, DataType> = {
-                if query.columns[0] != "*" {
-                    query
-                        .columns
-                        .iter()
-                        .filter_map(|s| row.get(s).map(|v| (s.clone(), v.clone())))
-                        .collect()
-                } else {
-                    row
-                }
-            };
-            return Some(vec![result]);
-        } else {
-            if query.where_clause.is_none() {
-                // TODO:this is really bad , should be refactored
-
-                let result: Vec<HashMap<String, DataType>> = {
-                    if query.columns[0] == "*" {
-                        self.rows.values_in_order()
-                    } else {
-                        self.rows
-                            .values_in_order()
-                            .iter()
-                            .map(|row| {
-                                query
-                                    .columns
-                                    .iter()
-                                    .filter_map(|s| row.get(s).map(|v| (s.clone(), v.clone())))
-                                    .collect()
-                            })
-                            .collect()
-                    }
-                };
-                return Some(result);
-            } else {
-                None
-            }
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        match u8::decode(reader)? {
+            0 => Ok(Constraints::Unique),
+            1 => Ok(Constraints::NotNull),
+            2 => Ok(Constraints::Null),
+            3 => Ok(Constraints::PrimaryKey),
+            4 => Ok(Constraints::ForeignKey(Reference::decode(reader)?)),
+            5 => Ok(Constraints::Default(Expr::decode(reader)?)),
+            6 => Ok(Constraints::Check(Check::decode(reader)?)),
+            tag => Err(Error::Io(format!("Unknown constraint tag {}", tag))),
         }
     }
 }
 
-#[derive(Debug)]
-pub struct Neoqlite {
-    tables: HashMap<String, Table>,
-    debug: bool,
+impl Codec for ReferentialAction {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            ReferentialAction::NoAction => 0,
+            ReferentialAction::Restrict => 1,
+            ReferentialAction::Cascade => 2,
+            ReferentialAction::SetNull => 3,
+        };
+        tag.encode(buf);
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        match u8::decode(reader)? {
+            0 => Ok(ReferentialAction::NoAction),
+            1 => Ok(ReferentialAction::Restrict),
+            2 => Ok(ReferentialAction::Cascade),
+            3 => Ok(ReferentialAction::SetNull),
+            tag => Err(Error::Io(format!("Unknown referential action tag {}", tag))),
+        }
+    }
 }
 
-impl Neoqlite {
-    pub fn new() -> Self {
-        let mut tables = HashMap::new();
-        let mut user_table = Table::new();
-        user_table
-            .schema
-            .add_column("id".to_string(), ColumnType::Int, vec![]);
-        user_table
-            .schema
-            .add_column("username".to_string(), ColumnType::String, vec![]);
-        user_table
-            .schema
-            .add_column("email".to_string(), ColumnType::String, vec![]);
+impl Codec for Reference {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.table.encode(buf);
+        self.columns.encode(buf);
+        self.on_delete.encode(buf);
+        self.on_update.encode(buf);
+    }
 
-        user_table
-            .schema
-            .add_column("otp".to_string(), ColumnType::Int, vec![]);
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(Self {
+            table: String::decode(reader)?,
+            columns: Vec::decode(reader)?,
+            on_delete: ReferentialAction::decode(reader)?,
+            on_update: ReferentialAction::decode(reader)?,
+        })
+    }
+}
 
-        let mut dummy_table = Table::new();
-        dummy_table
-            .schema
-            .add_column("id".to_string(), ColumnType::Int, vec![]);
-        dummy_table
-            .schema
-            .add_column("username".to_string(), ColumnType::String, vec![]);
-        dummy_table
-            .schema
-            .add_column("email".to_string(), ColumnType::String, vec![]);
+impl Codec for ForeignKey {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.columns.encode(buf);
+        self.reference.encode(buf);
+    }
 
-        tables.insert("users".to_string(), user_table);
-        tables.insert("dummy".to_string(), dummy_table);
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(Self {
+            columns: Vec::decode(reader)?,
+            reference: Reference::decode(reader)?,
+        })
+    }
+}
 
-        let debug = false;
-        Self { tables, debug }
+// an expression is kept as its sql text and parsed again
+impl Codec for Expr {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.to_string().encode(buf);
     }
 
-    //  GOOD OLD OOP HUH
-    pub fn set_debug(&mut self, value: bool) {
-        self.debug = value;
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        let sql = String::decode(reader)?;
+        Lexer::new(&sql)
+            .tokenize()
+            .and_then(|tokens| Parser::new(tokens).parse_expr())
+            .map_err(|err| Error::Io(format!("Bad expression {} in schema , {}", sql, err)))
     }
+}
 
-    pub fn exec_meta_command(&self, input: &str) {
-        match input {
-            ".exit" => exit(0),
-            ".bt" => {
-                for (table_name, table) in &self.tables {
-                    println!("Table : {}", table_name);
-                    println!("\n{:?}\n", table);
-                }
-            }
-            _ => println!("Unknown Meta Command"),
-        }
+impl Codec for Check {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.name.encode(buf);
+        self.expr.encode(buf);
     }
 
-    pub fn exec_insert(&mut self, query: InsertQuery) -> Result<(), String> {
-        let table = self
-            .tables
-            .get_mut(&query.table)
-            .ok_or_else(|| "Table not found".to_string())?;
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(Self {
+            name: Option::decode(reader)?,
+            expr: Expr::decode(reader)?,
+        })
+    }
+}
 
-        if query.columns.len() != query.values.len() {
-            return Err("columns and values are not the same length".to_string());
-        }
-        table.insert_row(query.columns, query.values)
+impl Codec for Column {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.name.encode(buf);
+        self.column_type.encode(buf);
+        self.constraints.encode(buf);
+        self.order.encode(buf);
     }
 
-    pub fn exec_select(&self, query: SelectQuery) -> Result<(), String> {
-        let table = self
-            .tables
-            .get(&query.table)
-            .ok_or_else(|| "Table not found".to_string())?;
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(Self {
+            name: String::decode(reader)?,
+            column_type: ColumnType::decode(reader)?,
+            constraints: Vec::decode(reader)?,
+            order: u32::decode(reader)?,
+        })
+    }
+}
 
-        let result = table.select_rows(&query).ok_or("None")?;
-        for row in result {
-            println!("{:?}", row);
-        }
-        Ok(())
+impl Codec for Index {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.name.encode(buf);
+        self.columns.encode(buf);
+        self.unique.encode(buf);
     }
 
-    pub fn exec_delete(&mut self, query: DeleteQuery) -> Result<(), String> {
-        let table = &mut self
-            .tables
-            .get_mut(&query.table)
-            .ok_or_else(|| "Table not found".to_string())?;
-        table.delete_row(query)?;
-        Ok(())
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(Self {
+            name: String::decode(reader)?,
+            columns: Vec::decode(reader)?,
+            unique: bool::decode(reader)?,
+        })
     }
+}
 
-    pub fn exec_create_table(&mut self, query: CreateTableQuery) -> Result<(), String> {
-        if self.tables.get(&query.table).is_some() {
-            return Err(format!("A Table with name '{}' exists", query.table));
+impl Codec for Schema {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.columns.encode(buf);
+        self.primary_key.encode(buf);
+        self.indexes.encode(buf);
+        self.foreign_keys.encode(buf);
+        self.checks.encode(buf);
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(Self {
+            columns: Vec::decode(reader)?,
+            primary_key: Vec::decode(reader)?,
+            indexes: Vec::decode(reader)?,
+            foreign_keys: Vec::decode(reader)?,
+            checks: Vec::decode(reader)?,
+        })
+    }
+}
+
+impl Codec for DataType {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            DataType::Int(val) => {
+                0u8.encode(buf);
+                val.encode(buf);
+            }
+            DataType::Text(val) => {
+                1u8.encode(buf);
+                val.encode(buf);
+            }
+            DataType::Null => 2u8.encode(buf),
+            DataType::Real(val) => {
+                3u8.encode(buf);
+                val.to_bits().encode(buf);
+            }
+            DataType::Boolean(val) => {
+                4u8.encode(buf);
+                val.encode(buf);
+            }
+            DataType::Blob(val) => {
+                5u8.encode(buf);
+                val.encode(buf);
+            }
+            DataType::Date(val) => {
+                6u8.encode(buf);
+                val.encode(buf);
+            }
+            DataType::Time(val) => {
+                7u8.encode(buf);
+                val.encode(buf);
+            }
+            DataType::Timestamp(val) => {
+                8u8.encode(buf);
+                val.encode(buf);
+            }
+        }
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        match u8::decode(reader)? {
+            0 => Ok(DataType::Int(i64::decode(reader)?)),
+            1 => Ok(DataType::Text(String::decode(reader)?)),
+            2 => Ok(DataType::Null),
+            3 => Ok(DataType::Real(f64::from_bits(u64::decode(reader)?))),
+            4 => Ok(DataType::Boolean(bool::decode(reader)?)),
+            5 => Ok(DataType::Blob(Vec::decode(reader)?)),
+            6 => Ok(DataType::Date(i64::decode(reader)?)),
+            7 => Ok(DataType::Time(i64::decode(reader)?)),
+            8 => Ok(DataType::Timestamp(i64::decode(reader)?)),
+            tag => Err(Error::Io(format!("Unknown data type tag {}", tag))),
+        }
+    }
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self {
+            columns: Vec::new(),
+            primary_key: Vec::new(),
+            indexes: Vec::new(),
+            foreign_keys: Vec::new(),
+            checks: Vec::new(),
+        }
+    }
+
+    pub fn add_column(
+        &mut self,
+        name: String,
+        column_type: ColumnType,
+        constraints: Vec<Constraints>,
+    ) {
+        let order = self.columns.len() as u32 + 1;
+        let new_column = Column::new(&name, column_type, &constraints, order);
+        self.columns.push(new_column);
+    }
+
+    // later columns move up one place so the order stays without gaps ,
+    // gives back the position the column had
+    pub fn drop_column(&mut self, name: &str) -> Result<usize, Error> {
+        let index = self
+            .column_index(name)
+            .ok_or_else(|| Error::NotFound(format!("No such column: {}", name)))?;
+        self.columns.remove(index);
+        for col in &mut self.columns[index..] {
+            col.order -= 1;
+        }
+        Ok(index)
+    }
+
+    pub fn rename_column(&mut self, from: &str, to: &str) -> Result<(), Error> {
+        if self.get_column(to).is_some() {
+            return Err(Error::Schema(format!("A column with name '{}' exists", to)));
+        }
+        let index = self
+            .column_index(from)
+            .ok_or_else(|| Error::NotFound(format!("No such column: {}", from)))?;
+        self.columns[index].name = to.to_string();
+        for check in &mut self.checks {
+            rename_ident(&mut check.expr, from, to);
+        }
+        let indexed = self.indexes.iter_mut().flat_map(|index| &mut index.columns);
+        let referencing = self.foreign_keys.iter_mut().flat_map(|fk| &mut fk.columns);
+        for name in self
+            .primary_key
+            .iter_mut()
+            .chain(indexed)
+            .chain(referencing)
+        {
+            if name == from {
+                *name = to.to_string();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_primary_key(&mut self, columns: Vec<String>) -> Result<(), Error> {
+        for (i, name) in columns.iter().enumerate() {
+            if self.get_column(name).is_none() {
+                return Err(Error::NotFound(format!("No such column: {}", name)));
+            }
+            if columns[..i].contains(name) {
+                return Err(Error::Schema(format!(
+                    "Column {} is in the primary key twice",
+                    name
+                )));
+            }
+        }
+        self.primary_key = columns;
+        Ok(())
+    }
+
+    // empty when rows are keyed by rowid
+    pub fn primary_key(&self) -> &[String] {
+        &self.primary_key
+    }
+
+    pub fn is_primary_key(&self, name: &str) -> bool {
+        self.primary_key.iter().any(|key| key == name)
+    }
+
+    pub fn indexes(&self) -> &[Index] {
+        &self.indexes
+    }
+
+    // the first index that covers the column
+    pub fn index_on(&self, name: &str) -> Option<&Index> {
+        self.indexes
+            .iter()
+            .find(|index| index.columns.iter().any(|col| col == name))
+    }
+
+    pub fn add_foreign_key(
+        &mut self,
+        columns: Vec<String>,
+        reference: Reference,
+    ) -> Result<(), Error> {
+        for name in &columns {
+            if self.get_column(name).is_none() {
+                return Err(Error::NotFound(format!("No such column: {}", name)));
+            }
+        }
+        if !reference.columns.is_empty() && reference.columns.len() != columns.len() {
+            return Err(Error::Schema(format!(
+                "Foreign key on {} has {} columns but references {}",
+                columns.join(", "),
+                columns.len(),
+                reference.columns.len()
+            )));
+        }
+        self.foreign_keys.push(ForeignKey { columns, reference });
+        Ok(())
+    }
+
+    pub fn foreign_keys(&self) -> &[ForeignKey] {
+        &self.foreign_keys
+    }
+
+    // the expression can only use columns of the table
+    pub fn add_check(&mut self, check: Check) -> Result<(), Error> {
+        eval(&check.expr, self, &vec![DataType::Null; self.columns.len()])?;
+        self.checks.push(check);
+        Ok(())
+    }
+
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    // every CHECK of the table on the row
+    pub fn check_row(&self, row: &Row) -> Result<(), Error> {
+        for check in &self.checks {
+            let value = eval(&check.expr, self, row)?;
+            if !value.is_null() && !is_true(&value) {
+                return Err(check.error());
+            }
+        }
+        Ok(())
+    }
+
+    // what the row with `key` is stored under in the index , None when it is left out
+    fn index_key(&self, index: &Index, key: &Key, row: &Row) -> Option<Key> {
+        let mut index_key = self.values_of(&index.columns, row)?;
+        if !index.unique {
+            index_key.extend(key.iter().cloned());
+        }
+        Some(index_key)
+    }
+
+    // the values of `columns` in `row` , None if one of them is NULL
+    fn values_of(&self, columns: &[String], row: &Row) -> Option<Key> {
+        columns
+            .iter()
+            .map(|name| {
+                let value = row.get(self.column_index(name)?)?;
+                (!value.is_null()).then(|| value.clone())
+            })
+            .collect()
+    }
+
+    // names of the columns in the order they were declared
+    pub fn column_names(&self) -> Vec<String> {
+        self.columns.iter().map(|col| col.name.clone()).collect()
+    }
+
+    pub fn get_column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|col| col.name == name)
+    }
+
+    // where the value of the column is in a row
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|col| col.name == name)
+    }
+
+    pub fn validate_column(schema_col: &Column, val: DataType) -> Option<DataType> {
+        match (&schema_col.column_type, val) {
+            (ColumnType::String, val @ DataType::Text(_)) => Some(val),
+            (ColumnType::Int, val @ DataType::Int(_)) => Some(val),
+            (ColumnType::Real, val @ DataType::Real(_)) => Some(val),
+            // integers are fine where a REAL or a BOOLEAN goes , like 1 for TRUE
+            (ColumnType::Real, DataType::Int(val)) => Some(DataType::Real(val as f64)),
+            (ColumnType::Boolean, val @ DataType::Boolean(_)) => Some(val),
+            (ColumnType::Boolean, DataType::Int(val @ (0 | 1))) => {
+                Some(DataType::Boolean(val == 1))
+            }
+            (ColumnType::Blob, val @ DataType::Blob(_)) => Some(val),
+            // dates and times can be written as ISO-8601 text
+            (ColumnType::Date, val @ DataType::Date(_)) => Some(val),
+            (ColumnType::Date, DataType::Text(s)) => datetime::parse_date(&s).map(DataType::Date),
+            (ColumnType::Time, val @ DataType::Time(_)) => Some(val),
+            (ColumnType::Time, DataType::Text(s)) => datetime::parse_time(&s).map(DataType::Time),
+            (ColumnType::Timestamp, val @ DataType::Timestamp(_)) => Some(val),
+            (ColumnType::Timestamp, DataType::Date(days)) => {
+                Some(DataType::Timestamp(days * MS_PER_DAY))
+            }
+            (ColumnType::Timestamp, DataType::Text(s)) => {
+                datetime::parse_timestamp(&s).map(DataType::Timestamp)
+            }
+            // NOT NULL is checked on the whole row , see check_not_null
+            (_, DataType::Null) => Some(DataType::Null),
+            _ => None,
+        }
+    }
+
+    // the row in schema order , columns left out get their DEFAULT or NULL
+    pub fn validate_insert_row(&self, columns: &[String], values: &[Expr]) -> Result<Row, Error> {
+        let mut result: Row = Vec::with_capacity(self.columns.len());
+        for col in &self.columns {
+            if columns.contains(&col.name) {
+                result.push(DataType::Null);
+            } else {
+                result.push(col.default_value()?);
+            }
+        }
+        for (i, col) in columns.iter().enumerate() {
+            match self.column_index(col) {
+                Some(index) => {
+                    match Self::validate_column(&self.columns[index], eval_constant(&values[i])?) {
+                        Some(datatype) => result[index] = datatype,
+                        None => {
+                            return Err(Error::Schema(format!(
+                                "Invalid type for column {} expected {:?} got {}",
+                                col, self.columns[index].column_type, values[i]
+                            )));
+                        }
+                    }
+                }
+                None => {
+                    return Err(Error::Schema(format!(
+                        "Did you just create the column: {}",
+                        &col
+                    )))
+                }
+            }
+        }
+
+        self.check_not_null(&result)?;
+        Ok(result)
+    }
+
+    // a NULL in a NOTNULL column , whether it was left out or written as NULL
+    pub fn check_not_null(&self, row: &Row) -> Result<(), Error> {
+        for (col, value) in self.columns.iter().zip(row) {
+            if value.is_null() && col.constraints.contains(&Constraints::NotNull) {
+                return Err(Error::Constraint(format!(
+                    "NOT NULL Constraint Failed on Column {}",
+                    &col.name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// the values of a row by column position
+pub type Row = Vec<DataType>;
+
+// what a row is stored under , the values of its primary key columns or its rowid
+pub type Key = Vec<DataType>;
+
+/*
+*   Where the rows of a table live , either a plain in-memory BTree or a BTree
+*   whose nodes are pages of the database file.
+*/
+#[derive(Debug, Clone)]
+enum RowStore {
+    Memory(BTree<Key, Row>),
+    Paged(PagedBTree<Key, Row>),
+}
+
+impl RowStore {
+    fn search(&self, key: &Key) -> Result<Option<Row>, Error> {
+        match self {
+            RowStore::Memory(tree) => Ok(tree.search(key).cloned()),
+            RowStore::Paged(tree) => tree.search(key),
+        }
+    }
+
+    fn insert(&mut self, key: Key, row: Row) -> Result<(), Error> {
+        match self {
+            RowStore::Memory(tree) => {
+                tree.insert(key, row);
+                Ok(())
+            }
+            RowStore::Paged(tree) => tree.insert(key, row),
+        }
+    }
+
+    fn delete(&mut self, key: &Key) -> Result<(), Error> {
+        match self {
+            RowStore::Memory(tree) => {
+                tree.delete(key);
+                Ok(())
+            }
+            RowStore::Paged(tree) => tree.delete(key),
+        }
+    }
+
+    fn values_in_order(&self) -> Result<Vec<Row>, Error> {
+        match self {
+            RowStore::Memory(tree) => Ok(tree.values_in_order()),
+            RowStore::Paged(tree) => tree.values_in_order(),
+        }
+    }
+
+    fn entries_in_order(&self) -> Result<Vec<(Key, Row)>, Error> {
+        match self {
+            RowStore::Memory(tree) => Ok(tree.entries_in_order()),
+            RowStore::Paged(tree) => tree.entries_in_order(),
+        }
+    }
+
+    // see BTree::entries_in_range
+    fn entries_in_range(&self, cmp: impl Fn(&Key) -> Ordering) -> Result<Vec<(Key, Row)>, Error> {
+        match self {
+            RowStore::Memory(tree) => Ok(tree.entries_in_range(cmp)),
+            RowStore::Paged(tree) => tree.entries_in_range(cmp),
+        }
+    }
+
+    // a cursor before the first row
+    fn cursor(&self) -> Result<RowCursor<'_>, Error> {
+        match self {
+            RowStore::Memory(tree) => Ok(RowCursor::Memory(tree.cursor())),
+            RowStore::Paged(tree) => Ok(RowCursor::Paged(tree.cursor()?)),
+        }
+    }
+
+    // a store of the same kind holding `entries` , which have to be in key order
+    fn filled_like(&self, entries: Vec<(Key, Row)>) -> Result<RowStore, Error> {
+        match self {
+            RowStore::Memory(_) => Ok(RowStore::Memory(BTree::from_sorted_iter(2, entries))),
+            RowStore::Paged(tree) => Ok(RowStore::Paged(tree.filled_like(entries)?)),
+        }
+    }
+
+    fn last_key(&self) -> Result<Option<Key>, Error> {
+        match self {
+            RowStore::Memory(tree) => Ok(tree.last_key().cloned()),
+            RowStore::Paged(tree) => tree.last_key(),
+        }
+    }
+
+    // frees the pages of a paged tree , for when the table goes away
+    fn clear(&mut self) -> Result<(), Error> {
+        match self {
+            RowStore::Memory(tree) => {
+                *tree = BTree::new(2);
+                Ok(())
+            }
+            RowStore::Paged(tree) => tree.clear(),
+        }
+    }
+
+    // 0 when there is no page , like everywhere else in the file format
+    fn root_page(&self) -> PageNum {
+        match self {
+            RowStore::Memory(_) => 0,
+            RowStore::Paged(tree) => tree.root().unwrap_or(0),
+        }
+    }
+}
+
+// a cursor over a RowStore , the rows are copied out one at a time as it moves
+enum RowCursor<'a> {
+    Memory(btree::Cursor<'a, Key, Row>),
+    Paged(PagedCursor<'a, Key, Row>),
+}
+
+impl RowCursor<'_> {
+    fn move_to_end(&mut self) -> Result<(), Error> {
+        match self {
+            RowCursor::Memory(cursor) => {
+                cursor.move_to_end();
+                Ok(())
+            }
+            RowCursor::Paged(cursor) => cursor.move_to_end(),
+        }
+    }
+
+    fn seek_by(&mut self, cmp: impl Fn(&Key) -> Ordering) -> Result<(), Error> {
+        match self {
+            RowCursor::Memory(cursor) => {
+                cursor.seek_by(cmp);
+                Ok(())
+            }
+            RowCursor::Paged(cursor) => cursor.seek_by(cmp),
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<(Key, Row)>, Error> {
+        match self {
+            RowCursor::Memory(cursor) => {
+                Ok(cursor.next().map(|(key, row)| (key.clone(), row.clone())))
+            }
+            RowCursor::Paged(cursor) => cursor.next().transpose(),
+        }
+    }
+
+    fn prev(&mut self) -> Result<Option<(Key, Row)>, Error> {
+        match self {
+            RowCursor::Memory(cursor) => {
+                Ok(cursor.prev().map(|(key, row)| (key.clone(), row.clone())))
+            }
+            RowCursor::Paged(cursor) => cursor.prev().transpose(),
+        }
+    }
+}
+
+/*
+*   The rows a WHERE clause can match , in key order or the other way around.
+*   A range of keys (or the whole table) is read through a cursor as the rows
+*   are asked for , rows found by their key or through an index are looked up
+*   up front.
+*/
+enum RowScan<'a> {
+    Found(std::vec::IntoIter<(Key, Row)>),
+    Range {
+        cursor: RowCursor<'a>,
+        range: IndexRange,
+        descending: bool,
+    },
+}
+
+impl Iterator for RowScan<'_> {
+    type Item = Result<(Key, Row), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            RowScan::Found(rows) => return rows.next().map(Ok),
+            RowScan::Range {
+                cursor,
+                range,
+                descending,
+            } => {
+                let entry = if *descending {
+                    cursor.prev()
+                } else {
+                    cursor.next()
+                };
+                match entry {
+                    Ok(Some((key, row))) if range.cmp(&key) == Ordering::Equal => {
+                        return Some(Ok((key, row)))
+                    }
+                    Err(error) => return Some(Err(error)),
+                    Ok(_) => {}
+                }
+            }
+        }
+        // past the end of the range , nothing comes after it
+        *self = RowScan::Found(Vec::new().into_iter());
+        None
+    }
+}
+
+/*
+*   The part of an index (or of the rows , by their key) a WHERE clause asks
+*   for: the values of its first columns and maybe bounds on the column after
+*   them , with whether the bound itself is in.
+*/
+#[derive(Debug, Default)]
+struct IndexRange {
+    equal: Vec<DataType>,
+    lower: Option<(DataType, bool)>,
+    upper: Option<(DataType, bool)>,
+}
+
+impl IndexRange {
+    // where an index key is compared to the range , see BTree::entries_in_range
+    fn cmp(&self, index_key: &Key) -> Ordering {
+        for (value, wanted) in index_key.iter().zip(&self.equal) {
+            match value.cmp(wanted) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            }
+        }
+        let Some(value) = index_key.get(self.equal.len()) else {
+            return Ordering::Equal;
+        };
+        if let Some((lower, inclusive)) = &self.lower {
+            if value < lower || (value == lower && !inclusive) {
+                return Ordering::Less;
+            }
+        }
+        if let Some((upper, inclusive)) = &self.upper {
+            if value > upper || (value == upper && !inclusive) {
+                return Ordering::Greater;
+            }
+        }
+        Ordering::Equal
+    }
+
+    // more is fewer rows to look at , 0 is no better than a table scan
+    fn score(&self) -> usize {
+        self.equal.len() * 2 + (self.lower.is_some() || self.upper.is_some()) as usize
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    schema: Schema,
+    rows: RowStore,
+    // one per schema index , in the same order
+    indexes: Vec<RowStore>,
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self {
+            schema: Schema::new(),
+            rows: RowStore::Memory(BTree::new(2)),
+            indexes: Vec::new(),
+        }
+    }
+
+    // `index_roots` has the root page of every schema index , in order
+    fn paged(
+        schema: Schema,
+        pager: Rc<RefCell<Pager>>,
+        root: PageNum,
+        index_roots: &[PageNum],
+    ) -> Self {
+        let store = |root: PageNum| {
+            let root = (root != 0).then_some(root);
+            RowStore::Paged(PagedBTree::new(Rc::clone(&pager), root, PAGED_BTREE_ORDER))
+        };
+        Self {
+            rows: store(root),
+            indexes: index_roots.iter().map(|&root| store(root)).collect(),
+            schema,
+        }
+    }
+
+    /*
+     *   Builds the index from the rows already in the table , a UNIQUE index
+     *   fails when two of them have the same values.
+     */
+    pub fn add_index(&mut self, index: Index) -> Result<(), Error> {
+        for name in &index.columns {
+            if self.schema.get_column(name).is_none() {
+                return Err(Error::NotFound(format!("No such column: {}", name)));
+            }
+        }
+        let mut entries = Vec::new();
+        for (key, row) in self.rows.entries_in_order()? {
+            if let Some(index_key) = self.schema.index_key(&index, &key, &row) {
+                entries.push((index_key, key));
+            }
+        }
+        // sorted the index can be built in one go instead of row by row
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        if index.unique && entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(index.unique_error());
+        }
+        let store = self.rows.filled_like(entries)?;
+        self.schema.indexes.push(index);
+        self.indexes.push(store);
+        Ok(())
+    }
+
+    // frees the pages of the index too
+    pub fn drop_index(&mut self, name: &str) -> Result<(), Error> {
+        let position = self
+            .schema
+            .indexes
+            .iter()
+            .position(|index| index.name == name)
+            .ok_or_else(|| Error::NotFound(format!("No such index: {}", name)))?;
+        self.schema.indexes.remove(position);
+        self.indexes.remove(position).clear()
+    }
+
+    // adds the row to every index , after it went into the table
+    fn index_row(&mut self, key: &Key, row: &Row) -> Result<(), Error> {
+        for (index, store) in self.schema.indexes.iter().zip(&mut self.indexes) {
+            if let Some(index_key) = self.schema.index_key(index, key, row) {
+                store.insert(index_key, key.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unindex_row(&mut self, key: &Key, row: &Row) -> Result<(), Error> {
+        for (index, store) in self.schema.indexes.iter().zip(&mut self.indexes) {
+            if let Some(index_key) = self.schema.index_key(index, key, row) {
+                store.delete(&index_key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     *   The UNIQUE index that already has the values of one of `rows` , rows
+     *   in `replaced` are about to go away so clashing with them is fine.
+     */
+    fn check_unique(&self, rows: &[&Row], replaced: &BTreeSet<&Key>) -> Result<(), Error> {
+        for (index, store) in self.schema.indexes.iter().zip(&self.indexes) {
+            if !index.unique {
+                continue;
+            }
+            let mut seen = BTreeSet::new();
+            for row in rows {
+                let Some(index_key) = self.schema.values_of(&index.columns, row) else {
+                    continue;
+                };
+                let taken = match store.search(&index_key)? {
+                    Some(owner) => !replaced.contains(&owner),
+                    None => false,
+                };
+                if taken || !seen.insert(index_key) {
+                    return Err(index.unique_error());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // gives back the row as it was stored
+    pub fn insert_row(&mut self, columns: Vec<String>, values_: Vec<Expr>) -> Result<Row, Error> {
+        let row = self.schema.validate_insert_row(&columns, &values_)?;
+        self.schema.check_row(&row)?;
+        let id = if self.schema.primary_key.is_empty() {
+            self.next_rowid()?
+        } else {
+            self.key_of(&row)?
+        };
+        if self.rows.search(&id)?.is_some() {
+            return Err(Error::Constraint("Duplicate primary key".to_string()));
+        }
+        self.check_unique(&[&row], &BTreeSet::new())?;
+
+        self.index_row(&id, &row)?;
+        self.rows.insert(id, row.clone())?;
+        Ok(row)
+    }
+
+    // find everything first so a bad row cant leave the delete half done
+    fn rows_to_delete(&self, query: &DeleteQuery) -> Result<Vec<(Key, Row)>, Error> {
+        let clause = query.where_clause.as_ref().ok_or_else(|| {
+            Error::Execution(
+                "Expected a where clause , do you want me to delete the whole table??????"
+                    .to_string(),
+            )
+        })?;
+        self.find_rows(Some(clause))
+    }
+
+    fn remove_rows(&mut self, rows: &[(Key, Row)]) -> Result<(), Error> {
+        for (key, row) in rows {
+            self.unindex_row(key, row)?;
+            self.rows.delete(key)?;
+        }
+        Ok(())
+    }
+
+    /*
+     *   (key , row , new row) for every row the update changes. Values are
+     *   computed from the row as it was before the update.
+     */
+    fn plan_update(&self, query: &UpdateQuery) -> Result<Vec<(Key, Row, Row)>, Error> {
+        for (column, _) in &query.assignments {
+            if self.schema.get_column(column).is_none() {
+                return Err(Error::Schema(format!(
+                    "Did you just create the column: {}",
+                    column
+                )));
+            }
+        }
+
+        let mut changes = Vec::new();
+        for (key, row) in self.find_rows(query.where_clause.as_ref())? {
+            let mut new_row = row.clone();
+            for (column, expr) in &query.assignments {
+                let index = self.schema.column_index(column).unwrap();
+                let schema_col = &self.schema.columns[index];
+                match Schema::validate_column(schema_col, eval(expr, &self.schema, &row)?) {
+                    Some(value) => new_row[index] = value,
+                    None => {
+                        return Err(Error::Schema(format!(
+                            "Invalid type for column {} expected {:?} got {}",
+                            column, schema_col.column_type, expr
+                        )))
+                    }
+                }
+            }
+            changes.push((key, row, new_row));
+        }
+        Ok(changes)
+    }
+
+    /*
+     *   Works out every new key before touching the tree , so a bad value or a
+     *   clashing key in the middle of the update leaves the table as it was.
+     */
+    fn replace_rows(&mut self, changes: &[(Key, Row, Row)]) -> Result<(), Error> {
+        // (key , row , new key , new row)
+        let mut updates: Vec<(&Key, &Row, Key, &Row)> = Vec::new();
+        for (key, row, new_row) in changes {
+            self.schema.check_not_null(new_row)?;
+            self.schema.check_row(new_row)?;
+            // a row keeps its rowid , there is nothing in the row to move it
+            let new_key = if self.schema.primary_key.is_empty() {
+                key.clone()
+            } else {
+                self.key_of(new_row)?
+            };
+            updates.push((key, row, new_key, new_row));
+        }
+
+        // a new key may only take the place of a key this update moves away
+        let old_keys: BTreeSet<&Key> = updates.iter().map(|(key, ..)| *key).collect();
+        let mut new_keys: BTreeSet<&Key> = BTreeSet::new();
+        for (_, _, new_key, _) in &updates {
+            if !new_keys.insert(new_key)
+                || (!old_keys.contains(new_key) && self.rows.search(new_key)?.is_some())
+            {
+                return Err(Error::Constraint("Duplicate primary key".to_string()));
+            }
+        }
+        let new_rows: Vec<&Row> = updates.iter().map(|(.., new_row)| *new_row).collect();
+        self.check_unique(&new_rows, &old_keys)?;
+
+        for (key, row, _, _) in &updates {
+            self.unindex_row(key, row)?;
+            self.rows.delete(key)?;
+        }
+        for (_, _, new_key, new_row) in updates {
+            self.index_row(&new_key, new_row)?;
+            self.rows.insert(new_key, new_row.clone())?;
+        }
+        Ok(())
+    }
+
+    fn add_column(
+        &mut self,
+        name: String,
+        column_type: ColumnType,
+        constraints: Vec<Constraints>,
+    ) -> Result<(), Error> {
+        if self.schema.get_column(&name).is_some() {
+            return Err(Error::Schema(format!(
+                "A column with name '{}' exists",
+                name
+            )));
+        }
+        // every existing row would get a NULL key
+        if constraints.contains(&Constraints::PrimaryKey) {
+            return Err(Error::Schema(format!(
+                "Cannot add a PRIMARY KEY column {}",
+                name
+            )));
+        }
+        if constraints.contains(&Constraints::Unique) {
+            return Err(Error::Schema(format!(
+                "Cannot add a UNIQUE column {}",
+                name
+            )));
+        }
+        let mut column_constraints = Vec::new();
+        let mut references = Vec::new();
+        let mut checks = Vec::new();
+        for constraint in constraints {
+            match constraint {
+                Constraints::ForeignKey(reference) => references.push(reference),
+                Constraints::Check(check) => checks.push(check),
+                constraint => column_constraints.push(constraint),
+            }
+        }
+
+        // existing rows get the DEFAULT , it has to pass the new constraints
+        let mut schema = self.schema.clone();
+        schema.add_column(name.clone(), column_type, column_constraints);
+        let column = schema.get_column(&name).unwrap();
+        let default = column.default_value()?;
+        let not_null = column.constraints.contains(&Constraints::NotNull);
+        let rows = self.rows.values_in_order()?;
+        if default.is_null() && not_null && !rows.is_empty() {
+            return Err(Error::Constraint(format!(
+                "Cannot add NOT NULL column {} without a default to a table that has rows",
+                name
+            )));
+        }
+        // a NULL points at nothing and is fine , a default would have to point at a parent row
+        if !default.is_null() && !references.is_empty() {
+            return Err(Error::Schema(format!(
+                "Cannot add a REFERENCES column {} with a default",
+                name
+            )));
+        }
+        for check in checks {
+            schema.add_check(check)?;
+        }
+        for reference in references {
+            schema.add_foreign_key(vec![name.clone()], reference)?;
+        }
+        for mut row in rows {
+            row.push(default.clone());
+            schema.check_row(&row)?;
+        }
+
+        self.schema = schema;
+        self.rewrite_rows(|row| row.push(default.clone()))
+    }
+
+    fn drop_column(&mut self, name: &str) -> Result<(), Error> {
+        if self.schema.is_primary_key(name) {
+            return Err(Error::Schema(format!(
+                "Cannot drop column {} , it is part of the primary key",
+                name
+            )));
+        }
+        if let Some(index) = self.schema.index_on(name) {
+            return Err(Error::Schema(format!(
+                "Cannot drop column {} , it is used by index {}",
+                name, index.name
+            )));
+        }
+        if self
+            .schema
+            .foreign_keys
+            .iter()
+            .any(|fk| fk.columns.iter().any(|col| col == name))
+        {
+            return Err(Error::Schema(format!(
+                "Cannot drop column {} , it is part of a foreign key",
+                name
+            )));
+        }
+        if let Some(check) = self
+            .schema
+            .checks
+            .iter()
+            .find(|check| mentions(&check.expr, name))
+        {
+            return Err(Error::Schema(format!(
+                "Cannot drop column {} , it is used by CHECK ({})",
+                name, check.expr
+            )));
+        }
+        let index = self.schema.drop_column(name)?;
+        self.rewrite_rows(|row| {
+            row.remove(index);
+        })
+    }
+
+    fn rename_column(&mut self, from: &str, to: &str) -> Result<(), Error> {
+        // rows dont hold column names , only the schema changes
+        self.schema.rename_column(from, to)
+    }
+
+    // runs `f` over every row to make it fit the schema again , keys stay the same
+    fn rewrite_rows(&mut self, f: impl Fn(&mut Row)) -> Result<(), Error> {
+        let mut entries = self.rows.entries_in_order()?;
+        for (_, row) in &mut entries {
+            f(row);
+        }
+        // the keys come out in order , so the tree is built again in one go
+        self.rows.clear()?;
+        self.rows = self.rows.filled_like(entries)?;
+        Ok(())
+    }
+
+    // the values of the primary key columns , only for tables that have one
+    fn key_of(&self, row: &Row) -> Result<Key, Error> {
+        let mut key = Vec::with_capacity(self.schema.primary_key.len());
+        for name in &self.schema.primary_key {
+            match self
+                .schema
+                .column_index(name)
+                .and_then(|index| row.get(index))
+            {
+                Some(DataType::Null) => {
+                    return Err(Error::Constraint(format!(
+                        "NOT NULL Constraint Failed on Column {}",
+                        name
+                    )))
+                }
+                Some(value) => key.push(value.clone()),
+                None => return Err(Error::Schema(format!("Row has no '{}' column", name))),
+            }
+        }
+        Ok(key)
+    }
+
+    // one past the largest rowid so far , rowids of deleted rows at the end get reused
+    fn next_rowid(&self) -> Result<Key, Error> {
+        let rowid = match self.rows.last_key()?.as_deref() {
+            Some([DataType::Int(last)]) => last
+                .checked_add(1)
+                .ok_or_else(|| Error::Execution("No rowid left for a new row".to_string()))?,
+            _ => 1,
+        };
+        Ok(vec![DataType::Int(rowid)])
+    }
+
+    fn matches(&self, clause: &Expr, row: &Row) -> Result<bool, Error> {
+        Ok(is_true(&eval(clause, &self.schema, row)?))
+    }
+
+    /*
+     *   The part of the rows or of an index with `columns` that `comparisons`
+     *   ask for: `column = <value>` (either way around) on its first columns
+     *   and then `<`, `<=`, `>` or `>=` on the next one.
+     */
+    fn range_on(
+        &self,
+        columns: &[String],
+        comparisons: &[(&String, BinaryOp, &Expr)],
+    ) -> IndexRange {
+        let mut range = IndexRange::default();
+        for name in columns {
+            let on_column = comparisons.iter().filter(|(column, ..)| *column == name);
+            let mut equal = None;
+            for (_, op, value) in on_column {
+                let Some(value) = self.column_value(name, value) else {
+                    continue;
+                };
+                match op {
+                    BinaryOp::Eq => equal = Some(value),
+                    BinaryOp::Gt | BinaryOp::GtEq if range.lower.is_none() => {
+                        range.lower = Some((value, *op == BinaryOp::GtEq))
+                    }
+                    BinaryOp::Lt | BinaryOp::LtEq if range.upper.is_none() => {
+                        range.upper = Some((value, *op == BinaryOp::LtEq))
+                    }
+                    _ => {}
+                }
+            }
+            // bounds only help on the column right after the equal ones
+            match equal {
+                Some(value) => {
+                    range.equal.push(value);
+                    range.lower = None;
+                    range.upper = None;
+                }
+                None => break,
+            }
+        }
+        range
+    }
+
+    // the index that narrows the rows down the most , None when none of them helps
+    fn index_lookup(
+        &self,
+        comparisons: &[(&String, BinaryOp, &Expr)],
+    ) -> Option<(usize, IndexRange)> {
+        let mut best: Option<(usize, IndexRange)> = None;
+        for (i, index) in self.schema.indexes.iter().enumerate() {
+            let range = self.range_on(&index.columns, comparisons);
+            if range.score() > best.as_ref().map_or(0, |(_, best)| best.score()) {
+                best = Some((i, range));
+            }
+        }
+        best
+    }
+
+    // the value `expr` would have stored in the column , `day = '2024-01-01'` is a DATE
+    fn column_value(&self, name: &str, expr: &Expr) -> Option<DataType> {
+        let value = eval_constant(expr).ok()?;
+        Schema::validate_column(self.schema.get_column(name)?, value)
+    }
+
+    /*
+     *   The `column <op> <constant>` parts of a chain of ANDs for = , < , <= ,
+     *   > and >= , turned around so the column is on the left. A BETWEEN is
+     *   its >= and <=.
+     */
+    fn collect_comparisons<'a>(
+        clause: &'a Expr,
+        result: &mut Vec<(&'a String, BinaryOp, &'a Expr)>,
+    ) {
+        match clause {
+            Expr::Nested(clause) => Self::collect_comparisons(clause, result),
+            Expr::Binary {
+                left,
+                op: BinaryOp::And,
+                right,
+            } => {
+                Self::collect_comparisons(left, result);
+                Self::collect_comparisons(right, result);
+            }
+            Expr::Between {
+                expr,
+                negated: false,
+                low,
+                high,
+            } => {
+                if let Expr::Ident(name) = &**expr {
+                    if is_constant(low) && is_constant(high) {
+                        result.push((name, BinaryOp::GtEq, low));
+                        result.push((name, BinaryOp::LtEq, high));
+                    }
+                }
+            }
+            Expr::Binary { left, op, right } => {
+                let flipped = match op {
+                    BinaryOp::Eq => BinaryOp::Eq,
+                    BinaryOp::Lt => BinaryOp::Gt,
+                    BinaryOp::LtEq => BinaryOp::GtEq,
+                    BinaryOp::Gt => BinaryOp::Lt,
+                    BinaryOp::GtEq => BinaryOp::LtEq,
+                    _ => return,
+                };
+                match (&**left, &**right) {
+                    (Expr::Ident(name), value) if is_constant(value) => {
+                        result.push((name, *op, value))
+                    }
+                    (value, Expr::Ident(name)) if is_constant(value) => {
+                        result.push((name, flipped, value))
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /*
+     *   The rows that can match `clause` , in key order. One lookup when the
+     *   clause pins down the primary key , else the part of the rows or of an
+     *   index it asks for , whichever is narrower , and a full table scan when
+     *   neither helps.
+     */
+    fn candidate_rows(
+        &self,
+        clause: Option<&Expr>,
+        descending: bool,
+    ) -> Result<RowScan<'_>, Error> {
+        let mut comparisons = Vec::new();
+        if let Some(clause) = clause {
+            Self::collect_comparisons(clause, &mut comparisons);
+        }
+        let key_range = self.range_on(&self.schema.primary_key, &comparisons);
+        if !key_range.equal.is_empty() && key_range.equal.len() == self.schema.primary_key.len() {
+            let key = key_range.equal;
+            let found: Vec<_> = self
+                .rows
+                .search(&key)?
+                .map(|row| (key, row))
+                .into_iter()
+                .collect();
+            return Ok(RowScan::Found(found.into_iter()));
+        }
+        // reading the rows in a range (or all of them) beats going through an index that is not narrower
+        let (i, range) = match self.index_lookup(&comparisons) {
+            Some((i, range)) if range.score() > key_range.score() => (i, range),
+            _ => {
+                let mut cursor = self.rows.cursor()?;
+                if key_range.score() == 0 {
+                    if descending {
+                        cursor.move_to_end()?;
+                    }
+                } else if descending {
+                    // before the first key after the range
+                    cursor.seek_by(|key| match key_range.cmp(key) {
+                        Ordering::Greater => Ordering::Greater,
+                        _ => Ordering::Less,
+                    })?;
+                } else {
+                    cursor.seek_by(|key| key_range.cmp(key))?;
+                }
+                return Ok(RowScan::Range {
+                    cursor,
+                    range: key_range,
+                    descending,
+                });
+            }
+        };
+        let mut keys: Vec<Key> = self.indexes[i]
+            .entries_in_range(|index_key| range.cmp(index_key))?
+            .into_iter()
+            .map(|(_, key)| key)
+            .collect();
+        // same order as a table scan
+        keys.sort();
+        if descending {
+            keys.reverse();
+        }
+        let mut found = Vec::with_capacity(keys.len());
+        for key in keys {
+            if let Some(row) = self.rows.search(&key)? {
+                found.push((key, row));
+            }
+        }
+        Ok(RowScan::Found(found.into_iter()))
+    }
+
+    // (key , row) of every row matching `clause` in key order
+    fn find_rows(&self, clause: Option<&Expr>) -> Result<Vec<(Key, Row)>, Error> {
+        self.scan_rows(clause, false)?.collect()
+    }
+
+    // like find_rows , but the rows are read as they are needed
+    fn scan_rows<'a>(
+        &'a self,
+        clause: Option<&'a Expr>,
+        descending: bool,
+    ) -> Result<impl Iterator<Item = Result<(Key, Row), Error>> + 'a, Error> {
+        let candidates = self.candidate_rows(clause, descending)?;
+        // the rest of the clause still has to hold after a lookup
+        Ok(candidates.filter_map(move |entry| {
+            let keep = match (&entry, clause) {
+                (Ok((_, row)), Some(clause)) => self.matches(clause, row),
+                _ => Ok(true),
+            };
+            match keep {
+                Ok(true) => Some(entry),
+                Ok(false) => None,
+                Err(error) => Some(Err(error)),
+            }
+        }))
+    }
+
+    // the rows whose `columns` hold `values` , straight from the BTree when they are the key
+    fn rows_matching(
+        &self,
+        columns: &[String],
+        values: &[DataType],
+    ) -> Result<Vec<(Key, Row)>, Error> {
+        let clause = columns
+            .iter()
+            .zip(values)
+            .map(|(column, value)| Expr::Binary {
+                left: Box::new(Expr::Ident(column.clone())),
+                op: BinaryOp::Eq,
+                right: Box::new(literal(value)),
+            })
+            .reduce(|left, right| Expr::Binary {
+                left: Box::new(left),
+                op: BinaryOp::And,
+                right: Box::new(right),
+            });
+        self.find_rows(clause.as_ref())
+    }
+
+    /*
+     *   Whether ORDER BY is the order of the keys (the first key columns , all
+     *   the same way) , Some(descending) when it is. Rows can then be read in
+     *   that order instead of being sorted. No ORDER BY is key order too.
+     */
+    fn key_order(&self, order_by: &[(Expr, bool)]) -> Option<bool> {
+        let Some((_, descending)) = order_by.first() else {
+            return Some(false);
+        };
+        let by_key = order_by.len() <= self.schema.primary_key.len()
+            && order_by
+                .iter()
+                .zip(&self.schema.primary_key)
+                .all(|((expr, desc), name)| {
+                    matches!(expr, Expr::Ident(column) if column == name) && desc == descending
+                });
+        by_key.then_some(*descending)
+    }
+
+    // rows in key order sorted by the ORDER BY terms , rows that tie stay in key order
+    fn sort_rows(
+        &self,
+        rows: Vec<(Key, Row)>,
+        order_by: &[(Expr, bool)],
+    ) -> Result<Vec<Row>, Error> {
+        let mut sorted = Vec::with_capacity(rows.len());
+        for (_, row) in rows {
+            let mut values = Vec::with_capacity(order_by.len());
+            for (expr, _) in order_by {
+                values.push(eval(expr, &self.schema, &row)?);
+            }
+            sorted.push((values, row));
+        }
+        sorted.sort_by(|(a, _), (b, _)| {
+            for ((a, b), (_, desc)) in a.iter().zip(b).zip(order_by) {
+                let ordering = if *desc { b.cmp(a) } else { a.cmp(b) };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+        Ok(sorted.into_iter().map(|(_, row)| row).collect())
+    }
+
+    // the values of the select list for one row
+    fn select_values(&self, items: &[SelectItem], row: &Row) -> Result<Row, Error> {
+        let mut values = Vec::with_capacity(items.len());
+        for item in items {
+            match item {
+                SelectItem::Wildcard => values.extend(row.iter().cloned()),
+                SelectItem::Expr { expr, .. } => values.push(eval(expr, &self.schema, row)?),
+            }
+        }
+        Ok(values)
+    }
+
+    pub fn select_rows(&self, query: &SelectQuery) -> Result<Rows, Error> {
+        let schema_columns = self.schema.column_names();
+        let mut columns = Vec::new();
+        for item in &query.columns {
+            match item {
+                SelectItem::Wildcard => columns.extend(schema_columns.iter().cloned()),
+                SelectItem::Expr { expr, alias } => {
+                    columns.push(alias.clone().unwrap_or_else(|| expr.to_string()))
+                }
+            }
+        }
+
+        let clause = query.where_clause.as_ref();
+        let mut result = Vec::new();
+        match self.key_order(&query.order_by) {
+            // straight from the cursor , one row at a time
+            Some(descending) => {
+                for entry in self.scan_rows(clause, descending)? {
+                    let (_, row) = entry?;
+                    result.push(self.select_values(&query.columns, &row)?);
+                }
+            }
+            None => {
+                let rows = self.sort_rows(self.find_rows(clause)?, &query.order_by)?;
+                for row in rows {
+                    result.push(self.select_values(&query.columns, &row)?);
+                }
+            }
+        }
+        Ok(Rows::new(columns, result))
+    }
+}
+
+#[derive(Debug)]
+struct Savepoint {
+    name: String,
+    // same deal as Transaction::snapshot
+    snapshot: Option<HashMap<String, Table>>,
+}
+
+#[derive(Debug)]
+struct Transaction {
+    // the tables as they were at BEGIN , only kept for in-memory databases ,
+    // file backed ones roll back through the pager instead. This is a copy of
+    // every table , so BEGIN (and every SAVEPOINT) costs as much as the whole
+    // database , no matter how little the transaction changes
+    snapshot: Option<HashMap<String, Table>>,
+    // innermost last , the index of a savepoint is also its pager savepoint depth
+    savepoints: Vec<Savepoint>,
+    // started by SAVEPOINT instead of BEGIN , releasing the outermost savepoint commits it
+    implicit: bool,
+}
+
+impl Transaction {
+    fn find_savepoint(&self, name: &str) -> Result<usize, Error> {
+        self.savepoints
+            .iter()
+            .rposition(|sp| sp.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::NotFound(format!("No such savepoint: {}", name)))
+    }
+}
+
+#[derive(Debug)]
+pub struct Neoqlite {
+    tables: HashMap<String, Table>,
+    debug: bool,
+    // None for in-memory databases
+    pager: Option<Rc<RefCell<Pager>>>,
+    // None means autocommit , every statement is its own transaction
+    transaction: Option<Transaction>,
+    // whether FOREIGN KEY constraints are enforced , see PRAGMA foreign_keys
+    foreign_keys: bool,
+    // a schema or a root page changed since the catalog was last written
+    catalog_dirty: bool,
+}
+
+impl Default for Neoqlite {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Neoqlite {
+    pub fn new() -> Self {
+        let mut tables = HashMap::new();
+        let mut user_table = Table::new();
+        user_table
+            .schema
+            .add_column("id".to_string(), ColumnType::Int, vec![]);
+        user_table
+            .schema
+            .add_column("username".to_string(), ColumnType::String, vec![]);
+        user_table
+            .schema
+            .add_column("email".to_string(), ColumnType::String, vec![]);
+
+        user_table
+            .schema
+            .add_column("otp".to_string(), ColumnType::Int, vec![]);
+
+        user_table
+            .schema
+            .set_primary_key(vec!["id".to_string()])
+            .expect("users has an id column");
+
+        let mut dummy_table = Table::new();
+        dummy_table
+            .schema
+            .add_column("id".to_string(), ColumnType::Int, vec![]);
+        dummy_table
+            .schema
+            .add_column("username".to_string(), ColumnType::String, vec![]);
+        dummy_table
+            .schema
+            .add_column("email".to_string(), ColumnType::String, vec![]);
+
+        dummy_table
+            .schema
+            .set_primary_key(vec!["id".to_string()])
+            .expect("dummy has an id column");
+
+        tables.insert("users".to_string(), user_table);
+        tables.insert("dummy".to_string(), dummy_table);
+
+        let debug = false;
+        Self {
+            tables,
+            debug,
+            pager: None,
+            transaction: None,
+            foreign_keys: true,
+            catalog_dirty: false,
+        }
+    }
+
+    /*
+     *   Opens (or creates) a file backed database.
+     *
+     *   The schema lives in a page chain pointed to by the file header , it is a
+     *   list of (table name , schema , root page of the rows BTree , root pages
+     *   of the index BTrees).
+     */
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let pager = Rc::new(RefCell::new(Pager::open(path)?));
+        let tables = Self::load_catalog(&pager)?;
+
+        Ok(Self {
+            tables,
+            debug: false,
+            pager: Some(pager),
+            transaction: None,
+            foreign_keys: true,
+            catalog_dirty: false,
+        })
+    }
+
+    fn load_catalog(pager: &Rc<RefCell<Pager>>) -> Result<HashMap<String, Table>, Error> {
+        let mut tables = HashMap::new();
+        let schema_root = pager.borrow().schema_root();
+        if schema_root != 0 {
+            let catalog: Vec<CatalogEntry> =
+                decode_from_slice(&pager.borrow_mut().read_chain(schema_root)?)?;
+            for (name, (schema, (root, index_roots))) in catalog {
+                let table = Table::paged(schema, Rc::clone(pager), root, &index_roots);
+                tables.insert(name, table);
+            }
+        }
+        Ok(tables)
+    }
+
+    fn new_table(&self) -> Table {
+        match &self.pager {
+            Some(pager) => Table::paged(Schema::new(), Rc::clone(pager), 0, &[]),
+            None => Table::new(),
+        }
+    }
+
+    /*
+     *   The rows are already in the pager , so we only have to write the schema
+     *   when it changed (new tables , roots that moved). Most statements change
+     *   neither , `catalog_dirty` saves them reading the whole catalog back.
+     *   Does nothing for in-memory databases.
+     */
+    fn write_catalog(&mut self) -> Result<(), Error> {
+        if !std::mem::take(&mut self.catalog_dirty) {
+            return Ok(());
+        }
+        let mut pager = match &self.pager {
+            Some(pager) => pager.borrow_mut(),
+            None => return Ok(()),
+        };
+
+        let mut catalog: Vec<(&String, &Table)> = self.tables.iter().collect();
+        catalog.sort_by_key(|(name, _)| *name);
+        let mut buf = Vec::new();
+        (catalog.len() as u32).encode(&mut buf);
+        for (name, table) in catalog {
+            name.encode(&mut buf);
+            table.schema.encode(&mut buf);
+            table.rows.root_page().encode(&mut buf);
+            let index_roots: Vec<PageNum> = table.indexes.iter().map(RowStore::root_page).collect();
+            index_roots.encode(&mut buf);
+        }
+
+        let root = pager.schema_root();
+        if root != 0 && pager.read_chain(root)? == buf {
+            return Ok(());
+        }
+        let root = pager.write_chain((root != 0).then_some(root), &buf)?;
+        pager.set_schema_root(root);
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), Error> {
+        self.write_catalog()?;
+        if let Some(pager) = &self.pager {
+            pager.borrow_mut().commit()?;
+        }
+        Ok(())
+    }
+
+    /*
+     *   Goes back to the state of the last commit , `snapshot` is what the
+     *   tables looked like back then for in-memory databases.
+     */
+    fn rollback(&mut self, snapshot: Option<HashMap<String, Table>>) -> Result<(), Error> {
+        match &self.pager {
+            Some(pager) => {
+                pager.borrow_mut().rollback();
+                self.tables = Self::load_catalog(pager)?;
+                self.catalog_dirty = false;
+            }
+            None => {
+                if let Some(tables) = snapshot {
+                    self.tables = tables;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     *   Goes back to pager savepoint `depth` , the tables of a file backed
+     *   database are reloaded since their roots may have moved.
+     */
+    fn rollback_pager_to(&mut self, depth: usize) -> Result<(), Error> {
+        if let Some(pager) = &self.pager {
+            pager.borrow_mut().rollback_to(depth);
+            self.tables = Self::load_catalog(pager)?;
+            self.catalog_dirty = false;
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Option<HashMap<String, Table>> {
+        self.pager.is_none().then(|| self.tables.clone())
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.pager.is_some()
+    }
+
+    //  GOOD OLD OOP HUH
+    pub fn set_debug(&mut self, value: bool) {
+        self.debug = value;
+    }
+
+    pub fn exec_meta_command(&self, input: &str) {
+        match input {
+            ".exit" => exit(0),
+            ".bt" => {
+                for (table_name, table) in &self.tables {
+                    println!("Table : {}", table_name);
+                    println!("\n{:?}\n", table);
+                }
+            }
+            _ => println!("Unknown Meta Command"),
+        }
+    }
+
+    fn table(&self, name: &str) -> Result<&Table, Error> {
+        self.tables
+            .get(name)
+            .ok_or_else(|| Error::NotFound("Table not found".to_string()))
+    }
+
+    fn table_mut(&mut self, name: &str) -> Result<&mut Table, Error> {
+        self.tables
+            .get_mut(name)
+            .ok_or_else(|| Error::NotFound("Table not found".to_string()))
+    }
+
+    // (child table , foreign key) for every foreign key that points at `parent`
+    fn references_to(&self, parent: &str) -> Vec<(String, ForeignKey)> {
+        let mut result = Vec::new();
+        for (name, table) in &self.tables {
+            for fk in &table.schema.foreign_keys {
+                if fk.reference.table == parent {
+                    result.push((name.clone(), fk.clone()));
+                }
+            }
+        }
+        result
+    }
+
+    // whether a change to `table` has foreign keys to look after , in either direction
+    fn has_foreign_keys(&self, table: &str) -> bool {
+        self.foreign_keys
+            && self.tables.iter().any(|(name, t)| {
+                t.schema
+                    .foreign_keys
+                    .iter()
+                    .any(|fk| name == table || fk.reference.table == table)
+            })
+    }
+
+    // the parent columns a foreign key points at , its primary key when the key names none
+    fn parent_columns(&self, fk: &ForeignKey) -> Result<Vec<String>, Error> {
+        let parent = self
+            .tables
+            .get(&fk.reference.table)
+            .ok_or_else(|| Error::NotFound(format!("No such table: {}", fk.reference.table)))?;
+        let columns = if fk.reference.columns.is_empty() {
+            parent.schema.primary_key.clone()
+        } else {
+            fk.reference.columns.clone()
+        };
+        let is_key = !columns.is_empty()
+            && (columns == parent.schema.primary_key
+                || parent
+                    .schema
+                    .indexes
+                    .iter()
+                    .any(|index| index.unique && index.columns == columns));
+        if !is_key || columns.len() != fk.columns.len() {
+            return Err(Error::Schema(format!(
+                "Foreign key mismatch , {}({}) is not a PRIMARY KEY or UNIQUE",
+                fk.reference.table,
+                columns.join(", ")
+            )));
+        }
+        Ok(columns)
+    }
+
+    /*
+     *   Foreign key actions change other tables as they go , so a statement
+     *   that has them cant check everything before touching a row. In memory
+     *   it runs on top of a copy of the tables to go back to , file backed
+     *   databases roll back through the pager anyway.
+     *
+     *   That copy is of every table , not only the ones the statement touches ,
+     *   so with foreign keys on each INSERT , UPDATE and DELETE of an in-memory
+     *   table that has them is as slow as the database is big.
+     */
+    fn with_undo<T>(
+        &mut self,
+        table: &str,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let snapshot = if self.has_foreign_keys(table) {
+            self.snapshot()
+        } else {
+            None
+        };
+        let result = f(self);
+        if let (Err(_), Some(tables)) = (&result, snapshot) {
+            self.tables = tables;
+        }
+        result
+    }
+
+    // every row of `table` in `rows` has to point at a parent row that exists
+    fn check_references(&self, table: &str, rows: &[Row]) -> Result<(), Error> {
+        if !self.foreign_keys {
+            return Ok(());
+        }
+        let schema = &self.table(table)?.schema;
+        for fk in &schema.foreign_keys {
+            for row in rows {
+                // a NULL points at nothing and doesnt have to
+                let Some(values) = schema.values_of(&fk.columns, row) else {
+                    continue;
+                };
+                let parent_columns = self.parent_columns(fk)?;
+                let parent = self.table(&fk.reference.table)?;
+                if parent.rows_matching(&parent_columns, &values)?.is_empty() {
+                    return Err(fk.error(table));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // the values `old` had in `columns` , None if they stay the same in `new` or had a NULL
+    fn changed_values(
+        schema: &Schema,
+        columns: &[String],
+        old: &Row,
+        new: Option<&Row>,
+    ) -> Option<Key> {
+        let values = schema.values_of(columns, old)?;
+        let kept = new.and_then(|new| schema.values_of(columns, new)).as_ref() == Some(&values);
+        (!kept).then_some(values)
+    }
+
+    /*
+     *   RESTRICT fails before anything changes. `changes` are (row , new row)
+     *   of `parent` , no new row for rows that get deleted.
+     */
+    fn check_restrict(&self, parent: &str, changes: &[(Row, Option<Row>)]) -> Result<(), Error> {
+        if !self.foreign_keys {
+            return Ok(());
+        }
+        for (child, fk) in self.references_to(parent) {
+            let parent_columns = self.parent_columns(&fk)?;
+            let schema = &self.table(parent)?.schema;
+            for (old, new) in changes {
+                let action = match new {
+                    Some(_) => fk.reference.on_update,
+                    None => fk.reference.on_delete,
+                };
+                if action != ReferentialAction::Restrict {
+                    continue;
+                }
+                let Some(values) = Self::changed_values(schema, &parent_columns, old, new.as_ref())
+                else {
+                    continue;
+                };
+                if !self
+                    .table(&child)?
+                    .rows_matching(&fk.columns, &values)?
+                    .is_empty()
+                {
+                    return Err(fk.error(&child));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     *   Runs the ON DELETE / ON UPDATE action of every foreign key pointing at
+     *   `parent` , once the rows in `changes` (same as check_restrict) changed.
+     *   Rows that CASCADE or SET NULL change go through this again for the
+     *   tables pointing at them.
+     */
+    fn apply_actions(
+        &mut self,
+        parent: &str,
+        changes: Vec<(Row, Option<Row>)>,
+    ) -> Result<(), Error> {
+        if !self.foreign_keys || changes.is_empty() {
+            return Ok(());
+        }
+        for (child, fk) in self.references_to(parent) {
+            let parent_columns = self.parent_columns(&fk)?;
+            let parent_table = self.table(parent)?;
+            let child_table = self.table(&child)?;
+
+            let mut removed = Vec::new();
+            let mut replaced = Vec::new();
+            for (old, new) in &changes {
+                let Some(values) =
+                    Self::changed_values(&parent_table.schema, &parent_columns, old, new.as_ref())
+                else {
+                    continue;
+                };
+                let dependents = child_table.rows_matching(&fk.columns, &values)?;
+                // another parent row has the values now , the children still point at something
+                if dependents.is_empty()
+                    || !parent_table
+                        .rows_matching(&parent_columns, &values)?
+                        .is_empty()
+                {
+                    continue;
+                }
+
+                let action = match new {
+                    Some(_) => fk.reference.on_update,
+                    None => fk.reference.on_delete,
+                };
+                match (action, new) {
+                    (ReferentialAction::NoAction | ReferentialAction::Restrict, _) => {
+                        return Err(fk.error(&child))
+                    }
+                    (ReferentialAction::Cascade, None) => removed.extend(dependents),
+                    (ReferentialAction::Cascade | ReferentialAction::SetNull, new) => {
+                        for (key, row) in dependents {
+                            let mut new_row = row.clone();
+                            for (column, parent_column) in fk.columns.iter().zip(&parent_columns) {
+                                let index = child_table.schema.column_index(column).unwrap();
+                                let value = match (action, new) {
+                                    (ReferentialAction::Cascade, Some(new)) => new
+                                        [parent_table.schema.column_index(parent_column).unwrap()]
+                                    .clone(),
+                                    _ => DataType::Null,
+                                };
+                                let schema_col = &child_table.schema.columns[index];
+                                new_row[index] = Schema::validate_column(schema_col, value.clone())
+                                    .ok_or_else(|| {
+                                        Error::Schema(format!(
+                                            "Invalid type for column {} expected {:?} got {}",
+                                            column, schema_col.column_type, value
+                                        ))
+                                    })?;
+                            }
+                            replaced.push((key, row, new_row));
+                        }
+                    }
+                }
+            }
+
+            let table = self.table_mut(&child)?;
+            table.remove_rows(&removed)?;
+            table.replace_rows(&replaced)?;
+            let mut child_changes: Vec<(Row, Option<Row>)> =
+                removed.into_iter().map(|(_, row)| (row, None)).collect();
+            child_changes.extend(
+                replaced
+                    .into_iter()
+                    .map(|(_, row, new_row)| (row, Some(new_row))),
+            );
+            self.apply_actions(&child, child_changes)?;
+        }
+        Ok(())
+    }
+
+    pub fn exec_insert(&mut self, query: InsertQuery) -> Result<(), Error> {
+        let table_name = query.table.clone();
+        // the row can point at itself , so it is checked once it is in
+        self.with_undo(&table_name, |db| {
+            let table = db.table_mut(&query.table)?;
+            if query.columns.len() != query.values.len() {
+                return Err(Error::Schema(
+                    "columns and values are not the same length".to_string(),
+                ));
+            }
+            let row = table.insert_row(query.columns, query.values)?;
+            db.check_references(&query.table, &[row])
+        })
+    }
+
+    pub fn exec_select(&self, query: SelectQuery) -> Result<Rows, Error> {
+        let table = self
+            .tables
+            .get(&query.table)
+            .ok_or_else(|| Error::NotFound("Table not found".to_string()))?;
+        table.select_rows(&query)
+    }
+
+    pub fn exec_delete(&mut self, query: DeleteQuery) -> Result<(), Error> {
+        let table_name = query.table.clone();
+        self.with_undo(&table_name, |db| {
+            let rows = db.table(&query.table)?.rows_to_delete(&query)?;
+            db.delete_rows(&query.table, rows)
+        })
+    }
+
+    // deletes rows found in `table` and runs the ON DELETE of what points at them
+    fn delete_rows(&mut self, table: &str, rows: Vec<(Key, Row)>) -> Result<(), Error> {
+        let changes: Vec<(Row, Option<Row>)> =
+            rows.iter().map(|(_, row)| (row.clone(), None)).collect();
+        self.check_restrict(table, &changes)?;
+        self.table_mut(table)?.remove_rows(&rows)?;
+        self.apply_actions(table, changes)
+    }
+
+    pub fn exec_update(&mut self, query: UpdateQuery) -> Result<(), Error> {
+        let table_name = query.table.clone();
+        self.with_undo(&table_name, |db| {
+            let changes = db.table(&query.table)?.plan_update(&query)?;
+            let pairs: Vec<(Row, Option<Row>)> = changes
+                .iter()
+                .map(|(_, row, new_row)| (row.clone(), Some(new_row.clone())))
+                .collect();
+            db.check_restrict(&query.table, &pairs)?;
+            db.table_mut(&query.table)?.replace_rows(&changes)?;
+            let new_rows: Vec<Row> = changes.into_iter().map(|(.., new_row)| new_row).collect();
+            db.check_references(&query.table, &new_rows)?;
+            db.apply_actions(&query.table, pairs)
+        })
+    }
+
+    pub fn exec_create_table(&mut self, query: CreateTableQuery) -> Result<(), Error> {
+        if self.tables.contains_key(&query.table) {
+            return Err(Error::Schema(format!(
+                "A Table with name '{}' exists",
+                query.table
+            )));
+        }
+
+        let mut new_table = self.new_table();
+
+        // either PRIMARY KEY on one column or a PRIMARY KEY (a , b) after them
+        let mut primary_keys: Vec<Vec<String>> = Vec::new();
+        let mut unique: Vec<Vec<String>> = Vec::new();
+        let mut foreign_keys: Vec<(Vec<String>, Reference)> = Vec::new();
+        let mut checks = Vec::new();
+        for (col, col_type, col_constraints) in query.columns {
+            let mut constraints = Vec::new();
+            for constraint in col_constraints {
+                match constraint {
+                    Constraints::PrimaryKey => primary_keys.push(vec![col.clone()]),
+                    Constraints::Unique => unique.push(vec![col.clone()]),
+                    // the reference and the check live in the schema with the table ones
+                    Constraints::ForeignKey(reference) => {
+                        foreign_keys.push((vec![col.clone()], reference));
+                        continue;
+                    }
+                    Constraints::Check(check) => {
+                        checks.push(check);
+                        continue;
+                    }
+                    _ => {}
+                }
+                constraints.push(constraint);
+            }
+            new_table.schema.add_column(col, col_type, constraints);
+        }
+        for constraint in query.constraints {
+            match constraint {
+                TableConstraint::PrimaryKey(columns) => primary_keys.push(columns),
+                TableConstraint::Unique(columns) => unique.push(columns),
+                TableConstraint::ForeignKey(columns, reference) => {
+                    foreign_keys.push((columns, reference))
+                }
+                TableConstraint::Check(check) => checks.push(check),
+            }
+        }
+
+        // a bad DEFAULT fails now and not on the first insert
+        for column in &new_table.schema.columns {
+            column.default_value()?;
+        }
+        for check in checks {
+            new_table.schema.add_check(check)?;
+        }
+
+        if primary_keys.len() > 1 {
+            return Err(Error::Schema(format!(
+                "Table {} has more than one primary key",
+                query.table
+            )));
+        }
+        if let Some(primary_key) = primary_keys.pop() {
+            new_table.schema.set_primary_key(primary_key)?;
+        }
+
+        // the primary key is unique already , it is what the rows are stored under
+        for columns in unique {
+            let covered = columns == new_table.schema.primary_key
+                || new_table
+                    .schema
+                    .indexes
+                    .iter()
+                    .any(|i| i.columns == columns);
+            if !covered {
+                let name = format!(
+                    "autoindex_{}_{}",
+                    query.table,
+                    new_table.schema.indexes.len() + 1
+                );
+                new_table.add_index(Index::new(&name, columns, true))?;
+            }
         }
 
-        let mut did_have_id = false;
+        // the parent table doesnt have to exist yet , it is looked up when rows change
+        for (columns, reference) in foreign_keys {
+            new_table.schema.add_foreign_key(columns, reference)?;
+        }
 
-        let mut new_table = Table::new();
+        self.tables.insert(query.table, new_table);
+        Ok(())
+    }
 
-        for (col, col_type, col_constraints) in &query.columns {
-            if col == "id" {
-                if *col_type != ColumnType::Int {
-                    return Err(format!("Expected 'id' to be an int"));
-                } else {
-                    did_have_id = true;
+    pub fn exec_drop_table(&mut self, query: DropTableQuery) -> Result<(), Error> {
+        // the rows go first like in a DELETE , so the rows pointing at them get their ON DELETE
+        if self.tables.contains_key(&query.table) && self.has_foreign_keys(&query.table) {
+            self.with_undo(&query.table, |db| {
+                let rows = db.table(&query.table)?.find_rows(None)?;
+                db.delete_rows(&query.table, rows)
+            })?;
+        }
+
+        match self.tables.remove(&query.table) {
+            Some(mut table) => {
+                for index in &mut table.indexes {
+                    index.clear()?;
                 }
+                table.rows.clear()
             }
+            None if query.if_exists => Ok(()),
+            None => Err(Error::NotFound("Table not found".to_string())),
+        }
+    }
 
-            new_table
-                .schema
-                .add_column(col.clone(), col_type.clone(), col_constraints.clone());
+    // index names are shared by all tables , like table names
+    fn table_of_index(&self, name: &str) -> Option<String> {
+        self.tables
+            .iter()
+            .find(|(_, table)| table.schema.indexes.iter().any(|index| index.name == name))
+            .map(|(table, _)| table.clone())
+    }
+
+    pub fn exec_create_index(&mut self, query: CreateIndexQuery) -> Result<(), Error> {
+        if self.table_of_index(&query.name).is_some() {
+            if query.if_not_exists {
+                return Ok(());
+            }
+            return Err(Error::Schema(format!(
+                "An Index with name '{}' exists",
+                query.name
+            )));
         }
+        // the names the indexes of UNIQUE constraints get
+        if query.name.starts_with("autoindex_") {
+            return Err(Error::Schema(format!(
+                "Index name {} is reserved",
+                query.name
+            )));
+        }
+        let index = Index::new(&query.name, query.columns, query.unique);
+        self.table_mut(&query.table)?.add_index(index)
+    }
 
-        if !did_have_id {
-            return Err(format!("Expected an 'id' column"));
+    pub fn exec_drop_index(&mut self, query: DropIndexQuery) -> Result<(), Error> {
+        let table = match self.table_of_index(&query.name) {
+            Some(table) => table,
+            None if query.if_exists => return Ok(()),
+            None => return Err(Error::NotFound(format!("No such index: {}", query.name))),
+        };
+        if query.name.starts_with("autoindex_") {
+            return Err(Error::Schema(format!(
+                "Cannot drop index {} , it belongs to a UNIQUE constraint",
+                query.name
+            )));
         }
+        self.table_mut(&table)?.drop_index(&query.name)
+    }
 
-        self.tables.insert(query.table, new_table);
+    pub fn exec_alter_table(&mut self, query: AlterTableQuery) -> Result<(), Error> {
+        if let AlterTableAction::RenameTo(new_name) = &query.action {
+            if self.tables.contains_key(new_name) {
+                return Err(Error::Schema(format!(
+                    "A Table with name '{}' exists",
+                    new_name
+                )));
+            }
+            let table = self
+                .tables
+                .remove(&query.table)
+                .ok_or_else(|| Error::NotFound("Table not found".to_string()))?;
+            self.tables.insert(new_name.clone(), table);
+            for fk in self.foreign_keys_to(&query.table) {
+                fk.reference.table = new_name.clone();
+            }
+            return Ok(());
+        }
+
+        match query.action {
+            AlterTableAction::AddColumn(name, column_type, constraints) => self
+                .table_mut(&query.table)?
+                .add_column(name, column_type, constraints),
+            AlterTableAction::DropColumn(name) => {
+                if let Some((child, _)) = self
+                    .references_to(&query.table)
+                    .iter()
+                    .find(|(_, fk)| fk.reference.columns.contains(&name))
+                {
+                    return Err(Error::Schema(format!(
+                        "Cannot drop column {} , a foreign key of {} points at it",
+                        name, child
+                    )));
+                }
+                self.table_mut(&query.table)?.drop_column(&name)
+            }
+            AlterTableAction::RenameColumn { from, to } => {
+                self.table_mut(&query.table)?.rename_column(&from, &to)?;
+                for fk in self.foreign_keys_to(&query.table) {
+                    for column in &mut fk.reference.columns {
+                        if *column == from {
+                            *column = to.clone();
+                        }
+                    }
+                }
+                Ok(())
+            }
+            AlterTableAction::RenameTo(_) => unreachable!(),
+        }
+    }
+
+    // the foreign keys pointing at `parent` , to follow it when it is renamed
+    fn foreign_keys_to<'a>(
+        &'a mut self,
+        parent: &'a str,
+    ) -> impl Iterator<Item = &'a mut ForeignKey> {
+        self.tables
+            .values_mut()
+            .flat_map(|table| &mut table.schema.foreign_keys)
+            .filter(move |fk| fk.reference.table == parent)
+    }
+
+    /*
+     *   PRAGMA wal_checkpoint;           folds the WAL back into the database file
+     *   PRAGMA wal_autocheckpoint;       returns the current threshold
+     *   PRAGMA wal_autocheckpoint = N;   checkpoint once the WAL has N frames , 0 turns it off
+     *
+     *   Both are no-ops for in-memory databases since there is no WAL.
+     *
+     *   PRAGMA foreign_keys;             1 when FOREIGN KEY constraints are enforced
+     *   PRAGMA foreign_keys = ON | OFF;  on by default , not saved with the database
+     */
+    pub fn exec_pragma(&mut self, query: PragmaQuery) -> Result<Rows, Error> {
+        match query.name.to_lowercase().as_str() {
+            "foreign_keys" => match query.value {
+                None => {
+                    let enabled = self.foreign_keys as i64;
+                    return Ok(Rows::new(
+                        vec![query.name],
+                        vec![vec![DataType::Int(enabled)]],
+                    ));
+                }
+                Some(Expr::Boolean(value)) => self.foreign_keys = value,
+                Some(Expr::Number(value @ (0 | 1))) => self.foreign_keys = value == 1,
+                Some(Expr::Ident(ref word)) if word.eq_ignore_ascii_case("off") => {
+                    self.foreign_keys = false
+                }
+                Some(value) => {
+                    return Err(Error::Execution(format!(
+                        "foreign_keys expects ON or OFF, got {}",
+                        value
+                    )))
+                }
+            },
+            "wal_checkpoint" => {
+                if let Some(pager) = &self.pager {
+                    pager.borrow_mut().checkpoint()?;
+                }
+            }
+            "wal_autocheckpoint" => match (&self.pager, query.value) {
+                (Some(pager), Some(Expr::Number(n))) if n >= 0 => {
+                    pager.borrow_mut().set_wal_autocheckpoint(n as u64)
+                }
+                (Some(pager), None) => {
+                    let frames = pager.borrow().wal_autocheckpoint() as i64;
+                    return Ok(Rows::new(
+                        vec![query.name],
+                        vec![vec![DataType::Int(frames)]],
+                    ));
+                }
+                (None, _) => {}
+                (_, Some(value)) => {
+                    return Err(Error::Execution(format!(
+                        "wal_autocheckpoint expects a non-negative number, got {}",
+                        value
+                    )))
+                }
+            },
+            _ => return Err(Error::NotFound(format!("Unknown pragma {}", query.name))),
+        }
+        Ok(Rows::default())
+    }
+
+    pub fn exec_transaction(&mut self, stmt: TransactionStmt) -> Result<(), Error> {
+        match stmt {
+            TransactionStmt::Begin => {
+                if self.transaction.is_some() {
+                    return Err(Error::Execution(
+                        "Cannot start a transaction within a transaction".to_string(),
+                    ));
+                }
+                self.transaction = Some(Transaction {
+                    snapshot: self.snapshot(),
+                    savepoints: Vec::new(),
+                    implicit: false,
+                });
+            }
+            TransactionStmt::Commit => {
+                if self.transaction.is_none() {
+                    return Err(Error::Execution(
+                        "Cannot commit , no transaction is active".to_string(),
+                    ));
+                }
+                self.commit()?;
+                self.transaction = None;
+            }
+            TransactionStmt::Rollback => {
+                let transaction = self.transaction.take().ok_or_else(|| {
+                    Error::Execution("Cannot rollback , no transaction is active".to_string())
+                })?;
+                self.rollback(transaction.snapshot)?;
+            }
+            TransactionStmt::Savepoint(name) => {
+                let snapshot = self.snapshot();
+                let transaction = self.transaction.get_or_insert_with(|| Transaction {
+                    snapshot: snapshot.clone(),
+                    savepoints: Vec::new(),
+                    implicit: true,
+                });
+                transaction.savepoints.push(Savepoint { name, snapshot });
+                if let Some(pager) = &self.pager {
+                    pager.borrow_mut().savepoint();
+                }
+            }
+            TransactionStmt::Release(name) => {
+                let transaction = self
+                    .transaction
+                    .as_mut()
+                    .ok_or_else(|| Error::NotFound(format!("No such savepoint: {}", name)))?;
+                let idx = transaction.find_savepoint(&name)?;
+                if idx == 0 && transaction.implicit {
+                    self.commit()?;
+                    self.transaction = None;
+                } else {
+                    transaction.savepoints.truncate(idx);
+                    if let Some(pager) = &self.pager {
+                        pager.borrow_mut().release(idx);
+                    }
+                }
+            }
+            TransactionStmt::RollbackTo(name) => {
+                let transaction = self
+                    .transaction
+                    .as_mut()
+                    .ok_or_else(|| Error::NotFound(format!("No such savepoint: {}", name)))?;
+                let idx = transaction.find_savepoint(&name)?;
+                // the savepoint itself survives , so it can be rolled back to again
+                transaction.savepoints.truncate(idx + 1);
+                if let Some(tables) = &transaction.savepoints[idx].snapshot {
+                    self.tables = tables.clone();
+                }
+                self.rollback_pager_to(idx)?;
+            }
+        }
         Ok(())
     }
 
-    pub fn exec(&mut self, query: Query) -> Result<(), String> {
+    // same as query() for a statement that is already parsed
+    pub fn exec(&mut self, query: Query) -> Result<Rows, Error> {
         if self.debug {
             println!("\n\nParserResult:\n{:?}\n\n", query)
         };
+        if let Query::Transaction(stmt) = query {
+            self.exec_transaction(stmt)?;
+            return Ok(Rows::default());
+        }
+
+        // autocommit , the statement is its own transaction. In-memory statements
+        // check everything before touching a row so there is nothing to undo.
+        if self.transaction.is_none() {
+            return match self.exec_query(query) {
+                Ok(rows) => self.commit().map(|_| rows),
+                Err(e) => {
+                    self.rollback(None)?;
+                    Err(e)
+                }
+            };
+        }
+
+        // inside a transaction a failed statement only undoes itself
+        let depth = self
+            .pager
+            .as_ref()
+            .map(|pager| pager.borrow_mut().savepoint());
+        let result = self
+            .exec_query(query)
+            .and_then(|rows| self.write_catalog().map(|_| rows));
+        if let Some(depth) = depth {
+            if result.is_err() {
+                self.rollback_pager_to(depth)?;
+            }
+            if let Some(pager) = &self.pager {
+                pager.borrow_mut().release(depth);
+            }
+        }
+        result
+    }
+
+    fn exec_query(&mut self, query: Query) -> Result<Rows, Error> {
+        if matches!(
+            query,
+            Query::CreateTable(_)
+                | Query::DropTable(_)
+                | Query::AlterTable(_)
+                | Query::CreateIndex(_)
+                | Query::DropIndex(_)
+        ) {
+            self.catalog_dirty = true;
+        }
         match query {
-            Query::Insert(query) => self.exec_insert(query)?,
-            Query::Select(query) => self.exec_select(query)?,
-            Query::Delete(query) => self.exec_delete(query)?,
+            Query::Select(query) => return self.exec_select(query),
+            Query::Pragma(query) => return self.exec_pragma(query),
+            Query::Insert(query) => self.tracking_roots(|db| db.exec_insert(query))?,
+            Query::Delete(query) => self.tracking_roots(|db| db.exec_delete(query))?,
+            Query::Update(query) => self.tracking_roots(|db| db.exec_update(query))?,
             Query::CreateTable(query) => self.exec_create_table(query)?,
+            Query::DropTable(query) => self.exec_drop_table(query)?,
+            Query::AlterTable(query) => self.exec_alter_table(query)?,
+            Query::CreateIndex(query) => self.exec_create_index(query)?,
+            Query::DropIndex(query) => self.exec_drop_index(query)?,
+            Query::Transaction(stmt) => self.exec_transaction(stmt)?,
+        }
+        Ok(Rows::default())
+    }
+
+    // a root that splits or shrinks moves , the catalog has to follow it
+    fn tracking_roots(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let roots = self.root_pages();
+        f(self)?;
+        if self.root_pages() != roots {
+            self.catalog_dirty = true;
         }
         Ok(())
     }
 
-    pub fn exec_stmt(&mut self, input: &str) -> Result<(), String> {
+    fn root_pages(&self) -> Vec<PageNum> {
+        self.tables
+            .values()
+            .flat_map(|table| {
+                std::iter::once(&table.rows)
+                    .chain(&table.indexes)
+                    .map(RowStore::root_page)
+            })
+            .collect()
+    }
+
+    // runs one statement , SELECT gives its rows back , everything else an empty Rows
+    pub fn query(&mut self, sql: &str) -> Result<Rows, Error> {
+        let parser = Parser::new(Lexer::new(sql).tokenize()?).parse()?;
+        self.exec(parser)
+    }
+
+    // parses `sql` once , bind its parameters and run it with execute()
+    pub fn prepare(&self, sql: &str) -> Result<Statement, Error> {
+        let mut parser = Parser::new(Lexer::new(sql).tokenize()?);
+        let query = parser.parse()?;
+        Ok(Statement::new(query, parser.parameters().to_vec()))
+    }
+
+    pub fn execute(&mut self, stmt: &Statement) -> Result<Rows, Error> {
+        self.exec(stmt.bound_query()?)
+    }
+
+    pub fn exec_stmt(&mut self, input: &str) -> Result<(), Error> {
         if input.starts_with(".") {
             self.exec_meta_command(input);
         } else {
-            let parser = Parser::new(Lexer::new(input).tokenize()).parse()?;
-            self.exec(parser)?;
+            self.query(input)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::testing::temp_path;
+
+    // an in-memory database and a file backed one , everything should behave the same in both
+    fn both(name: &str) -> [Neoqlite; 2] {
+        [Neoqlite::new(), Neoqlite::open(temp_path(name)).unwrap()]
+    }
+
+    fn run(db: &mut Neoqlite, statements: &[&str]) {
+        for sql in statements {
+            db.query(sql)
+                .unwrap_or_else(|error| panic!("{} : {:?}", sql, error));
+        }
+    }
+
+    fn ints(db: &mut Neoqlite, sql: &str) -> Vec<i64> {
+        let rows = db
+            .query(sql)
+            .unwrap_or_else(|error| panic!("{} : {:?}", sql, error));
+        rows.iter()
+            .map(|row| match row.values()[0] {
+                DataType::Int(n) => n,
+                ref value => panic!("{} gave {:?}", sql, value),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn key_ranges_and_key_order_match_on_both_backends() {
+        for mut db in both("key-order") {
+            run(
+                &mut db,
+                &["create table t (id int primary key, score int);"],
+            );
+            for id in (0..300).rev() {
+                db.query(&format!(
+                    "insert into t (id, score) values ({}, {});",
+                    id,
+                    id % 7
+                ))
+                .unwrap();
+            }
+            let all: Vec<i64> = (0..300).collect();
+            assert_eq!(ints(&mut db, "select id from t;"), all);
+            let desc: Vec<i64> = all.iter().rev().copied().collect();
+            assert_eq!(ints(&mut db, "select id from t order by id desc;"), desc);
+            assert_eq!(
+                ints(
+                    &mut db,
+                    "select id from t where id between 40 and 45 order by id desc;"
+                ),
+                vec![45, 44, 43, 42, 41, 40]
+            );
+            assert_eq!(
+                ints(&mut db, "select id from t where id > 295 and score != 3;"),
+                vec![296, 298, 299]
+            );
+            assert_eq!(
+                ints(&mut db, "select id from t where id < 3 order by id desc;"),
+                vec![2, 1, 0]
+            );
+            assert!(ints(&mut db, "select id from t where id between 50 and 40;").is_empty());
+            assert_eq!(
+                ints(
+                    &mut db,
+                    "select id from t where id < 10 order by score desc, id;"
+                ),
+                vec![6, 5, 4, 3, 2, 9, 1, 8, 0, 7]
+            );
+        }
+    }
+
+    #[test]
+    fn tables_are_there_after_reopening() {
+        let path = temp_path("reopen");
+        let mut db = Neoqlite::open(&path).unwrap();
+        run(
+            &mut db,
+            &[
+                "create table t (id int primary key, score int);",
+                "create index t_score on t (score);",
+                "insert into t (id, score) values (1, 10);",
+                "insert into t (id, score) values (2, 20);",
+            ],
+        );
+        drop(db);
+
+        let mut db = Neoqlite::open(&path).unwrap();
+        assert_eq!(ints(&mut db, "select id from t where score = 20;"), vec![2]);
+        run(&mut db, &["insert into t (id, score) values (3, 30);"]);
+        // a crash , the pager never gets to checkpoint
+        std::mem::forget(db);
+
+        let mut db = Neoqlite::open(&path).unwrap();
+        assert_eq!(ints(&mut db, "select id from t;"), vec![1, 2, 3]);
+        assert_eq!(
+            ints(&mut db, "select score from t where score > 15;"),
+            vec![20, 30]
+        );
+    }
+
+    #[test]
+    fn a_failed_statement_in_a_transaction_only_undoes_itself() {
+        for mut db in both("failed-statement") {
+            run(
+                &mut db,
+                &[
+                    "create table parent (id int primary key);",
+                    "create table child (id int primary key, parent int references parent(id) on delete cascade, code int unique);",
+                    "create table pinned (id int primary key, child int references child(id) on delete restrict);",
+                    "insert into parent (id) values (1);",
+                    "insert into parent (id) values (2);",
+                    "insert into child (id, parent, code) values (10, 1, 100);",
+                    "insert into child (id, parent, code) values (20, 2, 200);",
+                    "insert into pinned (id, child) values (1, 20);",
+                    "begin;",
+                    "insert into parent (id) values (3);",
+                    "insert into child (id, parent, code) values (30, 3, 300);",
+                ],
+            );
+            let failing = [
+                "insert into parent (id) values (1);",
+                "insert into child (id, parent, code) values (40, 9, 400);",
+                "update child set code = 100 where id = 30;",
+                // the cascade gets to child 20 before pinned stops it
+                "delete from parent where id > 0;",
+            ];
+            for sql in failing {
+                assert!(db.query(sql).is_err(), "{}", sql);
+                assert!(db.in_transaction());
+            }
+            assert_eq!(ints(&mut db, "select id from parent;"), vec![1, 2, 3]);
+            assert_eq!(ints(&mut db, "select id from child;"), vec![10, 20, 30]);
+            assert_eq!(
+                ints(&mut db, "select code from child;"),
+                vec![100, 200, 300]
+            );
+
+            run(&mut db, &["delete from parent where id = 1;", "commit;"]);
+            assert_eq!(ints(&mut db, "select id from parent;"), vec![2, 3]);
+            assert_eq!(ints(&mut db, "select id from child;"), vec![20, 30]);
+
+            run(
+                &mut db,
+                &["begin;", "delete from child where id = 30;", "rollback;"],
+            );
+            assert_eq!(ints(&mut db, "select id from child;"), vec![20, 30]);
+        }
+    }
+
+    #[test]
+    fn savepoints_nest_and_roll_back_again() {
+        for mut db in both("savepoints") {
+            run(
+                &mut db,
+                &[
+                    "create table t (id int primary key);",
+                    "insert into t (id) values (1);",
+                    "begin;",
+                    "insert into t (id) values (2);",
+                    "savepoint a;",
+                    "insert into t (id) values (3);",
+                    "savepoint b;",
+                    "insert into t (id) values (4);",
+                    "rollback to b;",
+                ],
+            );
+            assert_eq!(ints(&mut db, "select id from t;"), vec![1, 2, 3]);
+
+            // b is still there after rolling back to it
+            run(
+                &mut db,
+                &["insert into t (id) values (5);", "rollback to b;"],
+            );
+            assert_eq!(ints(&mut db, "select id from t;"), vec![1, 2, 3]);
+
+            run(
+                &mut db,
+                &[
+                    "insert into t (id) values (6);",
+                    "release b;",
+                    "rollback to a;",
+                ],
+            );
+            assert_eq!(ints(&mut db, "select id from t;"), vec![1, 2]);
+            assert!(db.query("rollback to b;").is_err());
+            assert!(db.query("release nothing;").is_err());
+
+            run(
+                &mut db,
+                &["insert into t (id) values (7);", "release a;", "commit;"],
+            );
+            assert_eq!(ints(&mut db, "select id from t;"), vec![1, 2, 7]);
+            assert!(!db.in_transaction());
+        }
+    }
+
+    #[test]
+    fn a_savepoint_outside_a_transaction_starts_one() {
+        for mut db in both("implicit-savepoint") {
+            run(
+                &mut db,
+                &[
+                    "create table t (id int primary key);",
+                    "savepoint outer;",
+                    "insert into t (id) values (1);",
+                    "savepoint inner;",
+                    "insert into t (id) values (2);",
+                    "release inner;",
+                ],
+            );
+            assert!(db.in_transaction());
+            run(&mut db, &["release outer;"]);
+            assert!(!db.in_transaction());
+            assert_eq!(ints(&mut db, "select id from t;"), vec![1, 2]);
+
+            run(
+                &mut db,
+                &[
+                    "savepoint outer;",
+                    "delete from t where id > 0;",
+                    "rollback to outer;",
+                    "release outer;",
+                ],
+            );
+            assert_eq!(ints(&mut db, "select id from t;"), vec![1, 2]);
+        }
+    }
+
+    fn fails(db: &mut Neoqlite, sql: &str) -> Error {
+        match db.query(sql) {
+            Ok(_) => panic!("{} went through", sql),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn unique_columns_reject_a_second_row() {
+        for mut db in both("unique") {
+            run(
+                &mut db,
+                &[
+                    "create table t (id int primary key, code int unique, team text, nick text, unique (team, nick));",
+                    "insert into t (id, code, team, nick) values (1, 10, 'a', 'x');",
+                    "insert into t (id, code, team, nick) values (2, 20, 'a', 'y');",
+                    "insert into t (id, code, team, nick) values (3, NULL, 'b', 'x');",
+                    // NULLs never clash
+                    "insert into t (id, code, team, nick) values (4, NULL, 'b', NULL);",
+                    "insert into t (id, code, team, nick) values (5, NULL, 'b', NULL);",
+                ],
+            );
+            for sql in [
+                "insert into t (id, code, team, nick) values (6, 10, 'c', 'z');",
+                "insert into t (id, code, team, nick) values (6, 60, 'a', 'y');",
+                "update t set code = 20 where id = 1;",
+                "update t set code = 7 where id < 3;",
+                "update t set team = 'a' where id = 3;",
+            ] {
+                assert!(
+                    matches!(fails(&mut db, sql), Error::Constraint(_)),
+                    "{}",
+                    sql
+                );
+            }
+            assert_eq!(
+                ints(&mut db, "select code from t where id < 3;"),
+                vec![10, 20]
+            );
+
+            // a value is free again once its row is gone or has moved on
+            run(
+                &mut db,
+                &[
+                    "update t set code = 11 where id = 1;",
+                    "delete from t where id = 2;",
+                    "insert into t (id, code, team, nick) values (6, 10, 'a', 'y');",
+                    "insert into t (id, code, team, nick) values (7, 20, 'c', 'z');",
+                ],
+            );
+            assert_eq!(ints(&mut db, "select id from t where code = 10;"), vec![6]);
+
+            assert!(matches!(
+                fails(&mut db, "create unique index t_team on t (team);"),
+                Error::Constraint(_)
+            ));
+            run(&mut db, &["create unique index t_id_team on t (id, team);"]);
+        }
+    }
+
+    // the first column of every row , NULL as None
+    fn maybe_ints(db: &mut Neoqlite, sql: &str) -> Vec<Option<i64>> {
+        let rows = db
+            .query(sql)
+            .unwrap_or_else(|error| panic!("{} : {:?}", sql, error));
+        rows.iter()
+            .map(|row| match row.values()[0] {
+                DataType::Int(n) => Some(n),
+                DataType::Null => None,
+                ref value => panic!("{} gave {:?}", sql, value),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn foreign_key_actions() {
+        for mut db in both("foreign-keys") {
+            run(
+                &mut db,
+                &[
+                    "create table parent (id int primary key);",
+                    "create table cascade (id int primary key, parent int references parent(id) on delete cascade on update cascade);",
+                    "create table cleared (id int primary key, parent int references parent(id) on delete set null on update set null);",
+                    "create table strict (id int primary key, parent int references parent(id) on delete restrict);",
+                    "insert into parent (id) values (1);",
+                    "insert into parent (id) values (2);",
+                    "insert into parent (id) values (3);",
+                    "insert into cascade (id, parent) values (10, 1);",
+                    "insert into cascade (id, parent) values (11, 2);",
+                    "insert into cleared (id, parent) values (20, 1);",
+                    "insert into cleared (id, parent) values (21, 2);",
+                    "insert into strict (id, parent) values (30, 3);",
+                ],
+            );
+            for sql in [
+                "insert into cascade (id, parent) values (12, 9);",
+                "update cascade set parent = 9 where id = 10;",
+                "delete from parent where id = 3;",
+                "update parent set id = 4 where id = 3;",
+            ] {
+                assert!(
+                    matches!(fails(&mut db, sql), Error::Constraint(_)),
+                    "{}",
+                    sql
+                );
+            }
+
+            run(&mut db, &["update parent set id = 5 where id = 1;"]);
+            assert_eq!(ints(&mut db, "select parent from cascade;"), vec![5, 2]);
+            assert_eq!(
+                maybe_ints(&mut db, "select parent from cleared;"),
+                vec![None, Some(2)]
+            );
+
+            run(&mut db, &["delete from parent where id = 2;"]);
+            assert_eq!(ints(&mut db, "select id from cascade;"), vec![10]);
+            assert_eq!(
+                maybe_ints(&mut db, "select parent from cleared;"),
+                vec![None, None]
+            );
+            assert_eq!(ints(&mut db, "select id from parent;"), vec![3, 5]);
+
+            // a NULL points at nothing , and with enforcement off anything goes
+            run(
+                &mut db,
+                &[
+                    "insert into cascade (id, parent) values (12, NULL);",
+                    "pragma foreign_keys = off;",
+                    "insert into cascade (id, parent) values (13, 9);",
+                    "delete from parent where id = 3;",
+                ],
+            );
+            assert_eq!(ints(&mut db, "select id from strict;"), vec![30]);
+        }
+    }
+
+    #[test]
+    fn checks_and_defaults() {
+        for mut db in both("checks") {
+            run(
+                &mut db,
+                &[
+                    "create table wallets (id int primary key, balance int default 0 check (balance >= 0), bonus int default (2 * 5), note text, constraint sane_id check (id > 0));",
+                    "insert into wallets (id) values (1);",
+                    "insert into wallets (id, balance, bonus) values (2, 50, NULL);",
+                    // NULL passes a check
+                    "insert into wallets (id, balance) values (3, NULL);",
+                ],
+            );
+            assert_eq!(
+                maybe_ints(&mut db, "select balance from wallets;"),
+                vec![Some(0), Some(50), None]
+            );
+            assert_eq!(
+                maybe_ints(&mut db, "select bonus from wallets;"),
+                vec![Some(10), None, Some(10)]
+            );
+
+            match fails(&mut db, "insert into wallets (id) values (0);") {
+                Error::Constraint(message) => assert!(message.contains("sane_id"), "{}", message),
+                error => panic!("{:?}", error),
+            }
+            for sql in [
+                "insert into wallets (id, balance) values (4, -1);",
+                "update wallets set balance = balance - 10 where id < 3;",
+            ] {
+                assert!(
+                    matches!(fails(&mut db, sql), Error::Constraint(_)),
+                    "{}",
+                    sql
+                );
+            }
+            assert_eq!(
+                ints(&mut db, "select balance from wallets where id < 3;"),
+                vec![0, 50]
+            );
+
+            run(
+                &mut db,
+                &["update wallets set balance = balance - 10 where id = 2;"],
+            );
+            assert_eq!(
+                ints(&mut db, "select balance from wallets where id = 2;"),
+                vec![40]
+            );
+        }
+    }
+
+    #[test]
+    fn update_checks_and_rekeys_rows() {
+        for mut db in both("update") {
+            run(
+                &mut db,
+                &[
+                    "create table t (id int primary key, n int, name text notnull);",
+                    "insert into t (id, n, name) values (1, 10, 'a');",
+                    "insert into t (id, n, name) values (2, 20, 'b');",
+                    "insert into t (id, n, name) values (3, 30, 'c');",
+                ],
+            );
+            assert!(matches!(
+                fails(&mut db, "update t set n = 'many' where id = 1;"),
+                Error::Schema(_)
+            ));
+            assert!(matches!(
+                fails(&mut db, "update t set name = NULL where id = 1;"),
+                Error::Constraint(_)
+            ));
+            assert!(matches!(
+                fails(&mut db, "update t set id = 2 where id = 1;"),
+                Error::Constraint(_)
+            ));
+            assert_eq!(ints(&mut db, "select n from t;"), vec![10, 20, 30]);
+
+            // nothing matches , nothing changes and that is fine
+            run(&mut db, &["update t set n = 0 where id > 100;"]);
+            assert_eq!(ints(&mut db, "select n from t;"), vec![10, 20, 30]);
+
+            // a new key moves the row to its new place in key order
+            run(
+                &mut db,
+                &["update t set id = id + 100, n = n + 1 where id = 1;"],
+            );
+            assert_eq!(ints(&mut db, "select id from t;"), vec![2, 3, 101]);
+            assert_eq!(ints(&mut db, "select n from t where id = 101;"), vec![11]);
+            assert!(ints(&mut db, "select n from t where id = 1;").is_empty());
+
+            // every key moves up by one , the new keys only clash with the old ones
+            run(&mut db, &["update t set id = id + 1 where id > 0;"]);
+            assert_eq!(ints(&mut db, "select id from t;"), vec![3, 4, 102]);
+            assert_eq!(ints(&mut db, "select n from t;"), vec![20, 30, 11]);
+        }
+    }
+
+    // the file backed database opened again , an in-memory one as it is
+    fn reopened(db: Neoqlite, path: &Path) -> Neoqlite {
+        if !db.is_persistent() {
+            return db;
+        }
+        drop(db);
+        Neoqlite::open(path).unwrap()
+    }
+
+    fn columns(db: &mut Neoqlite, table: &str) -> Vec<String> {
+        db.query(&format!("select * from {};", table))
+            .unwrap()
+            .columns()
+            .to_vec()
+    }
+
+    #[test]
+    fn alter_table_changes_the_schema_for_good() {
+        let path = temp_path("alter");
+        for mut db in [Neoqlite::new(), Neoqlite::open(&path).unwrap()] {
+            run(
+                &mut db,
+                &[
+                    "create table t (id int primary key, name text);",
+                    "insert into t (id, name) values (1, 'a');",
+                    "insert into t (id, name) values (2, 'b');",
+                    "alter table t add column score int;",
+                    "alter table t add level int default 5;",
+                ],
+            );
+            let mut db = reopened(db, &path);
+            assert_eq!(columns(&mut db, "t"), ["id", "name", "score", "level"]);
+            assert_eq!(
+                maybe_ints(&mut db, "select score from t;"),
+                vec![None, None]
+            );
+            assert_eq!(ints(&mut db, "select level from t;"), vec![5, 5]);
+            run(
+                &mut db,
+                &["insert into t (id, name, score) values (3, 'c', 30);"],
+            );
+            assert_eq!(ints(&mut db, "select level from t where id = 3;"), vec![5]);
+            assert!(matches!(
+                fails(&mut db, "alter table t add column name int;"),
+                Error::Schema(_)
+            ));
+
+            run(
+                &mut db,
+                &[
+                    "alter table t rename column name to title;",
+                    "alter table t drop column score;",
+                    "alter table t rename to u;",
+                ],
+            );
+            let mut db = reopened(db, &path);
+            assert_eq!(columns(&mut db, "u"), ["id", "title", "level"]);
+            assert!(matches!(
+                fails(&mut db, "select * from t;"),
+                Error::NotFound(_)
+            ));
+            assert!(matches!(
+                fails(&mut db, "select name from u;"),
+                Error::NotFound(_)
+            ));
+            assert!(matches!(
+                fails(&mut db, "alter table u drop column nothing;"),
+                Error::NotFound(_)
+            ));
+            let rows = db.query("select title, level from u;").unwrap();
+            let titles: Vec<String> = rows.iter().map(|row| row.get("title").unwrap()).collect();
+            assert_eq!(titles, ["a", "b", "c"]);
+            run(&mut db, &["insert into u (id, title) values (4, 'd');"]);
+            assert_eq!(ints(&mut db, "select id from u;"), vec![1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn drop_table_removes_it_for_good() {
+        let path = temp_path("drop-table");
+        for mut db in [Neoqlite::new(), Neoqlite::open(&path).unwrap()] {
+            run(
+                &mut db,
+                &[
+                    "create table t (id int primary key, code int unique);",
+                    "create index t_code on t (code);",
+                    "insert into t (id, code) values (1, 10);",
+                    "create table keep (id int primary key);",
+                    "insert into keep (id) values (7);",
+                    "drop table t;",
+                ],
+            );
+            assert!(matches!(
+                fails(&mut db, "select * from t;"),
+                Error::NotFound(_)
+            ));
+            assert!(matches!(
+                fails(&mut db, "drop table t;"),
+                Error::NotFound(_)
+            ));
+            run(&mut db, &["drop table if exists t;"]);
+
+            let mut db = reopened(db, &path);
+            assert!(matches!(
+                fails(&mut db, "select * from t;"),
+                Error::NotFound(_)
+            ));
+            assert_eq!(ints(&mut db, "select id from keep;"), vec![7]);
+
+            // the name , and the name of its index , are free again
+            run(
+                &mut db,
+                &[
+                    "create table t (id int primary key, code int);",
+                    "create index t_code on t (code);",
+                    "insert into t (id, code) values (1, 10);",
+                    "insert into t (id, code) values (2, 10);",
+                ],
+            );
+            assert_eq!(
+                ints(&mut db, "select id from t where code = 10;"),
+                vec![1, 2]
+            );
+        }
+    }
+
+    // names and positions of the columns , as the schema has them
+    fn column_order(db: &Neoqlite, table: &str) -> Vec<(String, u32)> {
+        db.table(table)
+            .unwrap()
+            .schema
+            .columns
+            .iter()
+            .map(|column| (column.name.clone(), column.order))
+            .collect()
+    }
+
+    #[test]
+    fn columns_keep_declaration_order_past_256() {
+        let path = temp_path("many-columns");
+        for mut db in [Neoqlite::new(), Neoqlite::open(&path).unwrap()] {
+            let names: Vec<String> = (0..300).map(|i| format!("c{}", i)).collect();
+            let declared: Vec<String> = names.iter().map(|name| format!("{} int", name)).collect();
+            run(
+                &mut db,
+                &[&format!("create table t ({});", declared.join(", "))],
+            );
+            let values: Vec<String> = (0..300).map(|i| i.to_string()).collect();
+            run(
+                &mut db,
+                &[&format!(
+                    "insert into t ({}) values ({});",
+                    names.join(", "),
+                    values.join(", ")
+                )],
+            );
+
+            let mut expected: Vec<(String, u32)> = names.iter().cloned().zip(1..).collect();
+            assert_eq!(column_order(&db, "t"), expected);
+            assert_eq!(columns(&mut db, "t"), names);
+
+            run(
+                &mut db,
+                &[
+                    "alter table t drop column c1;",
+                    "alter table t add column extra int;",
+                ],
+            );
+            let mut db = reopened(db, &path);
+            expected.remove(1);
+            expected.push(("extra".to_string(), 0));
+            for (i, column) in expected.iter_mut().enumerate() {
+                column.1 = i as u32 + 1;
+            }
+            assert_eq!(column_order(&db, "t"), expected);
+            assert_eq!(ints(&mut db, "select c299 from t;"), vec![299]);
+            assert_eq!(ints(&mut db, "select c2 from t;"), vec![2]);
+            let rows = db.query("select * from t;").unwrap();
+            assert_eq!(rows.get(0).unwrap().values()[1], DataType::Int(2));
+            assert_eq!(rows.get(0).unwrap().values()[299], DataType::Null);
+        }
+    }
+
+    #[test]
+    fn literals_keep_their_type() {
+        for mut db in both("literals") {
+            run(
+                &mut db,
+                &[
+                    "create table t (id int primary key, r real, b boolean, x blob);",
+                    "insert into t (id, r, b, x) values (-9223372036854775808, 3.5, true, x'00ff');",
+                    "insert into t (id, r, b, x) values (9223372036854775807, -0.25, false, x'');",
+                ],
+            );
+            assert_eq!(ints(&mut db, "select id from t;"), vec![i64::MIN, i64::MAX]);
+            assert_eq!(
+                ints(&mut db, "select id from t where id = -9223372036854775808;"),
+                vec![i64::MIN]
+            );
+            let rows = db.query("select r, b, x from t;").unwrap();
+            // == would find 1 = 1.0 = TRUE , so look at the variants
+            assert!(matches!(
+                rows.get(0).unwrap().values(),
+                [DataType::Real(r), DataType::Boolean(true), DataType::Blob(x)]
+                    if *r == 3.5 && x == &[0x00, 0xff]
+            ));
+            assert!(matches!(
+                rows.get(1).unwrap().values(),
+                [DataType::Real(r), DataType::Boolean(false), DataType::Blob(x)]
+                    if *r == -0.25 && x.is_empty()
+            ));
+            assert!(matches!(
+                fails(&mut db, "select id from t where id = 9223372036854775808;"),
+                Error::Parse { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn values_have_a_total_order() {
+        use DataType::*;
+        assert_eq!(Int(1), Real(1.0));
+        assert_eq!(Int(1), Boolean(true));
+        assert_eq!(Real(0.0), Boolean(false));
+        assert!(Int(1) < Real(1.5) && Real(1.5) < Int(2));
+        // 2^53 + 1 has no f64 , the comparison still sees the 1
+        assert!(Int(9007199254740993) > Real(9007199254740992.0));
+        assert!(Real(f64::NAN) < Int(i64::MIN));
+        assert!(Real(f64::NAN) < Real(f64::NEG_INFINITY));
+        assert_eq!(Real(f64::NAN), Real(f64::NAN));
+        assert!(Text("b".to_string()) > Text("a".to_string()));
+        assert!(Blob(vec![0]) < Blob(vec![0, 0]));
+
+        // a DATE is its midnight
+        assert_eq!(Date(1), Timestamp(86_400_000));
+        assert!(Date(1) < Timestamp(86_400_001));
+
+        let mut values = [
+            Blob(vec![1]),
+            Text("a".to_string()),
+            Time(0),
+            Timestamp(0),
+            Real(2.5),
+            Boolean(true),
+            Null,
+            Int(-3),
+        ];
+        values.sort();
+        let sorted: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+        assert_eq!(
+            sorted,
+            [
+                "Null",
+                "Int(-3)",
+                "Boolean(true)",
+                "Real(2.5)",
+                "Timestamp(0)",
+                "Time(0)",
+                "Text(\"a\")",
+                "Blob([1])",
+            ]
+        );
+    }
+
+    // `score + 0` and `tag || ''` are not plain columns , so those go through every row
+    fn same_as_a_scan(db: &mut Neoqlite, condition: &str) {
+        let indexed = format!("select id from t where {};", condition);
+        let scanned = indexed
+            .replace("score", "score + 0")
+            .replace("tag", "tag || ''");
+        assert_eq!(ints(db, &indexed), ints(db, &scanned), "{}", condition);
+    }
+
+    fn check_every_condition(db: &mut Neoqlite) {
+        for condition in [
+            "score = 3",
+            "score = 100",
+            "score > 4",
+            "score <= 1",
+            "3 < score",
+            "score between 2 and 3",
+            "score = 3 and id > 100",
+            "score = 2 or score = 5",
+            "tag = 't42'",
+            "tag >= 't5'",
+            "tag < 't2' and score != 0",
+        ] {
+            same_as_a_scan(db, condition);
+        }
+    }
+
+    #[test]
+    fn indexes_find_the_same_rows_as_a_scan() {
+        let path = temp_path("index-scan");
+        for mut db in [Neoqlite::new(), Neoqlite::open(&path).unwrap()] {
+            run(
+                &mut db,
+                &["create table t (id int primary key, score int, tag text);"],
+            );
+            for id in (0..200).rev() {
+                db.query(&format!(
+                    "insert into t (id, score, tag) values ({}, {}, 't{}');",
+                    id,
+                    id % 7,
+                    id
+                ))
+                .unwrap();
+            }
+            // score repeats , so its entries are told apart by the row key after it
+            run(
+                &mut db,
+                &[
+                    "create index t_score on t (score);",
+                    "create unique index t_tag on t (tag);",
+                ],
+            );
+            assert_eq!(ints(&mut db, "select id from t where score = 3;").len(), 29);
+            check_every_condition(&mut db);
+
+            // the indexes follow changed , re-keyed and deleted rows
+            run(
+                &mut db,
+                &[
+                    "update t set score = 100 where id % 10 = 0;",
+                    "update t set tag = 't' || (id + 1000) where id < 20;",
+                    "update t set id = id + 1000 where id > 180;",
+                    "delete from t where score = 3;",
+                    "delete from t where tag = 't1005';",
+                ],
+            );
+            check_every_condition(&mut db);
+            assert_eq!(
+                ints(&mut db, "select id from t where score = 100 and id > 150;"),
+                vec![160, 170, 180, 1190]
+            );
+            assert!(ints(&mut db, "select id from t where tag = 't5';").is_empty());
+            assert!(matches!(
+                fails(
+                    &mut db,
+                    "insert into t (id, score, tag) values (500, 1, 't42');"
+                ),
+                Error::Constraint(_)
+            ));
+
+            let mut db = reopened(db, &path);
+            check_every_condition(&mut db);
+        }
+    }
+
+    #[test]
+    fn drop_index_leaves_the_rows_alone() {
+        let path = temp_path("drop-index");
+        for mut db in [Neoqlite::new(), Neoqlite::open(&path).unwrap()] {
+            run(
+                &mut db,
+                &[
+                    "create table t (id int primary key, score int, tag text unique);",
+                    "create index t_score on t (score);",
+                    "insert into t (id, score, tag) values (1, 10, 'a');",
+                    "insert into t (id, score, tag) values (2, 20, 'b');",
+                    "insert into t (id, score, tag) values (3, 10, 'c');",
+                    "drop index t_score;",
+                ],
+            );
+            assert_eq!(
+                ints(&mut db, "select id from t where score = 10;"),
+                vec![1, 3]
+            );
+            assert!(matches!(
+                fails(&mut db, "drop index t_score;"),
+                Error::NotFound(_)
+            ));
+            run(&mut db, &["drop index if exists t_score;"]);
+            // the index of a UNIQUE constraint goes with the constraint only
+            assert!(matches!(
+                fails(&mut db, "drop index autoindex_t_1;"),
+                Error::Schema(_)
+            ));
+
+            // gone after reopening too , so the name is free again
+            let mut db = reopened(db, &path);
+            run(
+                &mut db,
+                &[
+                    "insert into t (id, score, tag) values (4, 10, 'd');",
+                    "create index t_score on t (score);",
+                ],
+            );
+            assert_eq!(
+                ints(&mut db, "select id from t where score = 10;"),
+                vec![1, 3, 4]
+            );
+        }
+    }
+
+    #[test]
+    fn moved_roots_are_in_the_catalog() {
+        let path = temp_path("moved-roots");
+        let mut db = Neoqlite::open(&path).unwrap();
+        run(
+            &mut db,
+            &[
+                "create table t (id int primary key, name text);",
+                "create index t_name on t (name);",
+            ],
+        );
+        let roots = db.root_pages();
+        // enough rows to split the roots , half of them in a transaction
+        for id in 0..400 {
+            if id == 200 {
+                run(&mut db, &["begin;"]);
+            }
+            db.query(&format!(
+                "insert into t (id, name) values ({}, 'name {}');",
+                id, id
+            ))
+            .unwrap();
+        }
+        run(&mut db, &["commit;"]);
+        assert_ne!(db.root_pages(), roots);
+
+        let mut db = reopened(db, &path);
+        assert_eq!(ints(&mut db, "select id from t;").len(), 400);
+        assert_eq!(
+            ints(&mut db, "select id from t where name = 'name 399';"),
+            vec![399]
+        );
+
+        // and back down to a root that is a leaf
+        let roots = db.root_pages();
+        run(&mut db, &["delete from t where id > 2;"]);
+        assert_ne!(db.root_pages(), roots);
+        let mut db = reopened(db, &path);
+        assert_eq!(ints(&mut db, "select id from t;"), vec![0, 1, 2]);
+        assert_eq!(
+            ints(&mut db, "select id from t where name = 'name 1';"),
+            vec![1]
+        );
+    }
+}
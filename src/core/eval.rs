@@ -0,0 +1,431 @@
+use crate::core::datetime;
+use crate::core::db::{DataType, Row, Schema};
+use crate::error::Error;
+use crate::parser::parser::{BinaryOp, Expr, UnaryOp};
+
+/*
+*   Evaluates an expression against one row of a table.
+*
+*   An identifier reads that column of `schema` from `row` , naming a column
+*   the table doesnt have is an error. Doing arithmetic or comparing with a
+*   NULL gives NULL again. Comparisons and logic give 1 or 0 like sqlite does
+*   , not a BOOLEAN. Arithmetic stays INT when both sides are INT (or
+*   BOOLEAN) and is REAL otherwise. AND , OR and NOT use three valued logic , an
+*   unknown (NULL) operand only matters when the other one doesnt decide it:
+*
+*   NULL AND 0 = 0    NULL AND 1 = NULL
+*   NULL OR 1 = 1     NULL OR 0 = NULL
+*/
+pub fn eval(expr: &Expr, schema: &Schema, row: &Row) -> Result<DataType, Error> {
+    match expr {
+        Expr::Ident(name) => match schema.column_index(name) {
+            Some(index) => Ok(row.get(index).cloned().unwrap_or(DataType::Null)),
+            None => Err(Error::NotFound(format!("No such column: {}", name))),
+        },
+        Expr::String(s) => Ok(DataType::Text(s.clone())),
+        Expr::Number(n) => Ok(DataType::Int(*n)),
+        Expr::Real(n) => Ok(DataType::from(*n)),
+        Expr::Boolean(b) => Ok(DataType::Boolean(*b)),
+        Expr::Blob(bytes) => Ok(DataType::Blob(bytes.clone())),
+        Expr::Null => Ok(DataType::Null),
+        Expr::Parameter(i) => Err(Error::Execution(format!("Parameter ?{} is not bound", i))),
+        Expr::Nested(expr) => eval(expr, schema, row),
+        // low <= expr AND expr <= high , with the NULLs that gives
+        Expr::Between {
+            expr,
+            negated,
+            low,
+            high,
+        } => {
+            let value = eval(expr, schema, row)?;
+            let above = eval_binary(BinaryOp::GtEq, value.clone(), eval(low, schema, row)?)?;
+            let below = eval_binary(BinaryOp::LtEq, value, eval(high, schema, row)?)?;
+            match eval_binary(BinaryOp::And, above, below)? {
+                DataType::Null => Ok(DataType::Null),
+                between => Ok(from_bool(is_true(&between) != *negated)),
+            }
+        }
+        Expr::IsNull { expr, negated } => {
+            let is_null = eval(expr, schema, row)?.is_null();
+            Ok(from_bool(is_null != *negated))
+        }
+        Expr::Unary { op, expr } => {
+            let value = eval(expr, schema, row)?;
+            if value.is_null() {
+                return Ok(DataType::Null);
+            }
+            match op {
+                UnaryOp::Not => Ok(from_bool(!is_true(&value))),
+                UnaryOp::Plus => Ok(value),
+                UnaryOp::Minus => match to_number(&value) {
+                    Number::Int(n) => n
+                        .checked_neg()
+                        .map(DataType::Int)
+                        .ok_or_else(|| Error::Execution("Integer overflow".to_string())),
+                    Number::Real(n) => Ok(DataType::Real(-n)),
+                },
+            }
+        }
+        Expr::Binary { left, op, right } => {
+            eval_binary(*op, eval(left, schema, row)?, eval(right, schema, row)?)
+        }
+        Expr::Function { name, args } => {
+            let mut values = Vec::with_capacity(args.len());
+            for arg in args {
+                values.push(eval(arg, schema, row)?);
+            }
+            call_function(name, values)
+        }
+    }
+}
+
+// for expressions that dont belong to any row , like the ones in VALUES
+pub fn eval_constant(expr: &Expr) -> Result<DataType, Error> {
+    eval(expr, &Schema::new(), &Row::new())
+}
+
+// true if the value of `expr` doesnt depend on the row
+pub fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Ident(_) => false,
+        Expr::String(_)
+        | Expr::Number(_)
+        | Expr::Real(_)
+        | Expr::Boolean(_)
+        | Expr::Blob(_)
+        | Expr::Null
+        | Expr::Parameter(_) => true,
+        Expr::Nested(expr) | Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } => {
+            is_constant(expr)
+        }
+        Expr::Binary { left, right, .. } => is_constant(left) && is_constant(right),
+        Expr::Between {
+            expr, low, high, ..
+        } => is_constant(expr) && is_constant(low) && is_constant(high),
+        Expr::Function { args, .. } => args.iter().all(is_constant),
+    }
+}
+
+// what WHERE keeps , NULL is not true
+pub fn is_true(value: &DataType) -> bool {
+    truth(value) == Some(true)
+}
+
+// None when the value is NULL , we dont know if it is true or not
+fn truth(value: &DataType) -> Option<bool> {
+    match value {
+        DataType::Null => None,
+        value => Some(match to_number(value) {
+            Number::Int(n) => n != 0,
+            Number::Real(n) => n != 0.0,
+        }),
+    }
+}
+
+fn from_bool(value: bool) -> DataType {
+    DataType::Int(value as i64)
+}
+
+enum Number {
+    Int(i64),
+    Real(f64),
+}
+
+impl Number {
+    fn as_f64(&self) -> f64 {
+        match self {
+            Number::Int(n) => *n as f64,
+            Number::Real(n) => *n,
+        }
+    }
+}
+
+// text that doesnt look like a number counts as 0 , same as sqlite , so do
+// blobs and dates (use julianday() to do arithmetic on those)
+fn to_number(value: &DataType) -> Number {
+    match value {
+        DataType::Int(n) => Number::Int(*n),
+        DataType::Boolean(b) => Number::Int(*b as i64),
+        DataType::Real(n) => Number::Real(*n),
+        DataType::Text(s) => {
+            let s = s.trim();
+            match s.parse() {
+                Ok(n) => Number::Int(n),
+                Err(_) => s.parse().map_or(Number::Int(0), Number::Real),
+            }
+        }
+        DataType::Null
+        | DataType::Blob(_)
+        | DataType::Date(_)
+        | DataType::Time(_)
+        | DataType::Timestamp(_) => Number::Int(0),
+    }
+}
+
+fn eval_binary(op: BinaryOp, left: DataType, right: DataType) -> Result<DataType, Error> {
+    match (op, truth(&left), truth(&right)) {
+        (BinaryOp::And, Some(false), _) | (BinaryOp::And, _, Some(false)) => {
+            return Ok(from_bool(false))
+        }
+        (BinaryOp::And, Some(true), Some(true)) => return Ok(from_bool(true)),
+        (BinaryOp::Or, Some(true), _) | (BinaryOp::Or, _, Some(true)) => {
+            return Ok(from_bool(true))
+        }
+        (BinaryOp::Or, Some(false), Some(false)) => return Ok(from_bool(false)),
+        (BinaryOp::And | BinaryOp::Or, _, _) => return Ok(DataType::Null),
+        _ => {}
+    }
+
+    if left.is_null() || right.is_null() {
+        return Ok(DataType::Null);
+    }
+    let (left, right) = coerce_temporal(left, right);
+
+    let result = match op {
+        BinaryOp::Eq => from_bool(left == right),
+        BinaryOp::NotEq => from_bool(left != right),
+        BinaryOp::Lt => from_bool(left < right),
+        BinaryOp::LtEq => from_bool(left <= right),
+        BinaryOp::Gt => from_bool(left > right),
+        BinaryOp::GtEq => from_bool(left >= right),
+        BinaryOp::Concat => DataType::Text(format!("{}{}", left, right)),
+        BinaryOp::Plus
+        | BinaryOp::Minus
+        | BinaryOp::Multiply
+        | BinaryOp::Divide
+        | BinaryOp::Modulo => arithmetic(op, to_number(&left), to_number(&right))?,
+        BinaryOp::And | BinaryOp::Or => unreachable!(),
+    };
+    Ok(result)
+}
+
+// text next to a date or a time is read as one , if it looks like one
+fn coerce_temporal(left: DataType, right: DataType) -> (DataType, DataType) {
+    match (&left, &right) {
+        (DataType::Text(s), other) if other.is_temporal() => {
+            (datetime::text_as(s, other).unwrap_or(left), right)
+        }
+        (other, DataType::Text(s)) if other.is_temporal() => {
+            let right = datetime::text_as(s, other).unwrap_or(right);
+            (left, right)
+        }
+        _ => (left, right),
+    }
+}
+
+fn arithmetic(op: BinaryOp, left: Number, right: Number) -> Result<DataType, Error> {
+    // dividing by zero gives NULL instead of an error
+    if matches!(op, BinaryOp::Divide | BinaryOp::Modulo) && right.as_f64() == 0.0 {
+        return Ok(DataType::Null);
+    }
+    match (left, right) {
+        (Number::Int(left), Number::Int(right)) => {
+            let result = match op {
+                BinaryOp::Plus => left.checked_add(right),
+                BinaryOp::Minus => left.checked_sub(right),
+                BinaryOp::Multiply => left.checked_mul(right),
+                BinaryOp::Divide => left.checked_div(right),
+                _ => left.checked_rem(right),
+            };
+            Ok(DataType::Int(result.ok_or_else(|| {
+                Error::Execution("Integer overflow".to_string())
+            })?))
+        }
+        (left, right) => {
+            let (left, right) = (left.as_f64(), right.as_f64());
+            let result = match op {
+                BinaryOp::Plus => left + right,
+                BinaryOp::Minus => left - right,
+                BinaryOp::Multiply => left * right,
+                BinaryOp::Divide => left / right,
+                _ => left % right,
+            };
+            // inf - inf and friends are NaN , which is NULL
+            Ok(DataType::from(result))
+        }
+    }
+}
+
+fn call_function(name: &str, args: Vec<DataType>) -> Result<DataType, Error> {
+    let name = name.to_lowercase();
+    // (fewest , most) arguments
+    let arity = match name.as_str() {
+        "abs" | "length" | "lower" | "upper" => (1, 1),
+        "date" | "time" | "datetime" | "julianday" => (0, usize::MAX),
+        "strftime" => (1, usize::MAX),
+        // CURRENT_TIMESTAMP and friends , written without parentheses
+        "current_timestamp" | "current_date" | "current_time" => (0, 0),
+        _ => return Err(Error::NotFound(format!("Unknown function {}", name))),
+    };
+    if args.len() < arity.0 || args.len() > arity.1 {
+        let expected = if arity.0 == arity.1 {
+            arity.0.to_string()
+        } else {
+            format!("at least {}", arity.0)
+        };
+        return Err(Error::Schema(format!(
+            "{}() takes {} argument(s) but got {}",
+            name,
+            expected,
+            args.len()
+        )));
+    }
+    if arity.1 == usize::MAX {
+        return Ok(datetime::call(&name, &args));
+    }
+    // the moment the statement runs , like datetime('now')
+    if let Some(unit) = name.strip_prefix("current_") {
+        let function = if unit == "timestamp" {
+            "datetime"
+        } else {
+            unit
+        };
+        return Ok(datetime::call(function, &[]));
+    }
+
+    let arg = match args.into_iter().next() {
+        Some(DataType::Null) | None => return Ok(DataType::Null),
+        Some(arg) => arg,
+    };
+    let result = match name.as_str() {
+        "abs" => match to_number(&arg) {
+            Number::Int(n) => DataType::Int(
+                n.checked_abs()
+                    .ok_or_else(|| Error::Execution("Integer overflow".to_string()))?,
+            ),
+            Number::Real(n) => DataType::Real(n.abs()),
+        },
+        // bytes for a blob , characters for everything else
+        "length" => match &arg {
+            DataType::Blob(bytes) => DataType::Int(bytes.len() as i64),
+            arg => DataType::Int(arg.to_string().chars().count() as i64),
+        },
+        "lower" => DataType::Text(arg.to_string().to_lowercase()),
+        _ => DataType::Text(arg.to_string().to_uppercase()),
+    };
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::db::Neoqlite;
+    use crate::parser::{lexer::Lexer, parser::Parser};
+
+    fn value(sql: &str) -> DataType {
+        let tokens = Lexer::new(sql).tokenize().unwrap();
+        let expr = Parser::new(tokens).parse_expr().unwrap();
+        eval_constant(&expr).unwrap_or_else(|error| panic!("{} : {:?}", sql, error))
+    }
+
+    const NULL: DataType = DataType::Null;
+    const FALSE: DataType = DataType::Int(0);
+    const TRUE: DataType = DataType::Int(1);
+
+    #[test]
+    fn and_or_not_use_three_valued_logic() {
+        let operands = [("NULL", NULL), ("0", FALSE), ("1", TRUE)];
+        for (a, a_value) in &operands {
+            for (b, b_value) in &operands {
+                let and = match (truth(a_value), truth(b_value)) {
+                    (Some(false), _) | (_, Some(false)) => FALSE,
+                    (Some(true), Some(true)) => TRUE,
+                    _ => NULL,
+                };
+                let or = match (truth(a_value), truth(b_value)) {
+                    (Some(true), _) | (_, Some(true)) => TRUE,
+                    (Some(false), Some(false)) => FALSE,
+                    _ => NULL,
+                };
+                assert_eq!(value(&format!("{} AND {}", a, b)), and, "{} AND {}", a, b);
+                assert_eq!(value(&format!("{} OR {}", a, b)), or, "{} OR {}", a, b);
+            }
+        }
+        // the ones from the comment at the top
+        assert_eq!(value("NULL AND 0"), FALSE);
+        assert_eq!(value("NULL AND 1"), NULL);
+        assert_eq!(value("NULL OR 1"), TRUE);
+        assert_eq!(value("NULL OR 0"), NULL);
+
+        assert_eq!(value("NOT NULL"), NULL);
+        assert_eq!(value("NOT 0"), TRUE);
+        assert_eq!(value("NOT 7"), FALSE);
+        assert_eq!(value("NOT 0.5"), FALSE);
+    }
+
+    #[test]
+    fn null_compares_to_null() {
+        for sql in [
+            "NULL = NULL",
+            "NULL != NULL",
+            "NULL = 1",
+            "1 <> NULL",
+            "NULL < 1",
+            "'a' >= NULL",
+            "1 + NULL",
+            "NULL || 'a'",
+            "NULL BETWEEN 1 AND 2",
+            "1 BETWEEN NULL AND 2",
+        ] {
+            assert_eq!(value(sql), NULL, "{}", sql);
+        }
+        // only one end decides it
+        assert_eq!(value("5 BETWEEN NULL AND 2"), FALSE);
+    }
+
+    #[test]
+    fn is_null_is_never_null() {
+        assert_eq!(value("NULL IS NULL"), TRUE);
+        assert_eq!(value("0 IS NULL"), FALSE);
+        assert_eq!(value("'' IS NULL"), FALSE);
+        assert_eq!(value("NULL IS NOT NULL"), FALSE);
+        assert_eq!(value("1 IS NOT NULL"), TRUE);
+        assert_eq!(value("(NULL = NULL) IS NULL"), TRUE);
+        assert_eq!(value("1 + NULL IS NOT NULL"), FALSE);
+    }
+
+    #[test]
+    fn only_true_is_true() {
+        assert!(!is_true(&NULL));
+        assert!(!is_true(&FALSE));
+        assert!(!is_true(&DataType::Real(0.0)));
+        assert!(!is_true(&DataType::Boolean(false)));
+        assert!(is_true(&DataType::Int(-3)));
+        assert!(is_true(&DataType::Real(0.5)));
+        assert!(is_true(&DataType::Boolean(true)));
+    }
+
+    #[test]
+    fn where_drops_rows_that_are_null() {
+        let mut db = Neoqlite::new();
+        for sql in [
+            "create table t (id int primary key, n int);",
+            "insert into t (id, n) values (1, 1);",
+            "insert into t (id, n) values (2, NULL);",
+            "insert into t (id, n) values (3, 3);",
+        ] {
+            db.query(sql).unwrap();
+        }
+        let ids = |db: &mut Neoqlite, clause: &str| -> Vec<i64> {
+            let rows = db
+                .query(&format!("select id from t where {};", clause))
+                .unwrap();
+            rows.iter().map(|row| row.get("id").unwrap()).collect()
+        };
+        assert_eq!(ids(&mut db, "n = NULL"), Vec::<i64>::new());
+        assert_eq!(ids(&mut db, "n != 1"), [3]);
+        assert_eq!(ids(&mut db, "NOT (n = 1)"), [3]);
+        assert_eq!(ids(&mut db, "n > 0 AND id > 0"), [1, 3]);
+        assert_eq!(ids(&mut db, "n IS NULL"), [2]);
+        assert_eq!(ids(&mut db, "n IS NOT NULL"), [1, 3]);
+        // the OR decides it , the unknown side doesnt matter
+        assert_eq!(ids(&mut db, "n > 0 OR id = 2"), [1, 2, 3]);
+        assert_eq!(ids(&mut db, "n > 2 OR id = 1"), [1, 3]);
+
+        // DELETE and UPDATE leave them alone too
+        db.query("update t set n = 0 where n != 3;").unwrap();
+        db.query("delete from t where n = 0;").unwrap();
+        assert_eq!(ids(&mut db, "id > 0"), [2, 3]);
+        assert_eq!(ids(&mut db, "n IS NULL"), [2]);
+    }
+}
@@ -1,3 +1,24 @@
 pub mod btree;
+pub mod codec;
+pub mod datetime;
 pub mod db;
+pub mod eval;
+pub mod paged_btree;
 pub mod pager;
+pub mod rows;
+pub mod statement;
+pub mod wal;
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::path::PathBuf;
+
+    // a database path of its own for every test , without the files of an earlier run
+    pub fn temp_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("neoqlite-{}-{}.db", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::core::wal::Wal::path_for(&path));
+        path
+    }
+}
@@ -0,0 +1,973 @@
+use crate::core::codec::{decode_from_slice, encode_to_vec, Codec, Reader};
+use crate::core::pager::{PageNum, Pager};
+use crate::error::Error;
+use std::cell::RefCell;
+use std::cmp::{Ord, Ordering};
+use std::fmt;
+use std::rc::Rc;
+
+/*
+*   A classic B-tree , values sit next to their keys in inner nodes too (unlike
+*   the B+tree in core::btree). Every node lives in a page chain owned by the
+*   pager and children are page numbers.
+*   Nodes are read from the pager when needed and written back as soon as they
+*   change , so only the pages the pager decides to cache are kept in memory.
+*/
+
+#[derive(Debug, Clone)]
+struct PagedNode<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+    children: Vec<PageNum>,
+    is_leaf: bool,
+}
+
+impl<K: Codec, V: Codec> Codec for PagedNode<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.is_leaf.encode(buf);
+        self.keys.encode(buf);
+        self.values.encode(buf);
+        self.children.encode(buf);
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(Self {
+            is_leaf: bool::decode(reader)?,
+            keys: Vec::decode(reader)?,
+            values: Vec::decode(reader)?,
+            children: Vec::decode(reader)?,
+        })
+    }
+}
+
+impl<K: Ord, V> PagedNode<K, V> {
+    fn new(is_leaf: bool) -> Self {
+        Self {
+            keys: Vec::new(),
+            values: Vec::new(),
+            children: Vec::new(),
+            is_leaf,
+        }
+    }
+
+    fn find_key(&self, key: &K) -> usize {
+        let mut i = 0;
+        while i < self.keys.len() && self.keys[i] < *key {
+            i += 1;
+        }
+        i
+    }
+}
+
+#[derive(Clone)]
+pub struct PagedBTree<K, V> {
+    pager: Rc<RefCell<Pager>>,
+    root: Option<PageNum>,
+    order: usize,
+    _marker: std::marker::PhantomData<(K, V)>,
+}
+
+impl<K, V> fmt::Debug for PagedBTree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PagedBTree")
+            .field("root", &self.root)
+            .field("order", &self.order)
+            .finish()
+    }
+}
+
+impl<K, V> PagedBTree<K, V>
+where
+    K: Ord + Clone + Codec,
+    V: Clone + Codec,
+{
+    /*
+     *   `root` is the page of an existing tree , or None for an empty one.
+     */
+    pub fn new(pager: Rc<RefCell<Pager>>, root: Option<PageNum>, order: usize) -> Self {
+        assert!(order >= 2, "BTree order must be at least 2");
+        Self {
+            pager,
+            root,
+            order,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    pub fn root(&self) -> Option<PageNum> {
+        self.root
+    }
+
+    // a new empty tree in the same file , with the same order
+    pub fn empty_like(&self) -> Self {
+        Self::new(Rc::clone(&self.pager), None, self.order)
+    }
+
+    // a new tree in the same file holding `entries` , see from_sorted_iter
+    pub fn filled_like(&self, entries: impl IntoIterator<Item = (K, V)>) -> Result<Self, Error> {
+        Self::from_sorted_iter(Rc::clone(&self.pager), self.order, entries)
+    }
+
+    /*
+     *   Builds the tree bottom-up from entries in key order (which the caller
+     *   has to make sure of) , writing every node once instead of splitting
+     *   from the root for every entry. Nodes are filled up and the entry that
+     *   doesnt fit any more moves up to the level above , like in a split.
+     *   The last node of a level can end up too small , it borrows from the
+     *   full one before it at the end. A key that comes twice keeps the last
+     *   value , like insert.
+     */
+    pub fn from_sorted_iter(
+        pager: Rc<RefCell<Pager>>,
+        order: usize,
+        entries: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<Self, Error> {
+        let mut tree = Self::new(pager, None, order);
+        // the node that is being filled on every level , leaves first
+        let mut levels = Vec::new();
+        let mut pending: Option<(K, V)> = None;
+        for (key, value) in entries {
+            match pending.take() {
+                Some((last, _)) if last == key => {}
+                Some(entry) => {
+                    debug_assert!(entry.0 < key, "from_sorted_iter needs the keys in order");
+                    tree.bulk_push(&mut levels, 0, entry, None)?;
+                }
+                None => {}
+            }
+            pending = Some((key, value));
+        }
+        if let Some(entry) = pending {
+            tree.bulk_push(&mut levels, 0, entry, None)?;
+        }
+
+        let top = levels.len().saturating_sub(1);
+        let mut child = None;
+        for (level, mut node) in levels.into_iter().enumerate() {
+            node.children.extend(child);
+            if level == top && node.keys.is_empty() {
+                break;
+            }
+            child = Some(tree.store_new(&node)?);
+        }
+        tree.root = child;
+        tree.fix_right_edge()?;
+        Ok(tree)
+    }
+
+    // adds the entry to the node of `level` , with the child on its left for inner nodes
+    fn bulk_push(
+        &self,
+        levels: &mut Vec<PagedNode<K, V>>,
+        level: usize,
+        (key, value): (K, V),
+        child: Option<PageNum>,
+    ) -> Result<(), Error> {
+        if levels.len() == level {
+            levels.push(PagedNode::new(level == 0));
+        }
+        levels[level].children.extend(child);
+        if self.is_full(&levels[level]) {
+            let full = std::mem::replace(&mut levels[level], PagedNode::new(level == 0));
+            let page = self.store_new(&full)?;
+            return self.bulk_push(levels, level + 1, (key, value), Some(page));
+        }
+        levels[level].keys.push(key);
+        levels[level].values.push(value);
+        Ok(())
+    }
+
+    // the nodes along the right edge after a bulk load take keys from their full left sibling
+    fn fix_right_edge(&self) -> Result<(), Error> {
+        let mut page = match self.root {
+            Some(root) => root,
+            None => return Ok(()),
+        };
+        loop {
+            let mut node = self.load(page)?;
+            if node.is_leaf {
+                return Ok(());
+            }
+            let last = node.children.len() - 1;
+            let mut changed = false;
+            while self.load(node.children[last])?.keys.len() < self.order - 1 {
+                self.borrow_from_prev(&mut node, last)?;
+                changed = true;
+            }
+            if changed {
+                self.store(page, &node)?;
+            }
+            page = node.children[last];
+        }
+    }
+
+    fn load(&self, page: PageNum) -> Result<PagedNode<K, V>, Error> {
+        let bytes = self.pager.borrow_mut().read_chain(page)?;
+        decode_from_slice(&bytes)
+    }
+
+    fn store(&self, page: PageNum, node: &PagedNode<K, V>) -> Result<(), Error> {
+        self.pager
+            .borrow_mut()
+            .write_chain(Some(page), &encode_to_vec(node))?;
+        Ok(())
+    }
+
+    fn store_new(&self, node: &PagedNode<K, V>) -> Result<PageNum, Error> {
+        self.pager
+            .borrow_mut()
+            .write_chain(None, &encode_to_vec(node))
+    }
+
+    fn free(&self, page: PageNum) -> Result<(), Error> {
+        self.pager.borrow_mut().free_chain(page)
+    }
+
+    fn is_full(&self, node: &PagedNode<K, V>) -> bool {
+        node.keys.len() == self.order * 2 - 1
+    }
+
+    pub fn search(&self, key: &K) -> Result<Option<V>, Error> {
+        let mut current = match self.root {
+            Some(root) => root,
+            None => return Ok(None),
+        };
+        loop {
+            let node = self.load(current)?;
+            let idx = node.find_key(key);
+            if idx < node.keys.len() && node.keys[idx] == *key {
+                return Ok(Some(node.values[idx].clone()));
+            }
+            if node.is_leaf {
+                return Ok(None);
+            }
+            current = node.children[idx];
+        }
+    }
+
+    // a key that is already there gets the new value , like core::btree::BTree
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), Error> {
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                let mut node = PagedNode::new(true);
+                node.keys.push(key);
+                node.values.push(value);
+                self.root = Some(self.store_new(&node)?);
+                return Ok(());
+            }
+        };
+
+        let root_node = self.load(root)?;
+        if self.is_full(&root_node) {
+            let mut new_root = PagedNode::new(false);
+            new_root.children.push(root);
+            self.split_child(&mut new_root, 0)?;
+            if new_root.keys[0] == key {
+                new_root.values[0] = value;
+                self.root = Some(self.store_new(&new_root)?);
+                return Ok(());
+            }
+
+            let i = if new_root.keys[0] < key { 1 } else { 0 };
+            let child = new_root.children[i];
+            self.root = Some(self.store_new(&new_root)?);
+            self.insert_non_full(child, key, value)
+        } else {
+            self.insert_non_full(root, key, value)
+        }
+    }
+
+    // the caller is responsible for storing `parent`
+    fn split_child(&self, parent: &mut PagedNode<K, V>, i: usize) -> Result<(), Error> {
+        let t = self.order;
+        let child_page = parent.children[i];
+        let mut child = self.load(child_page)?;
+
+        let mut new_child = PagedNode::new(child.is_leaf);
+        new_child.keys = child.keys.split_off(t);
+        new_child.values = child.values.split_off(t);
+        if !child.is_leaf {
+            new_child.children = child.children.split_off(t);
+        }
+
+        parent
+            .keys
+            .insert(i, child.keys.pop().expect("Child has no keys to move"));
+        parent
+            .values
+            .insert(i, child.values.pop().expect("Child has no values to move"));
+
+        self.store(child_page, &child)?;
+        let new_page = self.store_new(&new_child)?;
+        parent.children.insert(i + 1, new_page);
+        Ok(())
+    }
+
+    fn insert_non_full(&self, page: PageNum, key: K, value: V) -> Result<(), Error> {
+        let mut node = self.load(page)?;
+        let mut i = node.find_key(&key);
+        if i < node.keys.len() && node.keys[i] == key {
+            node.values[i] = value;
+            return self.store(page, &node);
+        }
+
+        if node.is_leaf {
+            node.keys.insert(i, key);
+            node.values.insert(i, value);
+            return self.store(page, &node);
+        }
+
+        let child = self.load(node.children[i])?;
+        if self.is_full(&child) {
+            self.split_child(&mut node, i)?;
+            // the middle key that moved up can be the one we are looking for
+            if key == node.keys[i] {
+                node.values[i] = value;
+                return self.store(page, &node);
+            }
+            self.store(page, &node)?;
+            if key > node.keys[i] {
+                i += 1;
+            }
+        }
+        self.insert_non_full(node.children[i], key, value)
+    }
+
+    pub fn delete(&mut self, key: &K) -> Result<(), Error> {
+        let root = match self.root {
+            Some(root) => root,
+            None => return Ok(()),
+        };
+
+        self.delete_from(root, key)?;
+
+        let root_node = self.load(root)?;
+        if root_node.keys.is_empty() {
+            self.root = if root_node.is_leaf {
+                None
+            } else {
+                Some(root_node.children[0])
+            };
+            self.free(root)?;
+        }
+        Ok(())
+    }
+
+    fn delete_from(&self, page: PageNum, key: &K) -> Result<(), Error> {
+        let mut node = self.load(page)?;
+        let mut idx = node.find_key(key);
+
+        if idx < node.keys.len() && node.keys[idx] == *key {
+            if node.is_leaf {
+                node.keys.remove(idx);
+                node.values.remove(idx);
+                return self.store(page, &node);
+            }
+            return self.delete_internal_node(page, node, key, idx);
+        }
+
+        if node.is_leaf {
+            return Ok(());
+        }
+
+        let flag = idx == node.keys.len();
+        if self.load(node.children[idx])?.keys.len() < self.order {
+            self.fill(&mut node, idx)?;
+            self.store(page, &node)?;
+        }
+
+        if flag && idx > node.keys.len() {
+            idx -= 1;
+        }
+        self.delete_from(node.children[idx], key)
+    }
+
+    fn delete_internal_node(
+        &self,
+        page: PageNum,
+        mut node: PagedNode<K, V>,
+        key: &K,
+        idx: usize,
+    ) -> Result<(), Error> {
+        let left = self.load(node.children[idx])?;
+        if left.keys.len() >= self.order {
+            let (pred_key, pred_value) = self.last_entry(node.children[idx])?;
+            node.keys[idx] = pred_key.clone();
+            node.values[idx] = pred_value;
+            self.store(page, &node)?;
+            return self.delete_from(node.children[idx], &pred_key);
+        }
+
+        let right = self.load(node.children[idx + 1])?;
+        if right.keys.len() >= self.order {
+            let (succ_key, succ_value) = self.first_entry(node.children[idx + 1])?;
+            node.keys[idx] = succ_key.clone();
+            node.values[idx] = succ_value;
+            self.store(page, &node)?;
+            return self.delete_from(node.children[idx + 1], &succ_key);
+        }
+
+        self.merge(&mut node, idx)?;
+        self.store(page, &node)?;
+        self.delete_from(node.children[idx], key)
+    }
+
+    fn last_entry(&self, page: PageNum) -> Result<(K, V), Error> {
+        let mut node = self.load(page)?;
+        while !node.is_leaf {
+            node = self.load(node.children[node.children.len() - 1])?;
+        }
+        let last = node.keys.len() - 1;
+        Ok((node.keys[last].clone(), node.values[last].clone()))
+    }
+
+    fn first_entry(&self, page: PageNum) -> Result<(K, V), Error> {
+        let mut node = self.load(page)?;
+        while !node.is_leaf {
+            node = self.load(node.children[0])?;
+        }
+        Ok((node.keys[0].clone(), node.values[0].clone()))
+    }
+
+    // like split_child , the caller stores `parent`
+    fn fill(&self, parent: &mut PagedNode<K, V>, idx: usize) -> Result<(), Error> {
+        let last = parent.children.len() - 1;
+        if idx > 0 && self.load(parent.children[idx - 1])?.keys.len() >= self.order {
+            self.borrow_from_prev(parent, idx)
+        } else if idx < last && self.load(parent.children[idx + 1])?.keys.len() >= self.order {
+            self.borrow_from_next(parent, idx)
+        } else if idx < last {
+            self.merge(parent, idx)
+        } else {
+            self.merge(parent, idx - 1)
+        }
+    }
+
+    fn borrow_from_prev(&self, parent: &mut PagedNode<K, V>, idx: usize) -> Result<(), Error> {
+        let mut sibling = self.load(parent.children[idx - 1])?;
+        let mut child = self.load(parent.children[idx])?;
+
+        let last_key = sibling.keys.pop().expect("Sibling has no keys to borrow");
+        let last_value = sibling
+            .values
+            .pop()
+            .expect("Sibling has no values to borrow");
+        child.keys.insert(0, parent.keys[idx - 1].clone());
+        child.values.insert(0, parent.values[idx - 1].clone());
+
+        parent.keys[idx - 1] = last_key;
+        parent.values[idx - 1] = last_value;
+        if !sibling.is_leaf {
+            if let Some(child_to_move) = sibling.children.pop() {
+                child.children.insert(0, child_to_move);
+            }
+        }
+
+        self.store(parent.children[idx - 1], &sibling)?;
+        self.store(parent.children[idx], &child)
+    }
+
+    fn borrow_from_next(&self, parent: &mut PagedNode<K, V>, idx: usize) -> Result<(), Error> {
+        let mut sibling = self.load(parent.children[idx + 1])?;
+        let mut child = self.load(parent.children[idx])?;
+
+        let first_key = sibling.keys.remove(0);
+        let first_value = sibling.values.remove(0);
+        child.keys.push(parent.keys[idx].clone());
+        child.values.push(parent.values[idx].clone());
+
+        parent.keys[idx] = first_key;
+        parent.values[idx] = first_value;
+        if !sibling.is_leaf {
+            child.children.push(sibling.children.remove(0));
+        }
+
+        self.store(parent.children[idx + 1], &sibling)?;
+        self.store(parent.children[idx], &child)
+    }
+
+    fn merge(&self, parent: &mut PagedNode<K, V>, idx: usize) -> Result<(), Error> {
+        let sibling_page = parent.children.remove(idx + 1);
+        let mut sibling = self.load(sibling_page)?;
+        let mut child = self.load(parent.children[idx])?;
+
+        child.keys.push(parent.keys.remove(idx));
+        child.values.push(parent.values.remove(idx));
+
+        child.keys.append(&mut sibling.keys);
+        child.values.append(&mut sibling.values);
+        if !child.is_leaf {
+            child.children.append(&mut sibling.children);
+        }
+
+        self.store(parent.children[idx], &child)?;
+        self.free(sibling_page)
+    }
+
+    /*
+     *   Gives every page of the tree back to the pager , the tree is empty
+     *   afterwards.
+     */
+    pub fn clear(&mut self) -> Result<(), Error> {
+        if let Some(root) = self.root.take() {
+            self.free_subtree(root)?;
+        }
+        Ok(())
+    }
+
+    fn free_subtree(&self, page: PageNum) -> Result<(), Error> {
+        let node = self.load(page)?;
+        for child in node.children {
+            self.free_subtree(child)?;
+        }
+        self.free(page)
+    }
+
+    pub fn values_in_order(&self) -> Result<Vec<V>, Error> {
+        let mut result = Vec::new();
+        if let Some(root) = self.root {
+            self.collect_values_in_order(root, &mut result)?;
+        }
+        Ok(result)
+    }
+
+    // same walk as values_in_order , with the key next to every value
+    pub fn entries_in_order(&self) -> Result<Vec<(K, V)>, Error> {
+        let mut result = Vec::new();
+        if let Some(root) = self.root {
+            self.collect_entries_in_order(root, &mut result)?;
+        }
+        Ok(result)
+    }
+
+    fn collect_entries_in_order(
+        &self,
+        page: PageNum,
+        result: &mut Vec<(K, V)>,
+    ) -> Result<(), Error> {
+        let node = self.load(page)?;
+        for (i, (key, value)) in node.keys.into_iter().zip(node.values).enumerate() {
+            if !node.is_leaf {
+                self.collect_entries_in_order(node.children[i], result)?;
+            }
+            result.push((key, value));
+        }
+        if let Some(last) = node.children.last().filter(|_| !node.is_leaf) {
+            self.collect_entries_in_order(*last, result)?;
+        }
+        Ok(())
+    }
+
+    // see BTree::entries_in_range , only the pages that can hold the range are read
+    pub fn entries_in_range(&self, cmp: impl Fn(&K) -> Ordering) -> Result<Vec<(K, V)>, Error> {
+        let mut result = Vec::new();
+        if let Some(root) = self.root {
+            self.collect_entries_in_range(root, &cmp, &mut result)?;
+        }
+        Ok(result)
+    }
+
+    fn collect_entries_in_range(
+        &self,
+        page: PageNum,
+        cmp: &impl Fn(&K) -> Ordering,
+        result: &mut Vec<(K, V)>,
+    ) -> Result<(), Error> {
+        let node = self.load(page)?;
+        for (i, (key, value)) in node.keys.into_iter().zip(node.values).enumerate() {
+            let ordering = cmp(&key);
+            if !node.is_leaf && ordering != Ordering::Less {
+                self.collect_entries_in_range(node.children[i], cmp, result)?;
+            }
+            match ordering {
+                Ordering::Less => {}
+                Ordering::Equal => result.push((key, value)),
+                Ordering::Greater => return Ok(()),
+            }
+        }
+        if let Some(last) = node.children.last().filter(|_| !node.is_leaf) {
+            self.collect_entries_in_range(*last, cmp, result)?;
+        }
+        Ok(())
+    }
+
+    // a cursor before the first entry
+    pub fn cursor(&self) -> Result<PagedCursor<'_, K, V>, Error> {
+        let mut cursor = PagedCursor {
+            tree: self,
+            stack: Vec::new(),
+        };
+        cursor.move_to_start()?;
+        Ok(cursor)
+    }
+
+    // the largest key is at the end of the rightmost leaf
+    pub fn last_key(&self) -> Result<Option<K>, Error> {
+        let mut current = match self.root {
+            Some(root) => root,
+            None => return Ok(None),
+        };
+        loop {
+            let node = self.load(current)?;
+            match node.children.last() {
+                Some(&child) if !node.is_leaf => current = child,
+                _ => return Ok(node.keys.last().cloned()),
+            }
+        }
+    }
+
+    fn collect_values_in_order(&self, page: PageNum, result: &mut Vec<V>) -> Result<(), Error> {
+        let node = self.load(page)?;
+        if node.is_leaf {
+            result.extend(node.values);
+            return Ok(());
+        }
+        for i in 0..node.keys.len() {
+            self.collect_values_in_order(node.children[i], result)?;
+            result.push(node.values[i].clone());
+        }
+        if let Some(last) = node.children.last() {
+            self.collect_values_in_order(*last, result)?;
+        }
+        Ok(())
+    }
+}
+
+/*
+*   A position in a PagedBTree , like core::btree::Cursor it sits between two
+*   entries. `stack` is the path from the root with the nodes read from their
+*   pages , for every node the child the path goes into and for the leaf at
+*   the top the gap , gap i is just before keys[i]. Only the pages along the
+*   path are held , the ones next to it are read when the cursor gets there.
+*/
+pub struct PagedCursor<'a, K, V> {
+    tree: &'a PagedBTree<K, V>,
+    stack: Vec<(PagedNode<K, V>, usize)>,
+}
+
+impl<K, V> PagedCursor<'_, K, V>
+where
+    K: Ord + Clone + Codec,
+    V: Clone + Codec,
+{
+    // before the first entry
+    pub fn move_to_start(&mut self) -> Result<(), Error> {
+        self.stack.clear();
+        match self.tree.root {
+            Some(root) => self.descend_left(root),
+            None => Ok(()),
+        }
+    }
+
+    // after the last entry
+    pub fn move_to_end(&mut self) -> Result<(), Error> {
+        self.stack.clear();
+        match self.tree.root {
+            Some(root) => self.descend_right(root),
+            None => Ok(()),
+        }
+    }
+
+    // before the first entry whose key is not less than `key`
+    pub fn seek(&mut self, key: &K) -> Result<(), Error> {
+        self.seek_by(|other| other.cmp(key))
+    }
+
+    // before the first entry for which `cmp` is not Less , see BTree::entries_in_range
+    pub fn seek_by(&mut self, cmp: impl Fn(&K) -> Ordering) -> Result<(), Error> {
+        self.stack.clear();
+        let mut page = match self.tree.root {
+            Some(root) => root,
+            None => return Ok(()),
+        };
+        loop {
+            let node = self.tree.load(page)?;
+            let i = node.keys.partition_point(|key| cmp(key) == Ordering::Less);
+            let child = node.children.get(i).copied();
+            let is_leaf = node.is_leaf;
+            self.stack.push((node, i));
+            match child {
+                Some(child) if !is_leaf => page = child,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    pub fn prev(&mut self) -> Option<Result<(K, V), Error>> {
+        // the nearest node on the path that has an entry before the cursor
+        let level = self.stack.iter().rposition(|(_, i)| *i > 0)?;
+        let is_top = level == self.stack.len() - 1;
+        self.stack.truncate(level + 1);
+        let (node, i) = &mut self.stack[level];
+        *i -= 1;
+        let entry = (node.keys[*i].clone(), node.values[*i].clone());
+        // a key of an inner node , the cursor goes to the end of the child before it
+        if !is_top {
+            let child = node.children[*i];
+            if let Err(error) = self.descend_right(child) {
+                return Some(Err(error));
+            }
+        }
+        Some(Ok(entry))
+    }
+
+    // the path to the start of the leftmost leaf below `page`
+    fn descend_left(&mut self, mut page: PageNum) -> Result<(), Error> {
+        loop {
+            let node = self.tree.load(page)?;
+            let child = node.children.first().copied();
+            let is_leaf = node.is_leaf;
+            self.stack.push((node, 0));
+            match child {
+                Some(child) if !is_leaf => page = child,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // the path to the end of the rightmost leaf below `page`
+    fn descend_right(&mut self, mut page: PageNum) -> Result<(), Error> {
+        loop {
+            let node = self.tree.load(page)?;
+            let end = node.keys.len();
+            let child = node.children.get(end).copied();
+            let is_leaf = node.is_leaf;
+            self.stack.push((node, end));
+            match child {
+                Some(child) if !is_leaf => page = child,
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+impl<K, V> Iterator for PagedCursor<'_, K, V>
+where
+    K: Ord + Clone + Codec,
+    V: Clone + Codec,
+{
+    type Item = Result<(K, V), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the nearest node on the path that has an entry after the cursor
+        let level = self
+            .stack
+            .iter()
+            .rposition(|(node, i)| *i < node.keys.len())?;
+        let is_top = level == self.stack.len() - 1;
+        self.stack.truncate(level + 1);
+        let (node, i) = &mut self.stack[level];
+        let entry = (node.keys[*i].clone(), node.values[*i].clone());
+        *i += 1;
+        // a key of an inner node , the cursor goes to the start of the child after it
+        if !is_top {
+            let child = node.children[*i];
+            if let Err(error) = self.descend_left(child) {
+                return Some(Err(error));
+            }
+        }
+        Some(Ok(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::testing::temp_path;
+    use std::collections::BTreeMap;
+
+    fn tree(name: &str) -> PagedBTree<i64, i64> {
+        let pager = Pager::open(temp_path(name)).unwrap();
+        PagedBTree::new(Rc::new(RefCell::new(pager)), None, 2)
+    }
+
+    #[test]
+    fn insert_replaces_a_key_that_is_there() {
+        let mut tree = tree("paged-duplicates");
+        for round in 0..3 {
+            for key in 0..200 {
+                tree.insert(key, key * 10 + round).unwrap();
+            }
+        }
+        let entries = tree.entries_in_order().unwrap();
+        assert_eq!(entries.len(), 200);
+        assert!(entries.iter().all(|&(key, value)| value == key * 10 + 2));
+        assert_eq!(tree.search(&7).unwrap(), Some(72));
+    }
+
+    // every node but the root has enough keys and every leaf is as deep as the others
+    fn leaf_depth(tree: &PagedBTree<i64, i64>, page: PageNum, is_root: bool) -> usize {
+        let node = tree.load(page).unwrap();
+        assert!(is_root || node.keys.len() >= tree.order - 1, "{:?}", node);
+        assert!(node.keys.windows(2).all(|pair| pair[0] < pair[1]));
+        if node.is_leaf {
+            return 0;
+        }
+        assert_eq!(node.children.len(), node.keys.len() + 1);
+        let depths: Vec<_> = node
+            .children
+            .iter()
+            .map(|child| leaf_depth(tree, *child, false))
+            .collect();
+        assert!(depths.windows(2).all(|pair| pair[0] == pair[1]));
+        depths[0] + 1
+    }
+
+    #[test]
+    fn bulk_load_gives_a_tree_that_keeps_working() {
+        for order in [2, 3] {
+            for count in [0i64, 1, 2, 3, 4, 5, 7, 8, 15, 16, 17, 63, 64, 65, 500] {
+                let pager = tree(&format!("paged-bulk-{}-{}", order, count)).pager;
+                let entries = (0..count).map(|key| (key * 2, key));
+                let mut tree = PagedBTree::from_sorted_iter(pager, order, entries.clone()).unwrap();
+                let mut map: BTreeMap<i64, i64> = entries.collect();
+                if let Some(root) = tree.root {
+                    leaf_depth(&tree, root, true);
+                }
+                assert_eq!(
+                    tree.entries_in_order().unwrap(),
+                    Vec::from_iter(map.clone())
+                );
+                for key in -1..count * 2 + 1 {
+                    assert_eq!(tree.search(&key).unwrap().as_ref(), map.get(&key));
+                }
+                // deleting and inserting afterwards needs every node to have enough keys
+                for key in (0..count * 2).step_by(3) {
+                    tree.delete(&key).unwrap();
+                    map.remove(&key);
+                }
+                for key in (0..count * 2).step_by(5) {
+                    tree.insert(key, -key).unwrap();
+                    map.insert(key, -key);
+                }
+                assert_eq!(tree.entries_in_order().unwrap(), Vec::from_iter(map));
+            }
+        }
+    }
+
+    #[test]
+    fn bulk_load_keeps_the_last_value_of_a_key() {
+        let pager = tree("paged-bulk-duplicates").pager;
+        let entries: Vec<(i64, i64)> = vec![(1, 1), (1, 2), (2, 3), (2, 4), (3, 5)];
+        let tree = PagedBTree::from_sorted_iter(pager, 2, entries).unwrap();
+        assert_eq!(
+            tree.entries_in_order().unwrap(),
+            vec![(1, 2), (2, 4), (3, 5)]
+        );
+    }
+
+    #[test]
+    fn cursor_walks_both_ways() {
+        let mut tree = tree("paged-cursor");
+        let keys: Vec<i64> = (0..400).map(|key| key * 3).collect();
+        for key in &keys {
+            tree.insert(*key, -key).unwrap();
+        }
+        let forward: Vec<_> = tree
+            .cursor()
+            .unwrap()
+            .map(|entry| entry.unwrap().0)
+            .collect();
+        assert_eq!(forward, keys);
+
+        let mut cursor = tree.cursor().unwrap();
+        cursor.move_to_end().unwrap();
+        let mut backward = Vec::new();
+        while let Some(entry) = cursor.prev() {
+            backward.push(entry.unwrap().0);
+        }
+        assert!(backward.into_iter().eq(keys.iter().rev().copied()));
+
+        for target in [-1, 0, 1, 299, 300, 301, 1197, 1198] {
+            let at = keys.partition_point(|key| *key < target);
+            cursor.seek(&target).unwrap();
+            assert_eq!(
+                cursor.next().map(|entry| entry.unwrap().0),
+                keys.get(at).copied()
+            );
+            // back over the entry next() gave (if any) and the one before it
+            if at < keys.len() {
+                cursor.prev();
+            }
+            let before = at.checked_sub(1).map(|at| keys[at]);
+            assert_eq!(cursor.prev().map(|entry| entry.unwrap().0), before);
+        }
+    }
+
+    #[test]
+    fn delete_and_range_keep_key_order() {
+        let mut tree = tree("paged-delete");
+        for key in (0..300).rev() {
+            tree.insert(key, -key).unwrap();
+        }
+        for key in (0..300).filter(|key| key % 3 == 0) {
+            tree.delete(&key).unwrap();
+        }
+        let expected: Vec<_> = (0..300)
+            .filter(|key| key % 3 != 0)
+            .map(|key| (key, -key))
+            .collect();
+        assert_eq!(tree.entries_in_order().unwrap(), expected);
+        let range = tree.entries_in_range(|key| {
+            if *key < 100 {
+                Ordering::Less
+            } else if *key > 120 {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        });
+        let expected: Vec<_> = expected
+            .into_iter()
+            .filter(|(key, _)| (100..=120).contains(key))
+            .collect();
+        assert_eq!(range.unwrap(), expected);
+        assert_eq!(tree.last_key().unwrap(), Some(299));
+    }
+
+    #[test]
+    fn a_committed_tree_is_there_after_reopening() {
+        let path = temp_path("paged-reopen");
+        let pager = Rc::new(RefCell::new(Pager::open(&path).unwrap()));
+        let mut tree = PagedBTree::new(Rc::clone(&pager), None, 2);
+        for key in 0..500i64 {
+            tree.insert(key * 7 % 500, key).unwrap();
+        }
+        let root = tree.root().unwrap();
+        pager.borrow_mut().commit().unwrap();
+
+        // left uncommitted , so gone after reopening
+        for key in 500..600i64 {
+            tree.insert(key, key).unwrap();
+        }
+        drop(tree);
+        drop(pager);
+
+        let pager = Rc::new(RefCell::new(Pager::open(&path).unwrap()));
+        let mut tree: PagedBTree<i64, i64> = PagedBTree::new(Rc::clone(&pager), Some(root), 2);
+        leaf_depth(&tree, root, true);
+        let entries = tree.entries_in_order().unwrap();
+        assert_eq!(entries.len(), 500);
+        assert!(entries.iter().all(|&(key, value)| value * 7 % 500 == key));
+
+        for key in 0..250i64 {
+            tree.delete(&key).unwrap();
+        }
+        let root = tree.root().unwrap();
+        pager.borrow_mut().commit().unwrap();
+        drop(tree);
+        drop(pager);
+
+        let pager = Rc::new(RefCell::new(Pager::open(&path).unwrap()));
+        let tree: PagedBTree<i64, i64> = PagedBTree::new(pager, Some(root), 2);
+        leaf_depth(&tree, root, true);
+        let keys: Vec<i64> = tree
+            .entries_in_order()
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, (250..500).collect::<Vec<_>>());
+    }
+}
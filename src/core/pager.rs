@@ -1,5 +1,729 @@
-// TODO: impl this
-#[allow(dead_code)]
-struct Pager {
-    index: usize,
+use crate::core::wal::Wal;
+use crate::error::Error;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub const PAGE_SIZE: usize = 4096;
+pub const FORMAT_VERSION: u32 = 9;
+const MAGIC: &[u8; 16] = b"Neoqlite format\0";
+
+// how many pages we keep around before throwing away the clean ones
+const CACHE_CAPACITY: usize = 1024;
+
+// frames in the WAL before a commit folds them back into the database file
+pub const DEFAULT_WAL_AUTOCHECKPOINT: u64 = 1000;
+
+// every page of a chain starts with the next page number and how many bytes
+// of payload this page holds
+const CHAIN_HEADER_SIZE: usize = 8;
+const CHAIN_PAYLOAD_SIZE: usize = PAGE_SIZE - CHAIN_HEADER_SIZE;
+
+pub type PageNum = u32;
+
+/*
+*   Everything needed to go back to the moment the savepoint was taken:
+*   the header fields and the old content of every page touched since then
+*   (plus whether that page was already dirty). Pages allocated past the old
+*   page count need nothing , they simply stop existing.
+*/
+#[derive(Debug)]
+struct PagerSavepoint {
+    header: (u32, PageNum, PageNum),
+    header_dirty: bool,
+    pages: HashMap<PageNum, (Vec<u8>, bool)>,
+}
+
+/*
+*   Page 0 is the file header:
+*
+*   0..16   magic "Neoqlite format\0"
+*   16..20  format version
+*   20..24  page size
+*   24..28  page count (header page included)
+*   28..32  first page of the freelist (0 if empty)
+*   32..36  first page of the schema chain (0 if there is no schema yet)
+*
+*   since page 0 is always the header , 0 doubles as the "no page" marker.
+*
+*   Pages are never written to the file directly , a commit appends them to the
+*   WAL and a checkpoint copies them over once the WAL gets too long (or when
+*   the pager is dropped).
+*/
+#[derive(Debug)]
+pub struct Pager {
+    file: File,
+    wal: Wal,
+    wal_autocheckpoint: u64,
+    page_count: u32,
+    freelist_head: PageNum,
+    schema_root: PageNum,
+    cache: HashMap<PageNum, Vec<u8>>,
+    dirty: HashSet<PageNum>,
+    header_dirty: bool,
+    // (page count , freelist head , schema root) as of the last commit
+    committed: (u32, PageNum, PageNum),
+    savepoints: Vec<PagerSavepoint>,
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+impl Pager {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let wal = Wal::open(path.as_ref())?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path.as_ref())
+            .map_err(|e| Error::Io(format!("Could not open {}: {}", path.as_ref().display(), e)))?;
+
+        let mut pager = Self {
+            file,
+            wal,
+            wal_autocheckpoint: DEFAULT_WAL_AUTOCHECKPOINT,
+            page_count: 1,
+            freelist_head: 0,
+            schema_root: 0,
+            cache: HashMap::new(),
+            dirty: HashSet::new(),
+            header_dirty: true,
+            committed: (1, 0, 0),
+            savepoints: Vec::new(),
+        };
+
+        // whatever is still in the WAL was committed before a crash
+        if !pager.wal.is_empty() {
+            pager.checkpoint()?;
+        }
+
+        let len = pager.file.metadata()?.len();
+        if len == 0 {
+            let header = pager.header_page();
+            pager.write_to_file(0, &header)?;
+            pager.file.sync_all()?;
+            pager.header_dirty = false;
+            pager.committed = (pager.page_count, pager.freelist_head, pager.schema_root);
+            return Ok(pager);
+        }
+
+        let mut header = vec![0u8; PAGE_SIZE];
+        pager.file.seek(SeekFrom::Start(0))?;
+        pager
+            .file
+            .read_exact(&mut header)
+            .map_err(|_| Error::Io("File is too small to be a neoqlite database".to_string()))?;
+        pager.load_header(&header)?;
+
+        if len < pager.page_count as u64 * PAGE_SIZE as u64 {
+            return Err(Error::Io(format!(
+                "Database file is truncated , header says {} pages but file has {} bytes",
+                pager.page_count, len
+            )));
+        }
+        Ok(pager)
+    }
+
+    fn load_header(&mut self, header: &[u8]) -> Result<(), Error> {
+        if &header[0..16] != MAGIC {
+            return Err(Error::Io("File is not a neoqlite database".to_string()));
+        }
+        let version = read_u32(header, 16);
+        if version != FORMAT_VERSION {
+            return Err(Error::Io(format!(
+                "Unsupported format version {} , expected {}",
+                version, FORMAT_VERSION
+            )));
+        }
+        let page_size = read_u32(header, 20);
+        if page_size as usize != PAGE_SIZE {
+            return Err(Error::Io(format!(
+                "Unsupported page size {} , expected {}",
+                page_size, PAGE_SIZE
+            )));
+        }
+        self.page_count = read_u32(header, 24);
+        self.freelist_head = read_u32(header, 28);
+        self.schema_root = read_u32(header, 32);
+        self.header_dirty = false;
+        self.committed = (self.page_count, self.freelist_head, self.schema_root);
+        Ok(())
+    }
+
+    fn header_page(&self) -> Vec<u8> {
+        let mut header = vec![0u8; PAGE_SIZE];
+        header[0..16].copy_from_slice(MAGIC);
+        write_u32(&mut header, 16, FORMAT_VERSION);
+        write_u32(&mut header, 20, PAGE_SIZE as u32);
+        write_u32(&mut header, 24, self.page_count);
+        write_u32(&mut header, 28, self.freelist_head);
+        write_u32(&mut header, 32, self.schema_root);
+        header
+    }
+
+    pub fn wal_autocheckpoint(&self) -> u64 {
+        self.wal_autocheckpoint
+    }
+
+    // 0 turns automatic checkpoints off
+    pub fn set_wal_autocheckpoint(&mut self, frames: u64) {
+        self.wal_autocheckpoint = frames;
+    }
+
+    pub fn page_count(&self) -> u32 {
+        self.page_count
+    }
+
+    pub fn schema_root(&self) -> PageNum {
+        self.schema_root
+    }
+
+    pub fn set_schema_root(&mut self, page: PageNum) {
+        self.schema_root = page;
+        self.header_dirty = true;
+    }
+
+    fn check_page(&self, page: PageNum) -> Result<(), Error> {
+        if page == 0 || page >= self.page_count {
+            return Err(Error::Io(format!(
+                "Page {} is out of bounds (page count is {})",
+                page, self.page_count
+            )));
+        }
+        Ok(())
+    }
+
+    fn load_page(&mut self, page: PageNum) -> Result<(), Error> {
+        self.check_page(page)?;
+        if self.cache.contains_key(&page) {
+            return Ok(());
+        }
+
+        if self.cache.len() >= CACHE_CAPACITY {
+            let dirty = &self.dirty;
+            self.cache.retain(|num, _| dirty.contains(num));
+        }
+
+        let buf = match self.wal.read_page(page)? {
+            Some(buf) => buf,
+            None => {
+                let mut buf = vec![0u8; PAGE_SIZE];
+                self.file
+                    .seek(SeekFrom::Start(page as u64 * PAGE_SIZE as u64))?;
+                self.file
+                    .read_exact(&mut buf)
+                    .map_err(|e| Error::Io(format!("Could not read page {}: {}", page, e)))?;
+                buf
+            }
+        };
+        self.cache.insert(page, buf);
+        Ok(())
+    }
+
+    pub fn get_page(&mut self, page: PageNum) -> Result<&[u8], Error> {
+        self.load_page(page)?;
+        Ok(&self.cache[&page])
+    }
+
+    pub fn get_page_mut(&mut self, page: PageNum) -> Result<&mut [u8], Error> {
+        self.load_page(page)?;
+        if let Some(savepoint) = self.savepoints.last_mut() {
+            if page < savepoint.header.0 && !savepoint.pages.contains_key(&page) {
+                let old = (self.cache[&page].clone(), self.dirty.contains(&page));
+                savepoint.pages.insert(page, old);
+            }
+        }
+        self.dirty.insert(page);
+        Ok(self.cache.get_mut(&page).unwrap())
+    }
+
+    pub fn allocate_page(&mut self) -> Result<PageNum, Error> {
+        if self.freelist_head != 0 {
+            let page = self.freelist_head;
+            let buf = self.get_page_mut(page)?;
+            let next = read_u32(buf, 0);
+            buf.fill(0);
+            self.freelist_head = next;
+            self.header_dirty = true;
+            return Ok(page);
+        }
+
+        let page = self.page_count;
+        self.page_count += 1;
+        self.header_dirty = true;
+        self.cache.insert(page, vec![0u8; PAGE_SIZE]);
+        self.dirty.insert(page);
+        Ok(page)
+    }
+
+    pub fn free_page(&mut self, page: PageNum) -> Result<(), Error> {
+        let next = self.freelist_head;
+        let buf = self.get_page_mut(page)?;
+        buf.fill(0);
+        write_u32(buf, 0, next);
+        self.freelist_head = page;
+        self.header_dirty = true;
+        Ok(())
+    }
+
+    /*
+     *   Stores `data` in a chain of pages starting at `first` , reusing the pages
+     *   that are already part of the chain , growing or shrinking it as needed.
+     *   If `first` is None a fresh chain is allocated.
+     *   Returns the first page of the chain.
+     */
+    pub fn write_chain(&mut self, first: Option<PageNum>, data: &[u8]) -> Result<PageNum, Error> {
+        let first = match first {
+            Some(page) => page,
+            None => self.allocate_page()?,
+        };
+
+        let mut chunks = data.chunks(CHAIN_PAYLOAD_SIZE).peekable();
+        let mut current = first;
+        loop {
+            let chunk = chunks.next().unwrap_or(&[]);
+            let old_next = read_u32(self.get_page(current)?, 0);
+
+            let next = if chunks.peek().is_some() {
+                if old_next != 0 {
+                    old_next
+                } else {
+                    self.allocate_page()?
+                }
+            } else {
+                if old_next != 0 {
+                    self.free_chain(old_next)?;
+                }
+                0
+            };
+
+            let buf = self.get_page_mut(current)?;
+            buf.fill(0);
+            write_u32(buf, 0, next);
+            write_u32(buf, 4, chunk.len() as u32);
+            buf[CHAIN_HEADER_SIZE..CHAIN_HEADER_SIZE + chunk.len()].copy_from_slice(chunk);
+
+            if next == 0 {
+                break;
+            }
+            current = next;
+        }
+        Ok(first)
+    }
+
+    pub fn read_chain(&mut self, first: PageNum) -> Result<Vec<u8>, Error> {
+        let mut result = Vec::new();
+        let mut current = first;
+        let mut visited = 0;
+        while current != 0 {
+            visited += 1;
+            if visited > self.page_count {
+                return Err(Error::Io(format!(
+                    "Page chain starting at {} has a cycle",
+                    first
+                )));
+            }
+            let buf = self.get_page(current)?;
+            let next = read_u32(buf, 0);
+            let len = read_u32(buf, 4) as usize;
+            if len > CHAIN_PAYLOAD_SIZE {
+                return Err(Error::Io(format!("Page {} is corrupted", current)));
+            }
+            result.extend_from_slice(&buf[CHAIN_HEADER_SIZE..CHAIN_HEADER_SIZE + len]);
+            current = next;
+        }
+        Ok(result)
+    }
+
+    pub fn free_chain(&mut self, first: PageNum) -> Result<(), Error> {
+        let mut current = first;
+        while current != 0 {
+            let next = read_u32(self.get_page(current)?, 0);
+            self.free_page(current)?;
+            current = next;
+        }
+        Ok(())
+    }
+
+    fn write_to_file(&mut self, page: PageNum, data: &[u8]) -> Result<(), Error> {
+        self.file
+            .seek(SeekFrom::Start(page as u64 * PAGE_SIZE as u64))?;
+        self.file
+            .write_all(data)
+            .map_err(|e| Error::Io(format!("Could not write page {}: {}", page, e)))
+    }
+
+    /*
+     *   Appends every dirty page (and the header if it changed) to the WAL as one
+     *   commit. Once this returns the changes survive a crash.
+     */
+    pub fn commit(&mut self) -> Result<(), Error> {
+        self.savepoints.clear();
+        if self.dirty.is_empty() && !self.header_dirty {
+            return Ok(());
+        }
+
+        let mut pages: Vec<PageNum> = self.dirty.drain().collect();
+        pages.sort();
+        let header = self.header_page();
+        let mut frames: Vec<(PageNum, &[u8])> = pages
+            .iter()
+            .map(|page| (*page, self.cache[page].as_slice()))
+            .collect();
+        // the header goes last , so its frame is the one marking the commit
+        frames.push((0, &header));
+        self.wal.append_commit(&frames, self.page_count)?;
+        self.header_dirty = false;
+        self.committed = (self.page_count, self.freelist_head, self.schema_root);
+
+        if self.wal_autocheckpoint != 0 && self.wal.frame_count() >= self.wal_autocheckpoint {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /*
+     *   Throws away every change since the last commit.
+     */
+    pub fn rollback(&mut self) {
+        let dirty = std::mem::take(&mut self.dirty);
+        self.cache.retain(|page, _| !dirty.contains(page));
+        (self.page_count, self.freelist_head, self.schema_root) = self.committed;
+        self.header_dirty = false;
+        self.savepoints.clear();
+    }
+
+    /*
+     *   Savepoints nest , the returned depth is what rollback_to and release
+     *   expect. They all go away on commit or rollback.
+     */
+    pub fn savepoint(&mut self) -> usize {
+        self.savepoints.push(PagerSavepoint {
+            header: (self.page_count, self.freelist_head, self.schema_root),
+            header_dirty: self.header_dirty,
+            pages: HashMap::new(),
+        });
+        self.savepoints.len() - 1
+    }
+
+    /*
+     *   Undoes every change made since savepoint `depth` was taken. The
+     *   savepoint itself stays around so it can be rolled back to again.
+     */
+    pub fn rollback_to(&mut self, depth: usize) {
+        if depth >= self.savepoints.len() {
+            return;
+        }
+        let header = self.savepoints[depth].header;
+        let header_dirty = self.savepoints[depth].header_dirty;
+
+        for savepoint in self.savepoints.drain(depth..).rev() {
+            for (page, (data, was_dirty)) in savepoint.pages {
+                self.cache.insert(page, data);
+                if was_dirty {
+                    self.dirty.insert(page);
+                } else {
+                    self.dirty.remove(&page);
+                }
+            }
+        }
+
+        let page_count = header.0;
+        self.cache.retain(|page, _| *page < page_count);
+        self.dirty.retain(|page| *page < page_count);
+        (self.page_count, self.freelist_head, self.schema_root) = header;
+        self.header_dirty = header_dirty;
+        self.savepoint();
+    }
+
+    /*
+     *   Forgets savepoint `depth` and the ones above it , keeping their changes.
+     *   The oldest copy of every page moves down to the enclosing savepoint.
+     */
+    pub fn release(&mut self, depth: usize) {
+        if depth >= self.savepoints.len() {
+            return;
+        }
+        let released: Vec<PagerSavepoint> = self.savepoints.drain(depth..).collect();
+        if let Some(parent) = self.savepoints.last_mut() {
+            for savepoint in released {
+                for (page, old) in savepoint.pages {
+                    if page < parent.header.0 && !parent.pages.contains_key(&page) {
+                        parent.pages.insert(page, old);
+                    }
+                }
+            }
+        }
+    }
+
+    /*
+     *   Copies every committed page from the WAL into the database file and
+     *   empties the WAL. Uncommitted (dirty) pages are left alone.
+     */
+    pub fn checkpoint(&mut self) -> Result<(), Error> {
+        for page in self.wal.pages() {
+            if let Some(data) = self.wal.read_page(page)? {
+                self.write_to_file(page, &data)?;
+            }
+        }
+        self.file.sync_all()?;
+        self.wal.reset()
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        // a clean shutdown leaves no WAL behind , if this fails the next open
+        // replays it anyway
+        if self.checkpoint().is_ok() {
+            let _ = std::fs::remove_file(self.wal.path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::testing::temp_path;
+
+    // bytes that are different on every position , so a page mixed up with
+    // another one shows
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    #[test]
+    fn a_new_file_gets_a_header() {
+        let path = temp_path("pager-new");
+        let pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.page_count(), 1);
+        assert_eq!(pager.schema_root(), 0);
+        drop(pager);
+
+        let pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.page_count(), 1);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), PAGE_SIZE as u64);
+    }
+
+    #[test]
+    fn the_header_is_there_after_reopening() {
+        let path = temp_path("pager-header");
+        let mut pager = Pager::open(&path).unwrap();
+        let page = pager.allocate_page().unwrap();
+        pager.get_page_mut(page).unwrap()[100] = 42;
+        pager.set_schema_root(page);
+        pager.commit().unwrap();
+        drop(pager);
+
+        let mut pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.page_count(), 2);
+        assert_eq!(pager.schema_root(), page);
+        assert_eq!(pager.get_page(page).unwrap()[100], 42);
+        assert!(pager.get_page(2).is_err());
+        assert!(pager.get_page(0).is_err());
+    }
+
+    #[test]
+    fn something_else_is_not_opened() {
+        let path = temp_path("pager-garbage");
+        std::fs::write(&path, vec![b'x'; PAGE_SIZE]).unwrap();
+        assert!(matches!(Pager::open(&path), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn chains_round_trip() {
+        let path = temp_path("pager-chain");
+        let mut pager = Pager::open(&path).unwrap();
+        let empty = pager.write_chain(None, &[]).unwrap();
+        let long = pager.write_chain(None, &payload(3 * PAGE_SIZE)).unwrap();
+        assert_eq!(pager.read_chain(empty).unwrap(), Vec::<u8>::new());
+        assert_eq!(pager.read_chain(long).unwrap(), payload(3 * PAGE_SIZE));
+        pager.commit().unwrap();
+        drop(pager);
+
+        let mut pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.read_chain(long).unwrap(), payload(3 * PAGE_SIZE));
+    }
+
+    #[test]
+    fn a_shrinking_chain_gives_its_pages_back() {
+        let path = temp_path("pager-shrink");
+        let mut pager = Pager::open(&path).unwrap();
+        let first = pager.write_chain(None, &payload(4 * PAGE_SIZE)).unwrap();
+        let page_count = pager.page_count();
+
+        assert_eq!(pager.write_chain(Some(first), &payload(10)).unwrap(), first);
+        assert_eq!(pager.read_chain(first).unwrap(), payload(10));
+
+        // the freed pages are used again before the file grows
+        let other = pager.write_chain(None, &payload(3 * PAGE_SIZE)).unwrap();
+        assert_eq!(pager.page_count(), page_count);
+        assert_eq!(pager.read_chain(other).unwrap(), payload(3 * PAGE_SIZE));
+        assert_eq!(pager.read_chain(first).unwrap(), payload(10));
+
+        pager.free_chain(other).unwrap();
+        pager.write_chain(None, &payload(PAGE_SIZE)).unwrap();
+        assert_eq!(pager.page_count(), page_count);
+    }
+
+    // a pager with one committed page holding `byte`
+    fn committed(path: &Path, byte: u8) -> (Pager, PageNum) {
+        let mut pager = Pager::open(path).unwrap();
+        pager.set_wal_autocheckpoint(0);
+        let page = pager.allocate_page().unwrap();
+        pager.get_page_mut(page).unwrap().fill(byte);
+        pager.commit().unwrap();
+        (pager, page)
+    }
+
+    fn wal_len(path: &Path) -> u64 {
+        std::fs::metadata(Wal::path_for(path)).unwrap().len()
+    }
+
+    #[test]
+    fn a_crash_after_the_commit_frame_keeps_the_commit() {
+        let path = temp_path("pager-crash-after");
+        let (mut pager, page) = committed(&path, 1);
+        pager.get_page_mut(page).unwrap().fill(2);
+        pager.allocate_page().unwrap();
+        // no drop , so no checkpoint either , as if the process died here
+        std::mem::forget(pager);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), PAGE_SIZE as u64);
+
+        let mut pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.page_count(), 2);
+        assert!(pager.get_page(page).unwrap().iter().all(|byte| *byte == 1));
+        // opening folded the log back into the file
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            2 * PAGE_SIZE as u64
+        );
+        assert!(wal_len(&path) < PAGE_SIZE as u64);
+    }
+
+    #[test]
+    fn a_crash_before_the_commit_frame_loses_the_commit() {
+        let path = temp_path("pager-crash-before");
+        let (mut pager, page) = committed(&path, 1);
+        let before = wal_len(&path);
+        pager.get_page_mut(page).unwrap().fill(2);
+        let other = pager.allocate_page().unwrap();
+        pager.get_page_mut(other).unwrap().fill(3);
+        pager.commit().unwrap();
+        std::mem::forget(pager);
+
+        // the header frame marking the second commit only made it halfway
+        let wal = OpenOptions::new()
+            .write(true)
+            .open(Wal::path_for(&path))
+            .unwrap();
+        wal.set_len(wal_len(&path) - PAGE_SIZE as u64 / 2).unwrap();
+        assert!(wal_len(&path) > before);
+
+        let mut pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.page_count(), 2);
+        assert!(pager.get_page(page).unwrap().iter().all(|byte| *byte == 1));
+        assert!(pager.get_page(other).is_err());
+    }
+
+    #[test]
+    fn checkpoint_moves_the_log_into_the_file() {
+        let path = temp_path("pager-checkpoint");
+        let (mut pager, page) = committed(&path, 1);
+        // committed pages are read back from the log until the checkpoint
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), PAGE_SIZE as u64);
+        assert!(pager.get_page(page).unwrap().iter().all(|byte| *byte == 1));
+
+        pager.get_page_mut(page).unwrap().fill(2);
+        pager.checkpoint().unwrap();
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            2 * PAGE_SIZE as u64
+        );
+        assert!(wal_len(&path) < PAGE_SIZE as u64);
+        // the uncommitted change is still pending and can still be thrown away
+        assert!(pager.get_page(page).unwrap().iter().all(|byte| *byte == 2));
+        pager.rollback();
+        std::mem::forget(pager);
+
+        std::fs::remove_file(Wal::path_for(&path)).unwrap();
+        let mut pager = Pager::open(&path).unwrap();
+        assert!(pager.get_page(page).unwrap().iter().all(|byte| *byte == 1));
+    }
+
+    #[test]
+    fn a_long_log_is_checkpointed_on_commit() {
+        let path = temp_path("pager-autocheckpoint");
+        let mut pager = Pager::open(&path).unwrap();
+        pager.set_wal_autocheckpoint(5);
+        let page = pager.allocate_page().unwrap();
+        for round in 0..3 {
+            pager.get_page_mut(page).unwrap().fill(round);
+            pager.commit().unwrap();
+        }
+        // two frames a commit (the page and the header) , so the third one went over
+        assert!(wal_len(&path) < PAGE_SIZE as u64);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            2 * PAGE_SIZE as u64
+        );
+    }
+
+    #[test]
+    fn dropping_the_pager_leaves_no_log() {
+        let path = temp_path("pager-drop");
+        let (pager, page) = committed(&path, 7);
+        drop(pager);
+        assert!(!Wal::path_for(&path).exists());
+
+        let mut pager = Pager::open(&path).unwrap();
+        assert!(pager.get_page(page).unwrap().iter().all(|byte| *byte == 7));
+    }
+
+    #[test]
+    fn savepoints_put_pages_and_the_header_back() {
+        let path = temp_path("pager-savepoints");
+        let (mut pager, page) = committed(&path, 1);
+
+        pager.get_page_mut(page).unwrap().fill(2);
+        let outer = pager.savepoint();
+        pager.get_page_mut(page).unwrap().fill(3);
+        let inner = pager.savepoint();
+        let chain = pager.write_chain(None, &payload(2 * PAGE_SIZE)).unwrap();
+        pager.set_schema_root(chain);
+        assert_eq!(pager.page_count(), 5);
+
+        pager.rollback_to(inner);
+        assert_eq!(pager.page_count(), 2);
+        assert_eq!(pager.schema_root(), 0);
+        assert!(pager.get_page(page).unwrap().iter().all(|byte| *byte == 3));
+
+        // a released savepoint hands its old pages to the one around it
+        pager.get_page_mut(page).unwrap().fill(4);
+        pager.release(inner);
+        pager.rollback_to(outer);
+        assert!(pager.get_page(page).unwrap().iter().all(|byte| *byte == 2));
+
+        pager.rollback();
+        assert!(pager.get_page(page).unwrap().iter().all(|byte| *byte == 1));
+
+        pager.get_page_mut(page).unwrap().fill(5);
+        let savepoint = pager.savepoint();
+        pager.allocate_page().unwrap();
+        pager.release(savepoint);
+        pager.commit().unwrap();
+        drop(pager);
+
+        let mut pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.page_count(), 3);
+        assert!(pager.get_page(page).unwrap().iter().all(|byte| *byte == 5));
+    }
 }
@@ -0,0 +1,267 @@
+use crate::core::db::DataType;
+use crate::error::Error;
+use std::rc::Rc;
+
+/*
+*   What a statement gives back. Column names come in the order they were
+*   asked for , `*` expands to the columns in schema order. Statements that
+*   dont return anything give an empty Rows with no columns.
+*
+*   let rows = neoqlite.query("select id , email from users;")?;
+*   for row in &rows {
+*       let id: i64 = row.get("id")?;
+*   }
+*/
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rows {
+    columns: Rc<Vec<String>>,
+    rows: Vec<Row>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    // shared with every other row of the same Rows
+    columns: Rc<Vec<String>>,
+    values: Vec<DataType>,
+}
+
+impl Rows {
+    pub fn new(columns: Vec<String>, rows: Vec<Vec<DataType>>) -> Self {
+        let columns = Rc::new(columns);
+        let rows = rows
+            .into_iter()
+            .map(|values| Row {
+                columns: columns.clone(),
+                values,
+            })
+            .collect();
+        Self { columns, rows }
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Row> {
+        self.rows.get(index)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Row> {
+        self.rows.iter()
+    }
+}
+
+impl IntoIterator for Rows {
+    type Item = Row;
+    type IntoIter = std::vec::IntoIter<Row>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rows.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Rows {
+    type Item = &'a Row;
+    type IntoIter = std::slice::Iter<'a, Row>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rows.iter()
+    }
+}
+
+impl Row {
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn values(&self) -> &[DataType] {
+        &self.values
+    }
+
+    // the raw value , DataType::Null for NULL
+    pub fn value(&self, column: &str) -> Result<&DataType, Error> {
+        let index = self
+            .columns
+            .iter()
+            .position(|name| name == column)
+            .ok_or_else(|| Error::NotFound(format!("No such column: {}", column)))?;
+        Ok(&self.values[index])
+    }
+
+    /*
+     *   The value of `column` as a T , asking for the wrong type or reading a
+     *   NULL (use Option<T> if it can be NULL) is an error.
+     */
+    pub fn get<T: FromValue>(&self, column: &str) -> Result<T, Error> {
+        let value = self.value(column)?;
+        T::from_value(value).ok_or_else(|| match value {
+            DataType::Null => Error::Schema(format!("Column {} is NULL", column)),
+            value => Error::Schema(format!(
+                "Column {} holds {:?} , not a {}",
+                column,
+                value,
+                std::any::type_name::<T>()
+            )),
+        })
+    }
+}
+
+// Rust types a value can be read as , None if it doesnt fit
+pub trait FromValue: Sized {
+    fn from_value(value: &DataType) -> Option<Self>;
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &DataType) -> Option<Self> {
+        match value {
+            DataType::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &DataType) -> Option<Self> {
+        match value {
+            DataType::Text(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &DataType) -> Option<Self> {
+        match value {
+            DataType::Real(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &DataType) -> Option<Self> {
+        match value {
+            DataType::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(value: &DataType) -> Option<Self> {
+        match value {
+            DataType::Blob(bytes) => Some(bytes.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for DataType {
+    fn from_value(value: &DataType) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+// NULL is None
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &DataType) -> Option<Self> {
+        match value {
+            DataType::Null => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::db::Neoqlite;
+
+    fn people() -> Neoqlite {
+        let mut db = Neoqlite::new();
+        for sql in [
+            "create table people (id int primary key, email text, score real, active boolean);",
+            "insert into people (id, email, score, active) values (1, 'a@x', 1.5, TRUE);",
+            "insert into people (id, score) values (2, 2.5);",
+        ] {
+            db.query(sql).unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn columns_come_in_the_order_asked_for() {
+        let mut db = people();
+        let rows = db.query("select score, id as key from people;").unwrap();
+        assert_eq!(rows.columns(), ["score", "key"]);
+        assert_eq!(rows.get(0).unwrap().columns(), ["score", "key"]);
+
+        let rows = db.query("select * from people;").unwrap();
+        assert_eq!(rows.columns(), ["id", "email", "score", "active"]);
+
+        // statements that give nothing back
+        let rows = db.query("delete from people where id = 5;").unwrap();
+        assert!(rows.is_empty());
+        assert!(rows.columns().is_empty());
+    }
+
+    #[test]
+    fn rows_by_index_and_values_by_name() {
+        let mut db = people();
+        let rows = db.query("select * from people;").unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.get(2).is_none());
+
+        let first = rows.get(0).unwrap();
+        assert_eq!(first.get::<i64>("id").unwrap(), 1);
+        assert_eq!(first.get::<String>("email").unwrap(), "a@x");
+        assert_eq!(first.get::<f64>("score").unwrap(), 1.5);
+        assert!(first.get::<bool>("active").unwrap());
+        assert_eq!(first.value("id").unwrap(), &DataType::Int(1));
+        assert_eq!(
+            first.values(),
+            [
+                DataType::Int(1),
+                DataType::Text("a@x".to_string()),
+                DataType::Real(1.5),
+                DataType::Boolean(true),
+            ]
+        );
+
+        let second = rows.get(1).unwrap();
+        assert_eq!(second.get::<Option<String>>("email").unwrap(), None);
+        assert_eq!(second.get::<DataType>("email").unwrap(), DataType::Null);
+        assert!(matches!(
+            second.get::<String>("email"),
+            Err(Error::Schema(_))
+        ));
+        assert!(matches!(second.get::<String>("id"), Err(Error::Schema(_))));
+        assert!(matches!(
+            second.get::<i64>("nothing"),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn iterating_gives_every_row_in_order() {
+        let mut db = people();
+        let rows = db.query("select id from people order by id desc;").unwrap();
+        let by_ref: Vec<i64> = (&rows)
+            .into_iter()
+            .map(|row| row.get("id").unwrap())
+            .collect();
+        let by_iter: Vec<i64> = rows.iter().map(|row| row.get("id").unwrap()).collect();
+        let owned: Vec<i64> = rows.into_iter().map(|row| row.get("id").unwrap()).collect();
+        assert_eq!(by_ref, [2, 1]);
+        assert_eq!(by_iter, by_ref);
+        assert_eq!(owned, by_ref);
+    }
+}
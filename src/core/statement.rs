@@ -0,0 +1,262 @@
+use crate::core::db::DataType;
+use crate::error::Error;
+use crate::parser::parser::{Expr, Query, SelectItem};
+
+/*
+*   A statement parsed once and run as many times as we like with different
+*   values for its parameters:
+*
+*   let mut stmt = neoqlite.prepare("insert into users(id,email) values(?, :email);")?;
+*   stmt.bind(1, 7)?;
+*   stmt.bind_named(":email", "x@email.com")?;
+*   neoqlite.execute(&stmt)?;
+*
+*   Bound values never go through the lexer , so there is nothing to escape.
+*/
+#[derive(Debug, Clone)]
+pub struct Statement {
+    query: Query,
+    // parameter n is at n - 1 , the name for :name parameters
+    parameters: Vec<Option<String>>,
+    values: Vec<Option<DataType>>,
+}
+
+impl Statement {
+    pub fn new(query: Query, parameters: Vec<Option<String>>) -> Self {
+        let values = vec![None; parameters.len()];
+        Self {
+            query,
+            parameters,
+            values,
+        }
+    }
+
+    pub fn parameter_count(&self) -> usize {
+        self.parameters.len()
+    }
+
+    // `name` with or without the ':'
+    pub fn parameter_index(&self, name: &str) -> Option<usize> {
+        let name = name.strip_prefix(':').unwrap_or(name);
+        self.parameters
+            .iter()
+            .position(|p| p.as_deref() == Some(name))
+            .map(|i| i + 1)
+    }
+
+    // parameters are numbered from 1
+    pub fn bind(&mut self, index: usize, value: impl Into<DataType>) -> Result<(), Error> {
+        if index == 0 || index > self.values.len() {
+            return Err(Error::NotFound(format!(
+                "No parameter ?{} , the statement has {}",
+                index,
+                self.values.len()
+            )));
+        }
+        self.values[index - 1] = Some(value.into());
+        Ok(())
+    }
+
+    pub fn bind_named(&mut self, name: &str, value: impl Into<DataType>) -> Result<(), Error> {
+        let index = self
+            .parameter_index(name)
+            .ok_or_else(|| Error::NotFound(format!("No parameter named {}", name)))?;
+        self.bind(index, value)
+    }
+
+    pub fn clear_bindings(&mut self) {
+        self.values.iter_mut().for_each(|value| *value = None);
+    }
+
+    // the statement with every parameter replaced by its value
+    pub fn bound_query(&self) -> Result<Query, Error> {
+        let mut query = self.query.clone();
+        for_each_expr(&mut query, &mut |expr| bind_expr(expr, &self.values))?;
+        Ok(query)
+    }
+}
+
+// the expression that evaluates to `value`
+pub fn literal(value: &DataType) -> Expr {
+    match value {
+        DataType::Int(n) => Expr::Number(*n),
+        DataType::Text(s) => Expr::String(s.clone()),
+        DataType::Real(n) => Expr::Real(*n),
+        DataType::Boolean(b) => Expr::Boolean(*b),
+        DataType::Blob(bytes) => Expr::Blob(bytes.clone()),
+        DataType::Null => Expr::Null,
+        // the column or the comparison reads the text back as a date
+        DataType::Date(_) | DataType::Time(_) | DataType::Timestamp(_) => {
+            Expr::String(value.to_string())
+        }
+    }
+}
+
+fn bind_expr(expr: &mut Expr, values: &[Option<DataType>]) -> Result<(), Error> {
+    match expr {
+        Expr::Parameter(i) => match values.get(*i - 1).and_then(|v| v.as_ref()) {
+            Some(value) => *expr = literal(value),
+            None => return Err(Error::Execution(format!("Parameter ?{} is not bound", i))),
+        },
+        Expr::Binary { left, right, .. } => {
+            bind_expr(left, values)?;
+            bind_expr(right, values)?;
+        }
+        Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Nested(expr) => {
+            bind_expr(expr, values)?
+        }
+        Expr::Function { args, .. } => {
+            for arg in args {
+                bind_expr(arg, values)?;
+            }
+        }
+        Expr::Between {
+            expr, low, high, ..
+        } => {
+            bind_expr(expr, values)?;
+            bind_expr(low, values)?;
+            bind_expr(high, values)?;
+        }
+        Expr::Ident(_)
+        | Expr::String(_)
+        | Expr::Number(_)
+        | Expr::Real(_)
+        | Expr::Boolean(_)
+        | Expr::Blob(_)
+        | Expr::Null => {}
+    }
+    Ok(())
+}
+
+// calls `f` on the top of every expression in the statement
+fn for_each_expr(
+    query: &mut Query,
+    f: &mut impl FnMut(&mut Expr) -> Result<(), Error>,
+) -> Result<(), Error> {
+    match query {
+        Query::Select(query) => {
+            for item in &mut query.columns {
+                if let SelectItem::Expr { expr, .. } = item {
+                    f(expr)?;
+                }
+            }
+            query.where_clause.iter_mut().try_for_each(&mut *f)?;
+            query.order_by.iter_mut().try_for_each(|(expr, _)| f(expr))
+        }
+        Query::Insert(query) => query.values.iter_mut().try_for_each(f),
+        Query::Update(query) => {
+            for (_, expr) in &mut query.assignments {
+                f(expr)?;
+            }
+            query.where_clause.iter_mut().try_for_each(f)
+        }
+        Query::Delete(query) => query.where_clause.iter_mut().try_for_each(f),
+        Query::Pragma(query) => query.value.iter_mut().try_for_each(f),
+        Query::CreateTable(_)
+        | Query::DropTable(_)
+        | Query::AlterTable(_)
+        | Query::CreateIndex(_)
+        | Query::DropIndex(_)
+        | Query::Transaction(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::db::Neoqlite;
+
+    fn db() -> Neoqlite {
+        let mut db = Neoqlite::new();
+        db.query("create table t (id int primary key, name text);")
+            .unwrap();
+        db
+    }
+
+    fn names(db: &mut Neoqlite) -> Vec<String> {
+        let rows = db.query("select name from t;").unwrap();
+        rows.iter().map(|row| row.get("name").unwrap()).collect()
+    }
+
+    #[test]
+    fn parameters_are_numbered_like_sqlite() {
+        let db = db();
+        let stmt = db
+            .prepare("select * from t where id = ? or id = ?5 or id = ? or name = :name;")
+            .unwrap();
+        // ? is one past the largest number so far , so the second one is 6
+        assert_eq!(stmt.parameter_count(), 7);
+        assert_eq!(stmt.parameter_index(":name"), Some(7));
+        assert_eq!(stmt.parameter_index("name"), Some(7));
+        assert_eq!(stmt.parameter_index(":other"), None);
+
+        // a name used twice is one parameter
+        let stmt = db
+            .prepare("select * from t where name = :a or id = ? or name = :a;")
+            .unwrap();
+        assert_eq!(stmt.parameter_count(), 2);
+        assert_eq!(stmt.parameter_index(":a"), Some(1));
+    }
+
+    #[test]
+    fn bound_values_are_used_as_they_are() {
+        let mut db = db();
+        let mut stmt = db
+            .prepare("insert into t (id, name) values (?1, :name);")
+            .unwrap();
+        stmt.bind(1, 1).unwrap();
+        stmt.bind_named(":name", "it's'); drop table t; --")
+            .unwrap();
+        db.execute(&stmt).unwrap();
+
+        // bound again and run again , the statement is parsed once
+        stmt.bind(1, 2).unwrap();
+        stmt.bind_named("name", "b").unwrap();
+        db.execute(&stmt).unwrap();
+
+        assert_eq!(names(&mut db), ["it's'); drop table t; --", "b"]);
+    }
+
+    #[test]
+    fn every_parameter_has_to_be_bound() {
+        let mut db = db();
+        let mut stmt = db
+            .prepare("insert into t (id, name) values (?, ?);")
+            .unwrap();
+        stmt.bind(1, 1).unwrap();
+        assert_eq!(
+            db.execute(&stmt),
+            Err(Error::Execution("Parameter ?2 is not bound".to_string()))
+        );
+
+        stmt.bind(2, "a").unwrap();
+        db.execute(&stmt).unwrap();
+        stmt.clear_bindings();
+        assert_eq!(
+            db.execute(&stmt),
+            Err(Error::Execution("Parameter ?1 is not bound".to_string()))
+        );
+
+        assert!(matches!(stmt.bind(0, 1), Err(Error::NotFound(_))));
+        assert!(matches!(stmt.bind(3, 1), Err(Error::NotFound(_))));
+        assert!(matches!(stmt.bind_named(":x", 1), Err(Error::NotFound(_))));
+        assert_eq!(names(&mut db), ["a"]);
+    }
+
+    #[test]
+    fn parameter_numbers_stop_at_32766() {
+        let db = db();
+        let stmt = db.prepare("select * from t where id = ?32766;").unwrap();
+        assert_eq!(stmt.parameter_count(), 32766);
+        for sql in [
+            "select * from t where id = ?32767;",
+            "select * from t where id = ?0;",
+        ] {
+            assert!(
+                matches!(db.prepare(sql), Err(Error::Parse { .. })),
+                "{}",
+                sql
+            );
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use crate::core::pager::{PageNum, PAGE_SIZE};
+use crate::error::Error;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const WAL_MAGIC: &[u8; 8] = b"NeoqWAL\0";
+const WAL_VERSION: u32 = 2;
+const WAL_HEADER_SIZE: u64 = 24;
+const FRAME_HEADER_SIZE: u64 = 24;
+const FRAME_SIZE: u64 = FRAME_HEADER_SIZE + PAGE_SIZE as u64;
+
+/*
+*   Write-ahead log , lives next to the database in "<path>-wal".
+*
+*   Header:
+*   0..8    magic "NeoqWAL\0"
+*   8..12   version
+*   12..16  page size
+*   16..20  salt , changes every time the log is reset
+*
+*   Then a list of frames , each one is a copy of a page:
+*   0..4    page number
+*   4..8    page count of the database if this frame ends a commit , 0 otherwise
+*   8..12   salt (has to match the header)
+*   12..16  unused
+*   16..24  checksum of the first 12 bytes and the page
+*   24..    the page
+*
+*   Only frames up to the last commit frame count , anything after that is a
+*   commit that never finished (we crashed while writing it) and is ignored.
+*
+*   Like in sqlite the checksums are cumulative , every frame's starts from the
+*   one of the frame before it (the first from the salt). A frame left over
+*   from an older , longer log can have the right salt and a checksum that
+*   fits its own bytes , but not one that carries on from the frames written
+*   over the ones before it , so recovery stops there.
+*/
+#[derive(Debug)]
+pub struct Wal {
+    file: File,
+    path: PathBuf,
+    salt: u32,
+    // where the latest committed copy of every page is
+    index: HashMap<PageNum, u64>,
+    // committed frames in the log , new frames go right after them
+    frame_count: u64,
+    // the checksum of the last committed frame , the next frame starts from it
+    last_checksum: u64,
+}
+
+// FNV-1a , not cryptographic , only here to spot torn writes and stale frames
+fn checksum(seed: u64, header: &[u8], page: &[u8]) -> u64 {
+    let mut hash = seed;
+    for byte in header.iter().chain(page.iter()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// what the checksum of the first frame starts from
+fn first_seed(salt: u32) -> u64 {
+    checksum(0xcbf29ce484222325, &salt.to_le_bytes(), &[])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn io_err(e: std::io::Error) -> Error {
+    Error::Io(format!("WAL: {}", e))
+}
+
+impl Wal {
+    pub fn path_for(db_path: &Path) -> PathBuf {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push("-wal");
+        PathBuf::from(path)
+    }
+
+    /*
+     *   Opens the log of `db_path` and finds every committed frame in it.
+     *   Nothing is applied to the database here , that is the pager's job.
+     */
+    pub fn open(db_path: &Path) -> Result<Self, Error> {
+        let path = Self::path_for(db_path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| Error::Io(format!("Could not open {}: {}", path.display(), e)))?;
+
+        let mut wal = Self {
+            file,
+            path,
+            salt: 0,
+            index: HashMap::new(),
+            frame_count: 0,
+            last_checksum: 0,
+        };
+
+        let len = wal.file.metadata().map_err(io_err)?.len();
+        if len < WAL_HEADER_SIZE {
+            wal.reset()?;
+            return Ok(wal);
+        }
+
+        let mut header = [0u8; WAL_HEADER_SIZE as usize];
+        wal.file.seek(SeekFrom::Start(0)).map_err(io_err)?;
+        wal.file.read_exact(&mut header).map_err(io_err)?;
+        if &header[0..8] != WAL_MAGIC
+            || read_u32(&header, 8) != WAL_VERSION
+            || read_u32(&header, 12) as usize != PAGE_SIZE
+        {
+            return Err(Error::Io(format!(
+                "{} is not a neoqlite WAL",
+                wal.path.display()
+            )));
+        }
+        wal.salt = read_u32(&header, 16);
+        wal.last_checksum = first_seed(wal.salt);
+        wal.recover(len)?;
+        Ok(wal)
+    }
+
+    fn recover(&mut self, len: u64) -> Result<(), Error> {
+        let mut pending: Vec<(PageNum, u64)> = Vec::new();
+        let mut frame = vec![0u8; FRAME_SIZE as usize];
+        let mut offset = WAL_HEADER_SIZE;
+        let mut frames = 0;
+        let mut sum = self.last_checksum;
+
+        while offset + FRAME_SIZE <= len {
+            self.file.seek(SeekFrom::Start(offset)).map_err(io_err)?;
+            self.file.read_exact(&mut frame).map_err(io_err)?;
+
+            let (header, page) = frame.split_at(FRAME_HEADER_SIZE as usize);
+            let stored = u64::from_le_bytes(header[16..24].try_into().unwrap());
+            sum = checksum(sum, &header[0..12], page);
+            if read_u32(header, 8) != self.salt || sum != stored {
+                break;
+            }
+
+            frames += 1;
+            pending.push((read_u32(header, 0), offset));
+            if read_u32(header, 4) != 0 {
+                self.index.extend(pending.drain(..));
+                self.frame_count = frames;
+                self.last_checksum = sum;
+            }
+            offset += FRAME_SIZE;
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    // every page that has a committed copy in the log
+    pub fn pages(&self) -> Vec<PageNum> {
+        let mut pages: Vec<PageNum> = self.index.keys().copied().collect();
+        pages.sort();
+        pages
+    }
+
+    pub fn read_page(&mut self, page: PageNum) -> Result<Option<Vec<u8>>, Error> {
+        let offset = match self.index.get(&page) {
+            Some(offset) => *offset,
+            None => return Ok(None),
+        };
+        let mut buf = vec![0u8; PAGE_SIZE];
+        self.file
+            .seek(SeekFrom::Start(offset + FRAME_HEADER_SIZE))
+            .map_err(io_err)?;
+        self.file.read_exact(&mut buf).map_err(io_err)?;
+        Ok(Some(buf))
+    }
+
+    /*
+     *   Appends one commit , the last frame gets the commit marker and the log
+     *   is synced before we return so the commit is durable.
+     */
+    pub fn append_commit(
+        &mut self,
+        pages: &[(PageNum, &[u8])],
+        page_count: u32,
+    ) -> Result<(), Error> {
+        let start = WAL_HEADER_SIZE + self.frame_count * FRAME_SIZE;
+        let mut buf = Vec::with_capacity(pages.len() * FRAME_SIZE as usize);
+        let mut sum = self.last_checksum;
+        for (i, (page, data)) in pages.iter().enumerate() {
+            let commit = if i == pages.len() - 1 { page_count } else { 0 };
+            let mut header = [0u8; FRAME_HEADER_SIZE as usize];
+            header[0..4].copy_from_slice(&page.to_le_bytes());
+            header[4..8].copy_from_slice(&commit.to_le_bytes());
+            header[8..12].copy_from_slice(&self.salt.to_le_bytes());
+            sum = checksum(sum, &header[0..12], data);
+            header[16..24].copy_from_slice(&sum.to_le_bytes());
+            buf.extend_from_slice(&header);
+            buf.extend_from_slice(data);
+        }
+
+        self.file.seek(SeekFrom::Start(start)).map_err(io_err)?;
+        self.file.write_all(&buf).map_err(io_err)?;
+        self.file.sync_data().map_err(io_err)?;
+
+        for (i, (page, _)) in pages.iter().enumerate() {
+            self.index.insert(*page, start + i as u64 * FRAME_SIZE);
+        }
+        self.frame_count += pages.len() as u64;
+        self.last_checksum = sum;
+        Ok(())
+    }
+
+    /*
+     *   Throws every frame away , only call this once the pages are safely in
+     *   the database file.
+     */
+    pub fn reset(&mut self) -> Result<(), Error> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        self.salt = self.salt.wrapping_add(1) ^ nanos;
+
+        let mut header = [0u8; WAL_HEADER_SIZE as usize];
+        header[0..8].copy_from_slice(WAL_MAGIC);
+        header[8..12].copy_from_slice(&WAL_VERSION.to_le_bytes());
+        header[12..16].copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+        header[16..20].copy_from_slice(&self.salt.to_le_bytes());
+
+        self.file.set_len(0).map_err(io_err)?;
+        self.file.seek(SeekFrom::Start(0)).map_err(io_err)?;
+        self.file.write_all(&header).map_err(io_err)?;
+        self.file.sync_all().map_err(io_err)?;
+
+        self.index.clear();
+        self.frame_count = 0;
+        self.last_checksum = first_seed(self.salt);
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::testing::temp_path;
+
+    fn page(byte: u8) -> Vec<u8> {
+        vec![byte; PAGE_SIZE]
+    }
+
+    fn commit(wal: &mut Wal, pages: &[(PageNum, u8)]) {
+        let data: Vec<(PageNum, Vec<u8>)> = pages.iter().map(|(n, b)| (*n, page(*b))).collect();
+        let frames: Vec<(PageNum, &[u8])> = data.iter().map(|(n, p)| (*n, p.as_slice())).collect();
+        wal.append_commit(&frames, 8).unwrap();
+    }
+
+    // overwrites one byte of a frame's page , as a torn write would
+    fn corrupt(db: &Path, frame: u64) {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(Wal::path_for(db))
+            .unwrap();
+        let offset = WAL_HEADER_SIZE + frame * FRAME_SIZE + FRAME_HEADER_SIZE + 10;
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&[0xee]).unwrap();
+    }
+
+    #[test]
+    fn commits_are_there_after_reopening() {
+        let path = temp_path("wal-reopen");
+        let mut wal = Wal::open(&path).unwrap();
+        commit(&mut wal, &[(1, 1), (2, 2)]);
+        commit(&mut wal, &[(2, 3)]);
+        drop(wal);
+
+        let mut wal = Wal::open(&path).unwrap();
+        assert_eq!(wal.frame_count(), 3);
+        assert_eq!(wal.pages(), vec![1, 2]);
+        assert_eq!(wal.read_page(1).unwrap(), Some(page(1)));
+        assert_eq!(wal.read_page(2).unwrap(), Some(page(3)));
+        assert_eq!(wal.read_page(3).unwrap(), None);
+    }
+
+    #[test]
+    fn a_torn_commit_is_left_out() {
+        let path = temp_path("wal-torn");
+        let mut wal = Wal::open(&path).unwrap();
+        commit(&mut wal, &[(1, 1)]);
+        commit(&mut wal, &[(1, 2), (2, 2)]);
+        drop(wal);
+        corrupt(&path, 2);
+
+        let mut wal = Wal::open(&path).unwrap();
+        assert_eq!(wal.frame_count(), 1);
+        assert_eq!(wal.pages(), vec![1]);
+        assert_eq!(wal.read_page(1).unwrap(), Some(page(1)));
+
+        // the next commit goes over the torn one and is found again
+        commit(&mut wal, &[(3, 3)]);
+        drop(wal);
+        let wal = Wal::open(&path).unwrap();
+        assert_eq!(wal.pages(), vec![1, 3]);
+    }
+
+    #[test]
+    fn frames_of_an_older_log_are_never_replayed() {
+        let path = temp_path("wal-stale");
+        let mut wal = Wal::open(&path).unwrap();
+        commit(&mut wal, &[(5, 5), (6, 6), (7, 7)]);
+        drop(wal);
+        corrupt(&path, 0);
+
+        // nothing committed is left , the new commit goes over frames 0 and 1
+        // and frame 2 still has the same salt and a checksum of its own
+        let mut wal = Wal::open(&path).unwrap();
+        assert!(wal.is_empty());
+        commit(&mut wal, &[(1, 1), (2, 2)]);
+        drop(wal);
+
+        let wal = Wal::open(&path).unwrap();
+        assert_eq!(wal.pages(), vec![1, 2]);
+        assert_eq!(wal.frame_count(), 2);
+    }
+
+    #[test]
+    fn reset_empties_the_log() {
+        let path = temp_path("wal-reset");
+        let mut wal = Wal::open(&path).unwrap();
+        commit(&mut wal, &[(1, 1)]);
+        wal.reset().unwrap();
+        assert!(wal.is_empty());
+        commit(&mut wal, &[(2, 2)]);
+        drop(wal);
+
+        let wal = Wal::open(&path).unwrap();
+        assert_eq!(wal.pages(), vec![2]);
+    }
+}
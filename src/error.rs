@@ -0,0 +1,227 @@
+use std::fmt;
+
+/*
+*   Where something is in the statement , `start..end` are byte offsets and
+*   line / column (both start at 1) are where `start` is.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(input: &str, start: usize, end: usize) -> Self {
+        let before = &input[..start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Self {
+            start,
+            end,
+            line,
+            column: input[line_start..start].chars().count() + 1,
+        }
+    }
+
+    /*
+     *   The line of `input` the span starts on with the span underlined , for
+     *   showing errors:
+     *
+     *   select * form users;
+     *            ^^^^
+     */
+    pub fn underline(&self, input: &str) -> String {
+        let line = input.lines().nth(self.line - 1).unwrap_or("");
+        let line_start = input[..self.start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = line_start + line.len();
+        let width = input[self.start.min(line_end)..self.end.clamp(self.start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+        format!(
+            "{}\n{}{}",
+            line,
+            " ".repeat(self.column - 1),
+            "^".repeat(width)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    // a character the lexer cant make sense of , or a quote that never ends
+    Lexer { message: String, span: Span },
+    // tokens that dont make a statement
+    Parse { message: String, span: Span },
+    // values and columns that dont fit the table , like text in an int column
+    Schema(String),
+    // a row that breaks NOT NULL , the primary key , ...
+    Constraint(String),
+    // reading or writing the database file , a corrupted file is one too
+    Io(String),
+    // a table , column , savepoint or function that doesnt exist
+    NotFound(String),
+    // a statement that cant run , like COMMIT without BEGIN or an overflow
+    Execution(String),
+}
+
+impl Error {
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Error::Lexer { span, .. } | Error::Parse { span, .. } => Some(span),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Lexer { message, span } | Error::Parse { message, span } => write!(
+                f,
+                "{} at line {}, column {}",
+                message, span.line, span.column
+            ),
+            Error::Schema(message)
+            | Error::Constraint(message)
+            | Error::Io(message)
+            | Error::NotFound(message)
+            | Error::Execution(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::db::Neoqlite;
+
+    fn failure(db: &mut Neoqlite, sql: &str) -> Error {
+        match db.query(sql) {
+            Ok(_) => panic!("{} went through", sql),
+            Err(error) => error,
+        }
+    }
+
+    // the underline of an error's span , and its line / column
+    fn pointed_at(error: &Error, sql: &str) -> (String, usize, usize) {
+        let span = error
+            .span()
+            .unwrap_or_else(|| panic!("{:?} has no span", error));
+        (span.underline(sql), span.line, span.column)
+    }
+
+    #[test]
+    fn spans_know_their_line_and_column() {
+        let input = "select *\n  from users\n where ü = 1;";
+        let start = input.find("from").unwrap();
+        let span = Span::new(input, start, start + 4);
+        assert_eq!((span.line, span.column), (2, 3));
+        assert_eq!(span.underline(input), "  from users\n  ^^^^");
+
+        // columns count characters , not bytes
+        let start = input.find('=').unwrap();
+        let span = Span::new(input, start, start + 1);
+        assert_eq!((span.line, span.column), (3, 10));
+        assert_eq!(span.underline(input), " where ü = 1;\n         ^");
+
+        // an empty span at the end still gets one mark
+        let span = Span::new(input, input.len(), input.len());
+        assert_eq!(span.underline(input), " where ü = 1;\n             ^");
+    }
+
+    #[test]
+    fn lexer_errors_point_at_the_bad_input() {
+        let mut db = Neoqlite::new();
+        let sql = "select @ from t;";
+        let error = failure(&mut db, sql);
+        assert!(matches!(error, Error::Lexer { .. }), "{:?}", error);
+        assert_eq!(
+            pointed_at(&error, sql),
+            ("select @ from t;\n       ^".to_string(), 1, 8)
+        );
+        assert_eq!(error.to_string(), "Invalid token @ at line 1, column 8");
+
+        let sql = "select * from t where a = 'open";
+        let error = failure(&mut db, sql);
+        assert!(matches!(error, Error::Lexer { .. }), "{:?}", error);
+        assert_eq!(
+            pointed_at(&error, sql),
+            (format!("{}\n{}^^^^^", sql, " ".repeat(26)), 1, 27)
+        );
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_token() {
+        let mut db = Neoqlite::new();
+        let sql = "select * form t;";
+        let error = failure(&mut db, sql);
+        assert!(matches!(error, Error::Parse { .. }), "{:?}", error);
+        assert_eq!(
+            pointed_at(&error, sql),
+            ("select * form t;\n         ^^^^".to_string(), 1, 10)
+        );
+
+        // the first token past the end of the statement
+        let sql = "delete from t\nwhere id = 1 garbage more;";
+        let error = failure(&mut db, sql);
+        assert!(matches!(error, Error::Parse { .. }), "{:?}", error);
+        assert_eq!(
+            pointed_at(&error, sql),
+            (
+                "where id = 1 garbage more;\n             ^^^^^^^".to_string(),
+                2,
+                14
+            )
+        );
+
+        // a statement that ends too early points at the end
+        let sql = "select * from";
+        let error = failure(&mut db, sql);
+        assert!(matches!(error, Error::Parse { .. }), "{:?}", error);
+        assert_eq!(
+            pointed_at(&error, sql),
+            ("select * from\n             ^".to_string(), 1, 14)
+        );
+    }
+
+    #[test]
+    fn errors_after_parsing_have_no_span() {
+        let mut db = Neoqlite::new();
+        db.query("create table t (id int primary key, name text notnull);")
+            .unwrap();
+        db.query("insert into t (id, name) values (1, 'a');")
+            .unwrap();
+
+        let cases = [
+            ("insert into t (id, name) values ('x', 'b');", "Schema"),
+            ("insert into t (id, name) values (1, 'b');", "Constraint"),
+            ("insert into t (id) values (2);", "Constraint"),
+            ("select * from nothing;", "NotFound"),
+            ("select nothing from t;", "NotFound"),
+            ("commit;", "Execution"),
+        ];
+        for (sql, kind) in cases {
+            let error = failure(&mut db, sql);
+            let found = match &error {
+                Error::Schema(_) => "Schema",
+                Error::Constraint(_) => "Constraint",
+                Error::NotFound(_) => "NotFound",
+                Error::Execution(_) => "Execution",
+                _ => "something else",
+            };
+            assert_eq!(found, kind, "{} gave {:?}", sql, error);
+            assert!(error.span().is_none());
+        }
+    }
+}
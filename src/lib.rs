@@ -1,2 +1,5 @@
 pub mod core;
+pub mod error;
 pub mod parser;
+
+pub use error::Error;
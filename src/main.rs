@@ -1,51 +1,73 @@
-use neoqlite::core::db::Neoqlite;
+use neoqlite::core::db::{DataType, Neoqlite};
+use neoqlite::core::rows::Rows;
+use neoqlite::Error;
 use std::io::{self, Write};
 
 #[tokio::main]
-async fn main() -> Result<(), String> {
+async fn main() -> Result<(), Error> {
     //let sql_query = "SELECT name, age FROM users WHERE age > 21;";
     //let sql_query = "INSERT INTO users (id,name,email) values('1','masoom','masoom@email.com');";
-    let mut neoqlite = Neoqlite::new();
+    // `cargo run -- my.db` opens a file backed database , otherwise we play in memory
+    let mut neoqlite = match std::env::args().nth(1) {
+        Some(path) => Neoqlite::open(path)?,
+        None => Neoqlite::new(),
+    };
 
-    neoqlite.exec_stmt(
-        "CREATE TABLE otp (
-        id int,
-        otp text notnull,
-        is_valid int
-    );",
-    )?;
+    if !neoqlite.is_persistent() {
+        neoqlite.exec_stmt(
+            "CREATE TABLE otp (
+            id int,
+            otp text notnull,
+            is_valid int
+        );",
+        )?;
 
-    neoqlite
-        .exec_stmt("insert into users(id,email,username) values(1,'masoom','masoom@email.com');")?;
-    neoqlite.exec_stmt(
-        "insert into users(id,email,username) values(2,'notmasoom','notmasoom@email.com');",
-    )?;
-    neoqlite
-        .exec_stmt("insert into users(id,email,username) values(3,'nani','nani@email.com');")?;
-    neoqlite
-        .exec_stmt("insert into users(id,email,username) values(4,'nani','nani@email.com');")?;
-    neoqlite
-        .exec_stmt("insert into users(id,email,username) values(5,'nani','nani@email.com');")?;
+        neoqlite.exec_stmt(
+            "insert into users(id,email,username) values(1,'masoom','masoom@email.com');",
+        )?;
+        neoqlite.exec_stmt(
+            "insert into users(id,email,username) values(2,'notmasoom','notmasoom@email.com');",
+        )?;
+        neoqlite
+            .exec_stmt("insert into users(id,email,username) values(3,'nani','nani@email.com');")?;
+        neoqlite
+            .exec_stmt("insert into users(id,email,username) values(4,'nani','nani@email.com');")?;
+        neoqlite
+            .exec_stmt("insert into users(id,email,username) values(5,'nani','nani@email.com');")?;
 
-    neoqlite.exec_stmt(
-        "insert into users(id,email,username,otp) values(6,'mehdi','mehdi@email.com',645);",
-    )?;
+        neoqlite.exec_stmt(
+            "insert into users(id,email,username,otp) values(6,'mehdi','mehdi@email.com',645);",
+        )?;
 
-    neoqlite.set_debug(true);
+        neoqlite.set_debug(true);
 
-    neoqlite.exec_stmt("delete from users where id = 5;")?;
+        neoqlite.exec_stmt("delete from users where id = 5;")?;
 
-    neoqlite.exec_stmt("select * from users;")?;
+        print_rows(&neoqlite.query("select * from users;")?);
+    }
 
     //neoqlite.exec_stmt("select * from users where id = 5;")?;
 
     //println!("{:?}", neoqlite);
     loop {
         print!("neoqlite => ");
-        let input: String = get_input().await?;
-        let res = neoqlite.exec_stmt(&input);
-        if !res.is_ok() {
-            println!("\n'{}'\n", res.err().unwrap());
+        let input: String = match get_input().await? {
+            Some(input) => input,
+            None => break,
+        };
+        if input.is_empty() {
+            continue;
+        }
+        let result = if input.starts_with('.') {
+            neoqlite.exec_stmt(&input)
+        } else {
+            neoqlite.query(&input).map(|rows| print_rows(&rows))
+        };
+        if let Err(e) = result {
+            match e.span() {
+                Some(span) => println!("\n{}\n{}\n", span.underline(&input), e),
+                None => println!("\n'{}'\n", e),
+            }
         }
         if input == ".q" {
             break;
@@ -56,11 +78,31 @@ async fn main() -> Result<(), String> {
     Ok(())
 }
 
-async fn get_input() -> Result<String, String> {
+// column names and then one line per row , NULLs are left empty like in the sqlite shell
+fn print_rows(rows: &Rows) {
+    if rows.columns().is_empty() {
+        return;
+    }
+    println!("{}", rows.columns().join(" | "));
+    for row in rows {
+        let values: Vec<String> = row
+            .values()
+            .iter()
+            .map(|value| match value {
+                DataType::Null => String::new(),
+                value => value.to_string(),
+            })
+            .collect();
+        println!("{}", values.join(" | "));
+    }
+}
+
+// None once stdin is closed
+async fn get_input() -> Result<Option<String>, Error> {
     let mut input_string: String = String::new();
-    io::stdout().flush().unwrap();
-    match io::stdin().read_line(&mut input_string) {
-        Ok(_) => Ok(input_string.trim().to_string()),
-        Err(e) => Err(e.to_string()),
+    io::stdout().flush()?;
+    match io::stdin().read_line(&mut input_string)? {
+        0 => Ok(None),
+        _ => Ok(Some(input_string.trim().to_string())),
     }
 }
@@ -1,10 +1,19 @@
+use crate::error::{Error, Span};
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Ident(String),
     Keyword(Keyword),
     Operator(String),
-    Number(i64),
+    // without its sign , so it can be one past i64::MAX for -9223372036854775808
+    Number(u64),
+    // 3.14 , 1e10 , .5
+    Real(f64),
     StringLiteral(String),
+    // x'0AFF'
+    Blob(Vec<u8>),
+    // ? , ?NNN or :name , as written
+    Parameter(String),
     Comma,
     Semicolon,
     LParen,
@@ -37,6 +46,25 @@ pub enum Keyword {
     GroupBy,
     OrderBy,
     Limit,
+    Pragma,
+    Begin,
+    Commit,
+    Rollback,
+    Transaction,
+    Savepoint,
+    Release,
+    To,
+    Drop,
+    Alter,
+    Add,
+    Column,
+    Rename,
+    If,
+    Exists,
+    Null,
+    Is,
+    True,
+    False,
 }
 
 /*
@@ -64,6 +92,7 @@ impl Lexer {
             "WHERE" => Token::Keyword(Keyword::Where),
             "INSERT" => Token::Keyword(Keyword::Insert),
             "UPDATE" => Token::Keyword(Keyword::Update),
+            "SET" => Token::Keyword(Keyword::Set),
             "DELETE" => Token::Keyword(Keyword::Delete),
             "CREATE" => Token::Keyword(Keyword::Create),
             "AND" => Token::Keyword(Keyword::And),
@@ -80,91 +109,243 @@ impl Lexer {
             "LIMIT" => Token::Keyword(Keyword::Limit),
             "VALUES" => Token::Keyword(Keyword::Values),
             "TABLE" => Token::Keyword(Keyword::Table),
+            "PRAGMA" => Token::Keyword(Keyword::Pragma),
+            "BEGIN" => Token::Keyword(Keyword::Begin),
+            "COMMIT" => Token::Keyword(Keyword::Commit),
+            "ROLLBACK" => Token::Keyword(Keyword::Rollback),
+            "TRANSACTION" => Token::Keyword(Keyword::Transaction),
+            "SAVEPOINT" => Token::Keyword(Keyword::Savepoint),
+            "RELEASE" => Token::Keyword(Keyword::Release),
+            "TO" => Token::Keyword(Keyword::To),
+            "DROP" => Token::Keyword(Keyword::Drop),
+            "ALTER" => Token::Keyword(Keyword::Alter),
+            "ADD" => Token::Keyword(Keyword::Add),
+            "COLUMN" => Token::Keyword(Keyword::Column),
+            "RENAME" => Token::Keyword(Keyword::Rename),
+            "IF" => Token::Keyword(Keyword::If),
+            "EXISTS" => Token::Keyword(Keyword::Exists),
+            "NULL" => Token::Keyword(Keyword::Null),
+            "IS" => Token::Keyword(Keyword::Is),
+            "TRUE" => Token::Keyword(Keyword::True),
+            "FALSE" => Token::Keyword(Keyword::False),
             _ => Token::Ident(token.to_string()),
         }
     }
 
-    pub fn tokenize(&self) -> Vec<Token> {
+    /*
+     *   Every token comes with where it is in the input , the last one is
+     *   always EOF so the parser has somewhere to point at when a statement
+     *   ends too early.
+     */
+    pub fn tokenize(&self) -> Result<Vec<(Token, Span)>, Error> {
+        let input = self.input.as_str();
         let mut tokens = Vec::new();
         let mut current = String::new();
-        let mut chars = self.input.chars().peekable();
+        let mut chars = input.char_indices().peekable();
 
-        while let Some(c) = chars.next() {
-            match c {
+        while let Some((start, c)) = chars.next() {
+            let token = match c {
                 ' ' | '\t' | '\n' | '\r' => {
-                    while let Some(&next) = chars.peek() {
+                    while let Some(&(_, next)) = chars.peek() {
                         if next.is_whitespace() {
                             chars.next();
                         } else {
                             break;
                         }
                     }
+                    None
+                }
+                'x' | 'X' if matches!(chars.peek(), Some((_, '\''))) => {
+                    chars.next();
+                    let mut closed = false;
+                    for (_, next) in chars.by_ref() {
+                        if next == '\'' {
+                            closed = true;
+                            break;
+                        }
+                        current.push(next);
+                    }
+                    let end = chars.peek().map(|&(i, _)| i).unwrap_or(input.len());
+                    match Self::parse_blob(&current) {
+                        Some(bytes) if closed => Some(Token::Blob(bytes)),
+                        _ => {
+                            return Err(Error::Lexer {
+                                message: "Malformed blob literal , expected x'<hex digits>'"
+                                    .to_string(),
+                                span: Span::new(input, start, end),
+                            })
+                        }
+                    }
                 }
-                'a'..='z' | 'A'..='Z' | '_' | '*' => {
+                'a'..='z' | 'A'..='Z' | '_' => {
                     current.push(c);
-                    while let Some(&next) = chars.peek() {
+                    while let Some(&(_, next)) = chars.peek() {
                         if next.is_alphanumeric() || next == '_' {
-                            current.push(chars.next().unwrap());
+                            current.push(next);
+                            chars.next();
                         } else {
                             break;
                         }
                     }
-
-                    tokens.push(Self::classify_token(&current));
-                    current.clear();
+                    Some(Self::classify_token(&current))
                 }
-                '0'..='9' => {
+                /*
+                 *   digits [. digits] [e [+-] digits] , a number with a point or
+                 *   an exponent is REAL. `-5` is a unary minus and 5 , the parser
+                 *   folds them into one number.
+                 */
+                '0'..='9' | '.' if c != '.' || matches!(chars.peek(), Some((_, '0'..='9'))) => {
                     current.push(c);
-                    while let Some(&next) = chars.peek() {
-                        if next.is_digit(10) {
-                            current.push(chars.next().unwrap());
+                    let mut real = c == '.';
+                    while let Some(&(_, next)) = chars.peek() {
+                        if next.is_ascii_digit() || (next == '.' && !real) {
+                            real |= next == '.';
+                        } else if (next == 'e' || next == 'E') && !current.contains(['e', 'E']) {
+                            real = true;
+                            current.push(next);
+                            chars.next();
+                            if let Some(&(_, sign @ ('+' | '-'))) = chars.peek() {
+                                current.push(sign);
+                                chars.next();
+                            }
+                            continue;
                         } else {
                             break;
                         }
+                        current.push(next);
+                        chars.next();
                     }
 
-                    if let Ok(num) = current.parse::<i64>() {
-                        tokens.push(Token::Number(num));
+                    let end = start + current.len();
+                    if real {
+                        match current.parse::<f64>() {
+                            Ok(num) => Some(Token::Real(num)),
+                            Err(_) => {
+                                return Err(Error::Lexer {
+                                    message: format!("Malformed number {}", current),
+                                    span: Span::new(input, start, end),
+                                });
+                            }
+                        }
+                    } else {
+                        match current.parse::<u64>() {
+                            Ok(num) if num <= i64::MIN.unsigned_abs() => Some(Token::Number(num)),
+                            _ => {
+                                return Err(Error::Lexer {
+                                    message: format!("Number {} is too large", current),
+                                    span: Span::new(input, start, end),
+                                });
+                            }
+                        }
                     }
-
-                    current.clear();
                 }
 
-                '\'' | '\"' => {
-                    while let Some(&next) = chars.peek() {
-                        if next == '\'' || next == '\"' {
-                            chars.next().unwrap();
-                            break;
-                        } else {
-                            current.push(chars.next().unwrap());
+                /*
+                 *   'text' is a string , "name" is an identifier that can hold
+                 *   anything (spaces , keywords). The quote is escaped by doubling
+                 *   it: 'it''s'
+                 */
+                '\'' | '"' => {
+                    let mut closed = false;
+                    while let Some((_, next)) = chars.next() {
+                        if next == c {
+                            if chars.peek().map(|&(_, n)| n) == Some(c) {
+                                chars.next();
+                            } else {
+                                closed = true;
+                                break;
+                            }
                         }
+                        current.push(next);
                     }
 
-                    tokens.push(Token::Ident(current.clone()));
-                    current.clear();
+                    if !closed {
+                        return Err(Error::Lexer {
+                            message: "Unterminated quote".to_string(),
+                            span: Span::new(input, start, input.len()),
+                        });
+                    } else if c == '\'' {
+                        Some(Token::StringLiteral(current.clone()))
+                    } else {
+                        Some(Token::Ident(current.clone()))
+                    }
                 }
 
                 '=' | '>' | '<' | '!' => {
                     current.push(c);
-                    if let Some(&next) = chars.peek() {
-                        if next == '=' {
-                            current.push(chars.next().unwrap());
+                    if let Some(&(_, next)) = chars.peek() {
+                        if next == '=' || (c == '<' && next == '>') {
+                            current.push(next);
+                            chars.next();
                         }
                     }
+                    Some(Token::Operator(current.clone()))
+                }
+
+                '+' | '-' | '*' | '/' | '%' => Some(Token::Operator(c.to_string())),
 
-                    tokens.push(Token::Operator(current.clone()));
-                    current.clear();
+                '|' if matches!(chars.peek(), Some((_, '|'))) => {
+                    chars.next();
+                    Some(Token::Operator("||".to_string()))
+                }
+
+                '?' | ':' => {
+                    current.push(c);
+                    while let Some(&(_, next)) = chars.peek() {
+                        let fits = if c == '?' {
+                            next.is_ascii_digit()
+                        } else {
+                            next.is_alphanumeric() || next == '_'
+                        };
+                        if fits {
+                            current.push(next);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    if c == ':' && current.len() == 1 {
+                        return Err(Error::Lexer {
+                            message: "Expected a parameter name after ':'".to_string(),
+                            span: Span::new(input, start, start + 1),
+                        });
+                    }
+                    Some(Token::Parameter(current.clone()))
                 }
 
-                ',' => tokens.push(Token::Comma),
-                ';' => tokens.push(Token::Semicolon),
-                '(' => tokens.push(Token::LParen),
-                ')' => tokens.push(Token::RParen),
-                _ => println!("Invalid token {}", c),
+                ',' => Some(Token::Comma),
+                ';' => Some(Token::Semicolon),
+                '(' => Some(Token::LParen),
+                ')' => Some(Token::RParen),
+                _ => {
+                    return Err(Error::Lexer {
+                        message: format!("Invalid token {}", c),
+                        span: Span::new(input, start, start + c.len_utf8()),
+                    })
+                }
+            };
+
+            if let Some(token) = token {
+                let end = chars.peek().map(|&(i, _)| i).unwrap_or(input.len());
+                tokens.push((token, Span::new(input, start, end)));
             }
+            current.clear();
         }
 
-        tokens
+        let end = Span::new(input, input.len(), input.len());
+        tokens.push((Token::EOF, end));
+        Ok(tokens)
+    }
+
+    // pairs of hex digits , None if there is an odd number or a non hex digit
+    fn parse_blob(hex: &str) -> Option<Vec<u8>> {
+        if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect()
     }
 
     pub fn new(input: &str) -> Self {
@@ -173,3 +354,136 @@ impl Lexer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the tokens without their spans or the EOF at the end
+    fn tokens(input: &str) -> Vec<Token> {
+        let mut tokens: Vec<Token> = Lexer::new(input)
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect();
+        assert_eq!(tokens.pop(), Some(Token::EOF));
+        tokens
+    }
+
+    fn lexer_error(input: &str) -> (String, Span) {
+        match Lexer::new(input).tokenize() {
+            Err(Error::Lexer { message, span }) => (message, span),
+            result => panic!("{} gave {:?}", input, result),
+        }
+    }
+
+    #[test]
+    fn quotes_tell_strings_from_identifiers() {
+        assert_eq!(
+            tokens("'masoom' \"masoom\" masoom"),
+            vec![
+                Token::StringLiteral("masoom".to_string()),
+                Token::Ident("masoom".to_string()),
+                Token::Ident("masoom".to_string()),
+            ]
+        );
+        // a quoted name can be a keyword or hold spaces , it stays a name
+        assert_eq!(
+            tokens("\"select\" \"first name\" select"),
+            vec![
+                Token::Ident("select".to_string()),
+                Token::Ident("first name".to_string()),
+                Token::Keyword(Keyword::Select),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_doubled_quote_is_one_quote() {
+        assert_eq!(
+            tokens("'it''s' '''' \"a\"\"b\" ''"),
+            vec![
+                Token::StringLiteral("it's".to_string()),
+                Token::StringLiteral("'".to_string()),
+                Token::Ident("a\"b".to_string()),
+                Token::StringLiteral(String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn an_unterminated_quote_is_an_error() {
+        for input in ["select 'abc", "select \"abc", "select 'it''s"] {
+            let (message, span) = lexer_error(input);
+            assert_eq!(message, "Unterminated quote");
+            assert_eq!((span.start, span.end), (7, input.len()));
+        }
+    }
+
+    #[test]
+    fn blob_literals() {
+        assert_eq!(
+            tokens("x'0aFF' X'' x"),
+            vec![
+                Token::Blob(vec![0x0a, 0xff]),
+                Token::Blob(Vec::new()),
+                Token::Ident("x".to_string()),
+            ]
+        );
+        for input in ["x'abc'", "x'zz'", "x'00"] {
+            let (message, span) = lexer_error(input);
+            assert!(message.starts_with("Malformed blob literal"), "{}", input);
+            assert_eq!(span.start, 0);
+        }
+    }
+
+    #[test]
+    fn numbers_and_reals() {
+        assert_eq!(
+            tokens("42 2.75 1e3 2.5E-2 .5 7."),
+            vec![
+                Token::Number(42),
+                Token::Real(2.75),
+                Token::Real(1000.0),
+                Token::Real(0.025),
+                Token::Real(0.5),
+                Token::Real(7.0),
+            ]
+        );
+        // the sign is a token of its own , 2^63 is only allowed for -2^63
+        assert_eq!(
+            tokens("-9223372036854775808"),
+            vec![
+                Token::Operator("-".to_string()),
+                Token::Number(9223372036854775808),
+            ]
+        );
+        let (message, _) = lexer_error("9223372036854775809");
+        assert_eq!(message, "Number 9223372036854775809 is too large");
+    }
+
+    #[test]
+    fn every_token_knows_where_it_is() {
+        let input = "select a,\n  'b c' from t;";
+        let spans: Vec<(usize, usize, usize, usize)> = Lexer::new(input)
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|(_, span)| (span.start, span.end, span.line, span.column))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (0, 6, 1, 1),
+                (7, 8, 1, 8),
+                (8, 9, 1, 9),
+                (12, 17, 2, 3),
+                (18, 22, 2, 9),
+                (23, 24, 2, 14),
+                (24, 25, 2, 15),
+                (25, 25, 2, 16),
+            ]
+        );
+    }
+}
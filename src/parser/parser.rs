@@ -1,32 +1,243 @@
 use crate::{
-    core::db::{ColumnType, Constraints},
+    core::db::{Check, ColumnType, Constraints, DataType, Reference, ReferentialAction},
+    error::{Error, Span},
     parser::lexer::{Keyword, Token},
 };
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Modulo,
+    Concat,
+}
+
+// NOT binds looser than comparisons , `NOT a = b` is `NOT (a = b)`
+const NOT_PRECEDENCE: u8 = 3;
+// IS [NOT] NULL and [NOT] BETWEEN bind like =
+const IS_PRECEDENCE: u8 = 4;
+// unary - and + bind tighter than every binary operator
+const UNARY_PRECEDENCE: u8 = 9;
+// same limit as sqlite
+const MAX_PARAMETERS: usize = 32766;
+
+impl BinaryOp {
+    // higher binds tighter , every binary operator is left associative
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq | BinaryOp::NotEq => 4,
+            BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq => 5,
+            BinaryOp::Plus | BinaryOp::Minus => 6,
+            BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => 7,
+            BinaryOp::Concat => 8,
+        }
+    }
+
+    fn from_token(token: &Token) -> Option<Self> {
+        let op = match token {
+            Token::Keyword(Keyword::Or) => BinaryOp::Or,
+            Token::Keyword(Keyword::And) => BinaryOp::And,
+            Token::Operator(op) => match op.as_str() {
+                "=" | "==" => BinaryOp::Eq,
+                "!=" | "<>" => BinaryOp::NotEq,
+                "<" => BinaryOp::Lt,
+                "<=" => BinaryOp::LtEq,
+                ">" => BinaryOp::Gt,
+                ">=" => BinaryOp::GtEq,
+                "+" => BinaryOp::Plus,
+                "-" => BinaryOp::Minus,
+                "*" => BinaryOp::Multiply,
+                "/" => BinaryOp::Divide,
+                "%" => BinaryOp::Modulo,
+                "||" => BinaryOp::Concat,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(op)
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            BinaryOp::Or => "OR",
+            BinaryOp::And => "AND",
+            BinaryOp::Eq => "=",
+            BinaryOp::NotEq => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::Plus => "+",
+            BinaryOp::Minus => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::Concat => "||",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Not,
+    Minus,
+    Plus,
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
+    // a column
     Ident(String),
+    // 'quoted text'
+    String(String),
     Number(i64),
+    Real(f64),
+    Boolean(bool),
+    // x'0AFF'
+    Blob(Vec<u8>),
+    Null,
+    // a placeholder , numbered from 1 in the order sqlite would number them
+    Parameter(usize),
+    Binary {
+        left: Box<Expr>,
+        op: BinaryOp,
+        right: Box<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Function {
+        name: String,
+        args: Vec<Expr>,
+    },
+    // expr IS NULL , or IS NOT NULL when negated
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    // expr BETWEEN low AND high , or NOT BETWEEN when negated
+    Between {
+        expr: Box<Expr>,
+        negated: bool,
+        low: Box<Expr>,
+        high: Box<Expr>,
+    },
+    // kept around so the expression prints the way it was written
+    Nested(Box<Expr>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Ident(name) => write!(f, "{}", name),
+            Expr::String(s) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expr::Number(n) => write!(f, "{}", n),
+            // same spelling as the values they turn into
+            Expr::Real(n) => write!(f, "{}", DataType::Real(*n)),
+            Expr::Boolean(b) => write!(f, "{}", DataType::Boolean(*b)),
+            Expr::Blob(bytes) => write!(f, "{}", DataType::Blob(bytes.clone())),
+            Expr::Null => write!(f, "NULL"),
+            Expr::Parameter(i) => write!(f, "?{}", i),
+            Expr::Binary { left, op, right } => write!(f, "{} {} {}", left, op, right),
+            Expr::Unary {
+                op: UnaryOp::Not,
+                expr,
+            } => write!(f, "NOT {}", expr),
+            Expr::Unary {
+                op: UnaryOp::Minus,
+                expr,
+            } => write!(f, "-{}", expr),
+            Expr::Unary {
+                op: UnaryOp::Plus,
+                expr,
+            } => write!(f, "+{}", expr),
+            Expr::Function { name, args } if args.is_empty() && is_current_function(name) => {
+                write!(f, "{}", name)
+            }
+            Expr::Function { name, args } => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Expr::IsNull {
+                expr,
+                negated: false,
+            } => write!(f, "{} IS NULL", expr),
+            Expr::IsNull {
+                expr,
+                negated: true,
+            } => write!(f, "{} IS NOT NULL", expr),
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => {
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, "{} {}BETWEEN {} AND {}", expr, not, low, high)
+            }
+            Expr::Nested(expr) => write!(f, "({})", expr),
+        }
+    }
+}
+
+// CURRENT_TIMESTAMP , CURRENT_DATE and CURRENT_TIME are called without parentheses
+fn is_current_function(name: &str) -> bool {
+    ["CURRENT_TIMESTAMP", "CURRENT_DATE", "CURRENT_TIME"]
+        .iter()
+        .any(|function| function.eq_ignore_ascii_case(name))
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct WhereClause {
-    pub left: Expr,
-    pub operator: String,
-    pub right: Expr,
+pub enum SelectItem {
+    // *
+    Wildcard,
+    Expr { expr: Expr, alias: Option<String> },
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SelectQuery {
-    pub columns: Vec<String>,
+    pub columns: Vec<SelectItem>,
     pub table: String,
-    pub where_clause: Option<WhereClause>,
+    pub where_clause: Option<Expr>,
+    // (expr , descending) in the order they were written
+    pub order_by: Vec<(Expr, bool)>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct DeleteQuery {
     pub table: String,
-    pub where_clause: Option<WhereClause>,
+    pub where_clause: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateQuery {
+    pub table: String,
+    // (column , new value) in the order they were written
+    pub assignments: Vec<(String, Expr)>,
+    pub where_clause: Option<Expr>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -40,11 +251,80 @@ pub struct InsertQuery {
 pub struct CreateTableQuery {
     pub table: String,
     pub columns: Vec<(String, ColumnType, Vec<Constraints>)>,
+    // the ones written after the columns
+    pub constraints: Vec<TableConstraint>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableConstraint {
+    // PRIMARY KEY (a , b)
+    PrimaryKey(Vec<String>),
+    // UNIQUE (a , b)
+    Unique(Vec<String>),
+    // FOREIGN KEY (a , b) REFERENCES ...
+    ForeignKey(Vec<String>, Reference),
+    // [CONSTRAINT name] CHECK (expr)
+    Check(Check),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DropTableQuery {
+    pub table: String,
+    pub if_exists: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateIndexQuery {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+    pub if_not_exists: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DropIndexQuery {
+    pub name: String,
+    pub if_exists: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlterTableAction {
+    AddColumn(String, ColumnType, Vec<Constraints>),
+    DropColumn(String),
+    RenameColumn { from: String, to: String },
+    RenameTo(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlterTableQuery {
+    pub table: String,
+    pub action: AlterTableAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PragmaQuery {
+    pub name: String,
+    pub value: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransactionStmt {
+    Begin,
+    Commit,
+    Rollback,
+    Savepoint(String),
+    Release(String),
+    RollbackTo(String),
 }
 
 pub struct Parser {
     tokens: Vec<Token>,
+    // where every token is in the input , for errors
+    spans: Vec<Span>,
     position: usize,
+    // one entry per parameter number , the name for :name parameters
+    parameters: Vec<Option<String>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -52,14 +332,83 @@ pub enum Query {
     Insert(InsertQuery),
     Select(SelectQuery),
     Delete(DeleteQuery),
+    Update(UpdateQuery),
     CreateTable(CreateTableQuery),
+    DropTable(DropTableQuery),
+    AlterTable(AlterTableQuery),
+    CreateIndex(CreateIndexQuery),
+    DropIndex(DropIndexQuery),
+    Pragma(PragmaQuery),
+    Transaction(TransactionStmt),
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<(Token, Span)>) -> Self {
+        let (tokens, spans) = tokens.into_iter().unzip();
         Self {
             tokens,
+            spans,
             position: 0,
+            parameters: Vec::new(),
+        }
+    }
+
+    // the parameters of what has been parsed so far , parameter n is at n - 1
+    pub fn parameters(&self) -> &[Option<String>] {
+        &self.parameters
+    }
+
+    /*
+     *   ?      the next number after the largest one so far
+     *   ?NNN   number NNN
+     *   :name  a new number the first time , the same one after that
+     */
+    fn parameter_index(&mut self, parameter: &str) -> Result<usize, Error> {
+        if let Some(name) = parameter.strip_prefix(':') {
+            if let Some(i) = self
+                .parameters
+                .iter()
+                .position(|p| p.as_deref() == Some(name))
+            {
+                return Ok(i + 1);
+            }
+            self.parameters.push(Some(name.to_string()));
+            return Ok(self.parameters.len());
+        }
+
+        let number = &parameter[1..];
+        if number.is_empty() {
+            self.parameters.push(None);
+            return Ok(self.parameters.len());
+        }
+        let index = match number.parse::<usize>() {
+            Ok(index) if (1..=MAX_PARAMETERS).contains(&index) => index,
+            _ => {
+                return Err(self.error(format!(
+                    "Parameter {} has to be between ?1 and ?{}",
+                    parameter, MAX_PARAMETERS
+                )))
+            }
+        };
+        if index > self.parameters.len() {
+            self.parameters.resize(index, None);
+        }
+        Ok(index)
+    }
+
+    // past the end means the last token , which is EOF coming from the lexer
+    fn current_span(&self) -> Span {
+        self.spans
+            .get(self.position)
+            .or(self.spans.last())
+            .copied()
+            .unwrap_or_else(|| Span::new("", 0, 0))
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::Parse {
+            message,
+            span: self.current_span(),
         }
     }
 
@@ -75,77 +424,81 @@ impl Parser {
         self.position += 1;
     }
 
-    fn expect_peek_token(&mut self, token: Token) -> Result<(), String> {
-        if let Some(ref curr_token) = self.peek_token() {
-            println!(
-                "curr_token is {:?} ,expected token is {:?}",
-                curr_token, token
-            );
+    fn expect_token(&mut self, token: Token) -> Result<(), Error> {
+        if let Some(ref curr_token) = self.current_token() {
             if **curr_token == token {
+                self.advance();
                 Ok(())
             } else {
-                Err(format!("Expected {:?}, but found {:?}", token, curr_token))
+                Err(self.error(format!("Expected {:?}, but found {:?}", token, curr_token)))
             }
         } else {
-            Err(format!(
+            Err(self.error(format!(
                 "Expected {:?}, but found {:?}",
                 token,
                 self.current_token()
-            ))
+            )))
         }
     }
-
-    fn expect_token(&mut self, token: Token) -> Result<(), String> {
-        if let Some(ref curr_token) = self.current_token() {
-            if **curr_token == token {
+    fn expect_keyword(&mut self, keyword: Keyword) -> Result<(), Error> {
+        if let Some(Token::Keyword(ref kw)) = self.current_token() {
+            if *kw == keyword {
                 self.advance();
                 Ok(())
             } else {
-                Err(format!("Expected {:?}, but found {:?}", token, curr_token))
+                Err(self.error(format!("Expected {:?}, but found {:?}", keyword, kw)))
             }
         } else {
-            Err(format!(
+            Err(self.error(format!(
                 "Expected {:?}, but found {:?}",
-                token,
+                keyword,
                 self.current_token()
-            ))
+            )))
         }
     }
-    fn expect_keyword(&mut self, keyword: Keyword) -> Result<(), String> {
+
+    // for optional noise words like the TRANSACTION in BEGIN TRANSACTION
+    fn skip_keyword(&mut self, keyword: Keyword) {
         if let Some(Token::Keyword(ref kw)) = self.current_token() {
             if *kw == keyword {
                 self.advance();
-                Ok(())
-            } else {
-                Err(format!("Expected {:?}, but found {:?}", keyword, kw))
             }
-        } else {
-            Err(format!(
-                "Expected {:?}, but found {:?}",
-                keyword,
-                self.current_token()
-            ))
         }
     }
 
-    fn expect_identifier(&mut self) -> Result<String, String> {
+    fn expect_identifier(&mut self) -> Result<String, Error> {
         if let Some(Token::Ident(ref id)) = self.current_token() {
             let identifier = id.clone();
             self.advance();
             Ok(identifier)
         } else {
-            Err(format!(
+            Err(self.error(format!(
                 "Expected identifier, but found {:?}",
                 self.current_token()
-            ))
+            )))
         }
     }
 
-    fn parse_insert(&mut self) -> Result<InsertQuery, String> {
-        self.expect_keyword(Keyword::Insert)?;
-        self.expect_keyword(Keyword::Into)?;
+    // words like PRIMARY and KEY are plain identifiers , they are only special in their place
+    fn is_word(token: Option<&Token>, word: &str) -> bool {
+        matches!(token, Some(Token::Ident(id)) if id.eq_ignore_ascii_case(word))
+    }
 
-        let table = self.expect_identifier()?;
+    fn expect_word(&mut self, word: &str) -> Result<(), Error> {
+        if Self::is_word(self.current_token(), word) {
+            self.advance();
+            Ok(())
+        } else {
+            Err(self.error(format!(
+                "Expected {}, but found {:?}",
+                word,
+                self.current_token()
+            )))
+        }
+    }
+
+    // (name [, name]*)
+    fn parse_column_list(&mut self) -> Result<Vec<String>, Error> {
         self.expect_token(Token::LParen)?;
         let mut columns = Vec::new();
         loop {
@@ -156,8 +509,16 @@ impl Parser {
                 break;
             }
         }
-
         self.expect_token(Token::RParen)?;
+        Ok(columns)
+    }
+
+    fn parse_insert(&mut self) -> Result<InsertQuery, Error> {
+        self.expect_keyword(Keyword::Insert)?;
+        self.expect_keyword(Keyword::Into)?;
+
+        let table = self.expect_identifier()?;
+        let columns = self.parse_column_list()?;
         self.expect_keyword(Keyword::Values)?;
         self.expect_token(Token::LParen)?;
         let mut values = Vec::new();
@@ -178,137 +539,759 @@ impl Parser {
         })
     }
 
-    fn to_column_type(input: &str) -> Result<ColumnType, String> {
+    fn parse_column_type(&mut self) -> Result<ColumnType, Error> {
+        let span = self.current_span();
+        let input = self.expect_identifier()?;
         match input.to_uppercase().as_str() {
             "INT" => Ok(ColumnType::Int),
             "TEXT" => Ok(ColumnType::String),
-            _ => Err(format!("Invalid Column type , got {}", input)),
+            "REAL" | "FLOAT" | "DOUBLE" => Ok(ColumnType::Real),
+            "BOOLEAN" | "BOOL" => Ok(ColumnType::Boolean),
+            "BLOB" => Ok(ColumnType::Blob),
+            "DATE" => Ok(ColumnType::Date),
+            "TIME" => Ok(ColumnType::Time),
+            "TIMESTAMP" | "DATETIME" => Ok(ColumnType::Timestamp),
+            _ => Err(Error::Parse {
+                message: format!("Invalid Column type , got {}", input),
+                span,
+            }),
         }
     }
 
-    fn parse_constraint(input: &str) -> Result<Constraints, String> {
+    fn parse_constraint(&mut self) -> Result<Constraints, Error> {
+        // CONSTRAINT name can go in front of any of them , only CHECK keeps it
+        let name = self.parse_constraint_name()?;
+        let span = self.current_span();
+        // NULL and NOT NULL are keywords , NOTNULL is an identifier
+        match self.current_token() {
+            Some(Token::Keyword(Keyword::Null)) => {
+                self.advance();
+                return Ok(Constraints::Null);
+            }
+            Some(Token::Keyword(Keyword::Not)) => {
+                self.advance();
+                self.expect_keyword(Keyword::Null)?;
+                return Ok(Constraints::NotNull);
+            }
+            _ => {}
+        }
+        let input = self.expect_identifier()?;
         match input.to_uppercase().as_str() {
             "NOTNULL" => Ok(Constraints::NotNull),
             "NULL" => Ok(Constraints::Null),
-            _ => Err(format!("Invalid constraint got {}", input)),
+            "PRIMARY" => {
+                self.expect_word("KEY")?;
+                Ok(Constraints::PrimaryKey)
+            }
+            "UNIQUE" => Ok(Constraints::Unique),
+            "REFERENCES" => Ok(Constraints::ForeignKey(self.parse_reference()?)),
+            "CHECK" => Ok(Constraints::Check(self.parse_check(name)?)),
+            "DEFAULT" => Ok(Constraints::Default(self.parse_default()?)),
+            _ => Err(Error::Parse {
+                message: format!("Invalid constraint got {}", input),
+                span,
+            }),
+        }
+    }
+
+    /*
+     *   PRIMARY KEY (column [, column]*)
+     *   UNIQUE (column [, column]*)
+     *   FOREIGN KEY (column [, column]*) REFERENCES ...
+     *   CHECK (expr)
+     *
+     *   each one can have a CONSTRAINT name in front. None when the next
+     *   thing is a column definition instead.
+     */
+    fn parse_table_constraint(&mut self) -> Result<Option<TableConstraint>, Error> {
+        let span = self.current_span();
+        let name = self.parse_constraint_name()?;
+        if Self::is_word(self.current_token(), "CHECK")
+            && matches!(self.peek_token(), Some(Token::LParen))
+        {
+            self.advance();
+            return Ok(Some(TableConstraint::Check(self.parse_check(name)?)));
+        }
+        match (self.parse_unnamed_table_constraint()?, name) {
+            (None, Some(name)) => Err(Error::Parse {
+                message: format!("Expected a table constraint after CONSTRAINT {}", name),
+                span,
+            }),
+            (constraint, _) => Ok(constraint),
+        }
+    }
+
+    fn parse_unnamed_table_constraint(&mut self) -> Result<Option<TableConstraint>, Error> {
+        if Self::is_word(self.current_token(), "PRIMARY") && Self::is_word(self.peek_token(), "KEY")
+        {
+            self.advance();
+            self.advance();
+            return Ok(Some(TableConstraint::PrimaryKey(self.parse_column_list()?)));
+        }
+        if Self::is_word(self.current_token(), "UNIQUE")
+            && matches!(self.peek_token(), Some(Token::LParen))
+        {
+            self.advance();
+            return Ok(Some(TableConstraint::Unique(self.parse_column_list()?)));
+        }
+        if Self::is_word(self.current_token(), "FOREIGN") && Self::is_word(self.peek_token(), "KEY")
+        {
+            self.advance();
+            self.advance();
+            let columns = self.parse_column_list()?;
+            self.expect_word("REFERENCES")?;
+            let reference = self.parse_reference()?;
+            return Ok(Some(TableConstraint::ForeignKey(columns, reference)));
+        }
+        Ok(None)
+    }
+
+    // CONSTRAINT name , None when there is no name
+    fn parse_constraint_name(&mut self) -> Result<Option<String>, Error> {
+        if !Self::is_word(self.current_token(), "CONSTRAINT") {
+            return Ok(None);
+        }
+        self.advance();
+        Ok(Some(self.expect_identifier()?))
+    }
+
+    // what comes after CHECK: (expr)
+    fn parse_check(&mut self, name: Option<String>) -> Result<Check, Error> {
+        self.expect_token(Token::LParen)?;
+        let expr = self.parse_expr()?;
+        self.expect_token(Token::RParen)?;
+        Ok(Check { name, expr })
+    }
+
+    /*
+     *   what comes after DEFAULT: a literal , a signed number , CURRENT_TIMESTAMP
+     *   and friends or an (expr). Anything longer needs the parentheses , a
+     *   NOT NULL right after the value is the next constraint.
+     */
+    fn parse_default(&mut self) -> Result<Expr, Error> {
+        if let Some(Token::Keyword(Keyword::Not)) = self.current_token() {
+            return Err(self.error(format!(
+                "Expected a default value, but found {:?}",
+                self.current_token()
+            )));
+        }
+        self.parse_prefix()
+    }
+
+    // what comes after REFERENCES: table [(column [, column]*)] [ON DELETE|UPDATE action]*
+    fn parse_reference(&mut self) -> Result<Reference, Error> {
+        let table = self.expect_identifier()?;
+        let columns = match self.current_token() {
+            Some(Token::LParen) => self.parse_column_list()?,
+            _ => Vec::new(),
+        };
+        let mut reference = Reference {
+            table,
+            columns,
+            on_delete: ReferentialAction::NoAction,
+            on_update: ReferentialAction::NoAction,
+        };
+        while let Some(Token::Keyword(Keyword::On)) = self.current_token() {
+            self.advance();
+            let span = self.current_span();
+            let token = self.current_token().cloned();
+            self.advance();
+            match token {
+                Some(Token::Keyword(Keyword::Delete)) => {
+                    reference.on_delete = self.parse_referential_action()?
+                }
+                Some(Token::Keyword(Keyword::Update)) => {
+                    reference.on_update = self.parse_referential_action()?
+                }
+                token => {
+                    return Err(Error::Parse {
+                        message: format!("Expected DELETE or UPDATE, but found {:?}", token),
+                        span,
+                    })
+                }
+            }
         }
+        Ok(reference)
     }
 
-    fn parse_create_table(&mut self) -> Result<CreateTableQuery, String> {
+    // CASCADE , SET NULL , RESTRICT or NO ACTION
+    fn parse_referential_action(&mut self) -> Result<ReferentialAction, Error> {
+        if let Some(Token::Keyword(Keyword::Set)) = self.current_token() {
+            self.advance();
+            self.expect_keyword(Keyword::Null)?;
+            return Ok(ReferentialAction::SetNull);
+        }
+        let span = self.current_span();
+        let input = self.expect_identifier()?;
+        match input.to_uppercase().as_str() {
+            "CASCADE" => Ok(ReferentialAction::Cascade),
+            "RESTRICT" => Ok(ReferentialAction::Restrict),
+            "NO" => {
+                self.expect_word("ACTION")?;
+                Ok(ReferentialAction::NoAction)
+            }
+            _ => Err(Error::Parse {
+                message: format!(
+                    "Expected CASCADE, SET NULL, RESTRICT or NO ACTION, but found {}",
+                    input
+                ),
+                span,
+            }),
+        }
+    }
+
+    fn parse_create_table(&mut self) -> Result<CreateTableQuery, Error> {
         self.expect_keyword(Keyword::Create)?;
         self.expect_keyword(Keyword::Table)?;
         let table = self.expect_identifier()?;
         self.expect_token(Token::LParen)?;
         let mut columns: Vec<(String, ColumnType, Vec<Constraints>)> = Vec::new();
+        let mut table_constraints = Vec::new();
         loop {
-            let column_name = self.expect_identifier()?;
-            let column_type = Self::to_column_type(&self.expect_identifier()?)?;
-            let mut constraints: Vec<Constraints> = Vec::new();
-            println!("here");
-            loop {
-                println!("{:?}", self.current_token());
-                if matches!(self.current_token(), Some(Token::Comma))
-                    || matches!(self.current_token(), Some(Token::RParen))
-                    || self.expect_peek_token(Token::RParen).is_ok()
-                {
-                    break;
-                } else {
-                    let constraint = Self::parse_constraint(&self.expect_identifier()?)?;
-                    constraints.push(constraint);
+            if let Some(constraint) = self.parse_table_constraint()? {
+                table_constraints.push(constraint);
+            } else {
+                let column_name = self.expect_identifier()?;
+                let column_type = self.parse_column_type()?;
+                let mut constraints: Vec<Constraints> = Vec::new();
+                loop {
+                    if matches!(self.current_token(), Some(Token::Comma))
+                        || matches!(self.current_token(), Some(Token::RParen))
+                    {
+                        break;
+                    } else {
+                        let constraint = self.parse_constraint()?;
+                        constraints.push(constraint);
+                    }
                 }
-            }
 
-            columns.push((column_name, column_type, constraints));
-            println!("after {:?}", columns);
+                columns.push((column_name, column_type, constraints));
+            }
 
             if let Some(Token::Comma) = self.current_token() {
                 /*
                  *   just in case if we have been in a place that after comma is a right paren
                  */
-                match self.peek_token() {
-                    Some(Token::RParen) => {
-                        //self.advance();
-                        break;
-                    }
-                    _ => {}
+                if let Some(Token::RParen) = self.peek_token() {
+                    //self.advance();
+                    break;
                 }
                 self.advance();
             } else {
                 break;
             }
         }
-        println!("columns : {:?}", columns);
         self.expect_token(Token::RParen)?;
         self.expect_token(Token::Semicolon)?;
 
-        Ok(CreateTableQuery { table, columns })
+        Ok(CreateTableQuery {
+            table,
+            columns,
+            constraints: table_constraints,
+        })
+    }
+
+    // DROP TABLE [IF EXISTS] name;
+    fn parse_drop_table(&mut self) -> Result<DropTableQuery, Error> {
+        self.expect_keyword(Keyword::Drop)?;
+        self.expect_keyword(Keyword::Table)?;
+        let if_exists = if self.expect_keyword(Keyword::If).is_ok() {
+            self.expect_keyword(Keyword::Exists)?;
+            true
+        } else {
+            false
+        };
+        let table = self.expect_identifier()?;
+        if let Some(Token::Semicolon) = self.current_token() {
+            self.advance();
+        }
+        Ok(DropTableQuery { table, if_exists })
+    }
+
+    // CREATE [UNIQUE] INDEX [IF NOT EXISTS] name ON table (column [, column]*);
+    fn parse_create_index(&mut self) -> Result<CreateIndexQuery, Error> {
+        self.expect_keyword(Keyword::Create)?;
+        let unique = Self::is_word(self.current_token(), "UNIQUE");
+        if unique {
+            self.advance();
+        }
+        self.expect_word("INDEX")?;
+        let if_not_exists = if self.expect_keyword(Keyword::If).is_ok() {
+            self.expect_keyword(Keyword::Not)?;
+            self.expect_keyword(Keyword::Exists)?;
+            true
+        } else {
+            false
+        };
+        let name = self.expect_identifier()?;
+        self.expect_keyword(Keyword::On)?;
+        let table = self.expect_identifier()?;
+        let columns = self.parse_column_list()?;
+        if let Some(Token::Semicolon) = self.current_token() {
+            self.advance();
+        }
+        Ok(CreateIndexQuery {
+            name,
+            table,
+            columns,
+            unique,
+            if_not_exists,
+        })
+    }
+
+    // DROP INDEX [IF EXISTS] name;
+    fn parse_drop_index(&mut self) -> Result<DropIndexQuery, Error> {
+        self.expect_keyword(Keyword::Drop)?;
+        self.expect_word("INDEX")?;
+        let if_exists = if self.expect_keyword(Keyword::If).is_ok() {
+            self.expect_keyword(Keyword::Exists)?;
+            true
+        } else {
+            false
+        };
+        let name = self.expect_identifier()?;
+        if let Some(Token::Semicolon) = self.current_token() {
+            self.advance();
+        }
+        Ok(DropIndexQuery { name, if_exists })
     }
 
-    pub fn parse(&mut self) -> Result<Query, String> {
-        let result = match &mut self.tokens.first().unwrap() {
+    /*
+     *   ALTER TABLE name ADD [COLUMN] column type [constraint]*;
+     *   ALTER TABLE name DROP [COLUMN] column;
+     *   ALTER TABLE name RENAME [COLUMN] column TO new_name;
+     *   ALTER TABLE name RENAME TO new_name;
+     */
+    fn parse_alter_table(&mut self) -> Result<AlterTableQuery, Error> {
+        self.expect_keyword(Keyword::Alter)?;
+        self.expect_keyword(Keyword::Table)?;
+        let table = self.expect_identifier()?;
+
+        let span = self.current_span();
+        let token = self.current_token().cloned();
+        self.advance();
+        let action = match token {
+            Some(Token::Keyword(Keyword::Add)) => {
+                self.skip_keyword(Keyword::Column);
+                let column_name = self.expect_identifier()?;
+                let column_type = self.parse_column_type()?;
+                let mut constraints = Vec::new();
+                while let Some(Token::Ident(_) | Token::Keyword(Keyword::Not | Keyword::Null)) =
+                    self.current_token()
+                {
+                    constraints.push(self.parse_constraint()?);
+                }
+                AlterTableAction::AddColumn(column_name, column_type, constraints)
+            }
+            Some(Token::Keyword(Keyword::Drop)) => {
+                self.skip_keyword(Keyword::Column);
+                AlterTableAction::DropColumn(self.expect_identifier()?)
+            }
+            Some(Token::Keyword(Keyword::Rename)) => {
+                if self.expect_keyword(Keyword::To).is_ok() {
+                    AlterTableAction::RenameTo(self.expect_identifier()?)
+                } else {
+                    self.skip_keyword(Keyword::Column);
+                    let from = self.expect_identifier()?;
+                    self.expect_keyword(Keyword::To)?;
+                    let to = self.expect_identifier()?;
+                    AlterTableAction::RenameColumn { from, to }
+                }
+            }
+            token => {
+                return Err(Error::Parse {
+                    message: format!("Expected ADD, DROP or RENAME, but found {:?}", token),
+                    span,
+                })
+            }
+        };
+        if let Some(Token::Semicolon) = self.current_token() {
+            self.advance();
+        }
+        Ok(AlterTableQuery { table, action })
+    }
+
+    pub fn parse(&mut self) -> Result<Query, Error> {
+        let token = match self.current_token() {
+            Some(Token::EOF) | None => return Err(self.error("Empty statement".to_string())),
+            Some(token) => token.clone(),
+        };
+        let result = match token {
             Token::Keyword(Keyword::Select) => Query::Select(self.parse_select()?),
             Token::Keyword(Keyword::Insert) => Query::Insert(self.parse_insert()?),
             Token::Keyword(Keyword::Delete) => Query::Delete(self.parse_delete()?),
-            Token::Keyword(Keyword::Create) => Query::CreateTable(self.parse_create_table()?),
-            _ => return Err(format!("Invalid Query got {:?}", self.tokens.first())),
+            Token::Keyword(Keyword::Update) => Query::Update(self.parse_update()?),
+            Token::Keyword(Keyword::Create) => match self.peek_token() {
+                Some(Token::Keyword(Keyword::Table)) => {
+                    Query::CreateTable(self.parse_create_table()?)
+                }
+                _ => Query::CreateIndex(self.parse_create_index()?),
+            },
+            Token::Keyword(Keyword::Drop) => match self.peek_token() {
+                Some(Token::Keyword(Keyword::Table)) => Query::DropTable(self.parse_drop_table()?),
+                _ => Query::DropIndex(self.parse_drop_index()?),
+            },
+            Token::Keyword(Keyword::Alter) => Query::AlterTable(self.parse_alter_table()?),
+            Token::Keyword(Keyword::Pragma) => Query::Pragma(self.parse_pragma()?),
+            Token::Keyword(Keyword::Begin)
+            | Token::Keyword(Keyword::Commit)
+            | Token::Keyword(Keyword::Rollback)
+            | Token::Keyword(Keyword::Savepoint)
+            | Token::Keyword(Keyword::Release) => Query::Transaction(self.parse_transaction()?),
+            token => return Err(self.error(format!("Invalid Query got {:?}", token))),
         };
-        Ok(result)
+
+        // whatever is left is syntax we dont know , running the statement without
+        // it would do something else than what was asked for
+        if let Some(Token::Semicolon) = self.current_token() {
+            self.advance();
+        }
+        match self.current_token() {
+            Some(Token::EOF) | None => Ok(result),
+            Some(token) => Err(self.error(format!(
+                "Unexpected {:?} after the end of the statement",
+                token
+            ))),
+        }
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expr, Error> {
+        self.parse_expr_with_precedence(0)
+    }
+
+    /*
+     *   Precedence climbing , loosest to tightest:
+     *
+     *   OR
+     *   AND
+     *   NOT
+     *   = == != <> IS [NOT] NULL
+     *   < <= > >=
+     *   + -
+     *   * / %
+     *   ||
+     *   unary - +
+     *
+     *   Parses a prefix and then keeps eating binary operators that bind at
+     *   least as tight as `min_precedence`. The right hand side only gets
+     *   operators that bind tighter , that is what makes them left associative.
+     */
+    fn parse_expr_with_precedence(&mut self, min_precedence: u8) -> Result<Expr, Error> {
+        let mut left = self.parse_prefix()?;
+        loop {
+            let negated = matches!(self.current_token(), Some(Token::Keyword(Keyword::Not)))
+                && Self::is_word(self.peek_token(), "BETWEEN");
+            if negated || Self::is_word(self.current_token(), "BETWEEN") {
+                if IS_PRECEDENCE < min_precedence {
+                    break;
+                }
+                if negated {
+                    self.advance();
+                }
+                self.advance();
+                // the AND in the middle belongs to the BETWEEN
+                let low = self.parse_expr_with_precedence(IS_PRECEDENCE + 1)?;
+                self.expect_keyword(Keyword::And)?;
+                let high = self.parse_expr_with_precedence(IS_PRECEDENCE + 1)?;
+                left = Expr::Between {
+                    expr: Box::new(left),
+                    negated,
+                    low: Box::new(low),
+                    high: Box::new(high),
+                };
+                continue;
+            }
+            if let Some(Token::Keyword(Keyword::Is)) = self.current_token() {
+                if IS_PRECEDENCE < min_precedence {
+                    break;
+                }
+                self.advance();
+                let negated = self.expect_keyword(Keyword::Not).is_ok();
+                self.expect_keyword(Keyword::Null)?;
+                left = Expr::IsNull {
+                    expr: Box::new(left),
+                    negated,
+                };
+                continue;
+            }
+            // [NOT] IN and [NOT] LIKE are keywords we cant evaluate yet , stopping
+            // here would quietly cut the rest of the condition off
+            let operator = match self.current_token() {
+                Some(Token::Keyword(Keyword::Not)) => self.peek_token(),
+                token => token,
+            };
+            match operator {
+                Some(Token::Keyword(Keyword::In)) => {
+                    return Err(self.error("IN is not supported".to_string()))
+                }
+                Some(Token::Keyword(Keyword::Like)) => {
+                    return Err(self.error("LIKE is not supported".to_string()))
+                }
+                _ => {}
+            }
+            let op = match self.current_token().and_then(BinaryOp::from_token) {
+                Some(op) => op,
+                None => break,
+            };
+            let precedence = op.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            self.advance();
+            let right = self.parse_expr_with_precedence(precedence + 1)?;
+            left = Expr::Binary {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
     }
 
-    fn parse_expr(&mut self) -> Result<Expr, String> {
+    fn parse_prefix(&mut self) -> Result<Expr, Error> {
         let token = self.current_token().cloned();
         match token {
+            Some(Token::Keyword(Keyword::Not)) => {
+                self.advance();
+                let expr = self.parse_expr_with_precedence(NOT_PRECEDENCE)?;
+                Ok(Expr::Unary {
+                    op: UnaryOp::Not,
+                    expr: Box::new(expr),
+                })
+            }
+            Some(Token::Operator(op)) if op == "-" || op == "+" => {
+                self.advance();
+                // -9223372036854775808 only fits once the minus is part of the number
+                if let (true, Some(Token::Number(num))) = (op == "-", self.current_token()) {
+                    let num = (*num as i64).wrapping_neg();
+                    self.advance();
+                    return Ok(Expr::Number(num));
+                }
+                let expr = self.parse_expr_with_precedence(UNARY_PRECEDENCE)?;
+                let op = if op == "-" {
+                    UnaryOp::Minus
+                } else {
+                    UnaryOp::Plus
+                };
+                Ok(Expr::Unary {
+                    op,
+                    expr: Box::new(expr),
+                })
+            }
+            Some(Token::LParen) => {
+                self.advance();
+                let expr = self.parse_expr()?;
+                self.expect_token(Token::RParen)?;
+                Ok(Expr::Nested(Box::new(expr)))
+            }
             Some(Token::Ident(id)) => {
                 self.advance();
-                Ok(Expr::Ident(id.to_string()))
+                if let Some(Token::LParen) = self.current_token() {
+                    return self.parse_function_args(id);
+                }
+                if is_current_function(&id) {
+                    return Ok(Expr::Function {
+                        name: id,
+                        args: Vec::new(),
+                    });
+                }
+                Ok(Expr::Ident(id))
+            }
+            Some(Token::StringLiteral(s)) => {
+                self.advance();
+                Ok(Expr::String(s))
             }
             Some(Token::Number(num)) => {
+                let num = i64::try_from(num)
+                    .map_err(|_| self.error(format!("Number {} is too large", num)))?;
                 self.advance();
                 Ok(Expr::Number(num))
             }
-            _ => Err(format!(
+            Some(Token::Real(num)) => {
+                self.advance();
+                Ok(Expr::Real(num))
+            }
+            Some(Token::Blob(bytes)) => {
+                self.advance();
+                Ok(Expr::Blob(bytes))
+            }
+            Some(Token::Keyword(keyword @ (Keyword::True | Keyword::False))) => {
+                self.advance();
+                Ok(Expr::Boolean(keyword == Keyword::True))
+            }
+            Some(Token::Keyword(Keyword::Null)) => {
+                self.advance();
+                Ok(Expr::Null)
+            }
+            Some(Token::Parameter(parameter)) => {
+                let index = self.parameter_index(&parameter)?;
+                self.advance();
+                Ok(Expr::Parameter(index))
+            }
+            _ => Err(self.error(format!(
                 "Expected expression, but found {:?}",
                 self.current_token()
-            )),
+            ))),
         }
     }
 
-    pub fn parse_where_clause(&mut self) -> Option<WhereClause> {
-        let does_have_where = self.expect_keyword(Keyword::Where);
-        let where_clause = {
-            if does_have_where.is_ok() {
-                let left = self.parse_expr().ok()?;
-                let _ = self.expect_token(Token::Operator("=".to_string())).ok()?;
-                let right = self.parse_expr().ok()?;
-                Some(WhereClause {
-                    left,
-                    operator: "=".to_string(),
-                    right,
-                })
+    // name ( [expr [, expr]*] )
+    fn parse_function_args(&mut self, name: String) -> Result<Expr, Error> {
+        self.expect_token(Token::LParen)?;
+        let mut args = Vec::new();
+        if let Some(Token::RParen) = self.current_token() {
+            self.advance();
+            return Ok(Expr::Function { name, args });
+        }
+        loop {
+            args.push(self.parse_expr()?);
+            if let Some(Token::Comma) = self.current_token() {
+                self.advance();
             } else {
-                None
+                break;
             }
-        };
-        where_clause
+        }
+        self.expect_token(Token::RParen)?;
+        Ok(Expr::Function { name, args })
     }
 
-    pub fn parse_delete(&mut self) -> Result<DeleteQuery, String> {
+    pub fn parse_where_clause(&mut self) -> Result<Option<Expr>, Error> {
+        if self.expect_keyword(Keyword::Where).is_err() {
+            return Ok(None);
+        }
+        Ok(Some(self.parse_expr()?))
+    }
+
+    pub fn parse_delete(&mut self) -> Result<DeleteQuery, Error> {
         self.expect_keyword(Keyword::Delete)?;
         self.expect_keyword(Keyword::From)?;
         let table = self.expect_identifier()?;
-        let where_clause = self.parse_where_clause();
+        let where_clause = self.parse_where_clause()?;
+        if let Some(Token::Semicolon) = self.current_token() {
+            self.advance();
+        }
         Ok(DeleteQuery {
             table,
             where_clause,
         })
     }
 
-    pub fn parse_select(&mut self) -> Result<SelectQuery, String> {
+    // UPDATE table SET column = expr [, column = expr]* [WHERE expr];
+    pub fn parse_update(&mut self) -> Result<UpdateQuery, Error> {
+        self.expect_keyword(Keyword::Update)?;
+        let table = self.expect_identifier()?;
+        self.expect_keyword(Keyword::Set)?;
+
+        let mut assignments = Vec::new();
+        loop {
+            let column = self.expect_identifier()?;
+            self.expect_token(Token::Operator("=".to_string()))?;
+            assignments.push((column, self.parse_expr()?));
+            if let Some(Token::Comma) = self.current_token() {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+
+        let where_clause = self.parse_where_clause()?;
+        if let Some(Token::Semicolon) = self.current_token() {
+            self.advance();
+        }
+        Ok(UpdateQuery {
+            table,
+            assignments,
+            where_clause,
+        })
+    }
+
+    /*
+     *   BEGIN [TRANSACTION];
+     *   COMMIT [TRANSACTION];
+     *   ROLLBACK [TRANSACTION];
+     *   SAVEPOINT name;
+     *   RELEASE [SAVEPOINT] name;
+     *   ROLLBACK [TRANSACTION] TO [SAVEPOINT] name;
+     */
+    pub fn parse_transaction(&mut self) -> Result<TransactionStmt, Error> {
+        let span = self.current_span();
+        let token = self.current_token().cloned();
+        self.advance();
+        let stmt = match token {
+            Some(Token::Keyword(Keyword::Begin)) => {
+                self.skip_keyword(Keyword::Transaction);
+                TransactionStmt::Begin
+            }
+            Some(Token::Keyword(Keyword::Commit)) => {
+                self.skip_keyword(Keyword::Transaction);
+                TransactionStmt::Commit
+            }
+            Some(Token::Keyword(Keyword::Rollback)) => {
+                self.skip_keyword(Keyword::Transaction);
+                if self.expect_keyword(Keyword::To).is_ok() {
+                    self.skip_keyword(Keyword::Savepoint);
+                    TransactionStmt::RollbackTo(self.expect_identifier()?)
+                } else {
+                    TransactionStmt::Rollback
+                }
+            }
+            Some(Token::Keyword(Keyword::Savepoint)) => {
+                TransactionStmt::Savepoint(self.expect_identifier()?)
+            }
+            Some(Token::Keyword(Keyword::Release)) => {
+                self.skip_keyword(Keyword::Savepoint);
+                TransactionStmt::Release(self.expect_identifier()?)
+            }
+            token => {
+                return Err(Error::Parse {
+                    message: format!(
+                        "Expected BEGIN, COMMIT, ROLLBACK, SAVEPOINT or RELEASE, but found {:?}",
+                        token
+                    ),
+                    span,
+                })
+            }
+        };
+        if let Some(Token::Semicolon) = self.current_token() {
+            self.advance();
+        }
+        Ok(stmt)
+    }
+
+    /*
+     *   PRAGMA name;
+     *   PRAGMA name = value;
+     */
+    pub fn parse_pragma(&mut self) -> Result<PragmaQuery, Error> {
+        self.expect_keyword(Keyword::Pragma)?;
+        let name = self.expect_identifier()?;
+        let value = if let Some(Token::Operator(op)) = self.current_token() {
+            if op != "=" {
+                return Err(self.error(format!("Expected '=' after pragma name, but found {}", op)));
+            }
+            self.advance();
+            // ON is a keyword , `PRAGMA foreign_keys = ON` reads it as TRUE
+            if let Some(Token::Keyword(Keyword::On)) = self.current_token() {
+                self.advance();
+                Some(Expr::Boolean(true))
+            } else {
+                Some(self.parse_expr()?)
+            }
+        } else {
+            None
+        };
+        if let Some(Token::Semicolon) = self.current_token() {
+            self.advance();
+        }
+        Ok(PragmaQuery { name, value })
+    }
+
+    pub fn parse_select(&mut self) -> Result<SelectQuery, Error> {
         self.expect_keyword(Keyword::Select)?;
 
         let mut columns = Vec::new();
         loop {
-            columns.push(self.expect_identifier()?);
+            columns.push(self.parse_select_item()?);
             if let Some(Token::Comma) = self.current_token() {
                 self.advance();
             } else {
@@ -322,7 +1305,8 @@ impl Parser {
 
         //let where_clause = self.parse_where_clause()?;
 
-        let where_clause = self.parse_where_clause();
+        let where_clause = self.parse_where_clause()?;
+        let order_by = self.parse_order_by()?;
         if let Some(Token::Semicolon) = self.current_token() {
             self.advance();
         }
@@ -331,6 +1315,246 @@ impl Parser {
             columns,
             table,
             where_clause,
+            order_by,
         })
     }
+
+    // [ORDER BY expr [ASC|DESC] [, expr [ASC|DESC]]*]
+    fn parse_order_by(&mut self) -> Result<Vec<(Expr, bool)>, Error> {
+        let mut order_by = Vec::new();
+        if self.expect_keyword(Keyword::OrderBy).is_err() {
+            return Ok(order_by);
+        }
+        self.expect_word("BY")?;
+        loop {
+            let expr = self.parse_expr()?;
+            let descending = Self::is_word(self.current_token(), "DESC");
+            if descending || Self::is_word(self.current_token(), "ASC") {
+                self.advance();
+            }
+            order_by.push((expr, descending));
+            if let Some(Token::Comma) = self.current_token() {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        Ok(order_by)
+    }
+
+    // * | expr [AS alias]
+    fn parse_select_item(&mut self) -> Result<SelectItem, Error> {
+        if let Some(Token::Operator(op)) = self.current_token() {
+            if op == "*" {
+                self.advance();
+                return Ok(SelectItem::Wildcard);
+            }
+        }
+        let expr = self.parse_expr()?;
+        let alias = if self.expect_keyword(Keyword::As).is_ok() {
+            Some(self.expect_identifier()?)
+        } else {
+            None
+        };
+        Ok(SelectItem::Expr { expr, alias })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::lexer::Lexer;
+
+    fn parse(sql: &str) -> Result<Query, Error> {
+        Parser::new(Lexer::new(sql).tokenize()?).parse()
+    }
+
+    #[test]
+    fn leftover_tokens_are_an_error() {
+        for sql in [
+            "delete from t where id in (2);",
+            "select * from t limit 1;",
+            "select * from t where id = 1 garbage;",
+            "update t set a = 1 where id = 1 limit 1;",
+            "insert into t (id) values (1) (2);",
+            "begin; commit;",
+        ] {
+            assert!(matches!(parse(sql), Err(Error::Parse { .. })), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn the_semicolon_is_optional() {
+        for sql in [
+            "select * from t where id = 1",
+            "select * from t where id = 1;",
+            "delete from t where id = 1",
+            "commit",
+        ] {
+            assert!(parse(sql).is_ok(), "{}", sql);
+        }
+    }
+
+    fn expr(sql: &str) -> Result<Expr, Error> {
+        Parser::new(Lexer::new(sql).tokenize()?).parse_expr()
+    }
+
+    // the expression with every operator in parentheses , to see how it was grouped
+    fn grouped(expr: &Expr) -> String {
+        match expr {
+            Expr::Binary { left, op, right } => {
+                format!("({} {} {})", grouped(left), op, grouped(right))
+            }
+            Expr::Unary {
+                op: UnaryOp::Not,
+                expr,
+            } => format!("(NOT {})", grouped(expr)),
+            Expr::Unary {
+                op: UnaryOp::Minus,
+                expr,
+            } => format!("(-{})", grouped(expr)),
+            Expr::Unary {
+                op: UnaryOp::Plus,
+                expr,
+            } => format!("(+{})", grouped(expr)),
+            Expr::IsNull { expr, negated } => {
+                let not = if *negated { "NOT " } else { "" };
+                format!("({} IS {}NULL)", grouped(expr), not)
+            }
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => {
+                let not = if *negated { "NOT " } else { "" };
+                format!(
+                    "({} {}BETWEEN {} AND {})",
+                    grouped(expr),
+                    not,
+                    grouped(low),
+                    grouped(high)
+                )
+            }
+            Expr::Nested(expr) => grouped(expr),
+            expr => expr.to_string(),
+        }
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        for (sql, expected) in [
+            ("1 + 2 * 3", "(1 + (2 * 3))"),
+            ("1 * 2 + 3", "((1 * 2) + 3)"),
+            ("(1 + 2) * 3", "((1 + 2) * 3)"),
+            ("a OR b AND c", "(a OR (b AND c))"),
+            ("a AND b OR c", "((a AND b) OR c)"),
+            ("NOT a = b", "(NOT (a = b))"),
+            ("NOT a AND b", "((NOT a) AND b)"),
+            ("a < b = c < d", "((a < b) = (c < d))"),
+            ("-a * b", "((-a) * b)"),
+            ("- 2 || 'x'", "(-2 || 'x')"),
+            ("a + b || c", "(a + (b || c))"),
+            ("a || b + c", "((a || b) + c)"),
+            (
+                "a + 1 BETWEEN b AND c + 1",
+                "((a + 1) BETWEEN b AND (c + 1))",
+            ),
+            ("a BETWEEN 1 AND 2 AND b", "((a BETWEEN 1 AND 2) AND b)"),
+            (
+                "a NOT BETWEEN 1 AND 2 OR b",
+                "((a NOT BETWEEN 1 AND 2) OR b)",
+            ),
+            ("a + 1 IS NULL", "((a + 1) IS NULL)"),
+            (
+                "a IS NULL OR b IS NOT NULL",
+                "((a IS NULL) OR (b IS NOT NULL))",
+            ),
+            ("NOT a IS NULL", "(NOT (a IS NULL))"),
+            ("a = b IS NULL", "((a = b) IS NULL)"),
+        ] {
+            assert_eq!(grouped(&expr(sql).unwrap()), expected, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn binary_operators_are_left_associative() {
+        for (sql, expected) in [
+            ("1 - 2 - 3", "((1 - 2) - 3)"),
+            ("8 / 4 / 2", "((8 / 4) / 2)"),
+            ("a || b || c", "((a || b) || c)"),
+            ("a OR b OR c", "((a OR b) OR c)"),
+            ("a = b = c", "((a = b) = c)"),
+        ] {
+            assert_eq!(grouped(&expr(sql).unwrap()), expected, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn the_minus_is_part_of_the_number() {
+        assert_eq!(expr("-9223372036854775808"), Ok(Expr::Number(i64::MIN)));
+        assert_eq!(expr("9223372036854775807"), Ok(Expr::Number(i64::MAX)));
+        assert_eq!(expr("- 5"), Ok(Expr::Number(-5)));
+        assert_eq!(
+            expr("1 - -2"),
+            Ok(Expr::Binary {
+                left: Box::new(Expr::Number(1)),
+                op: BinaryOp::Minus,
+                right: Box::new(Expr::Number(-2)),
+            })
+        );
+        // anything else keeps its unary minus
+        assert!(matches!(
+            expr("-a"),
+            Ok(Expr::Unary {
+                op: UnaryOp::Minus,
+                ..
+            })
+        ));
+        assert!(matches!(
+            expr("-1.5"),
+            Ok(Expr::Unary {
+                op: UnaryOp::Minus,
+                ..
+            })
+        ));
+        assert!(matches!(
+            expr("9223372036854775808"),
+            Err(Error::Parse { message, .. }) if message == "Number 9223372036854775808 is too large"
+        ));
+        assert!(matches!(
+            expr("- -9223372036854775808"),
+            Ok(Expr::Unary { .. })
+        ));
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(expr("2.75"), Ok(Expr::Real(2.75)));
+        assert_eq!(expr("TRUE"), Ok(Expr::Boolean(true)));
+        assert_eq!(expr("false"), Ok(Expr::Boolean(false)));
+        assert_eq!(expr("x'00ff'"), Ok(Expr::Blob(vec![0x00, 0xff])));
+        assert_eq!(expr("NULL"), Ok(Expr::Null));
+        // printing gives back something that parses to the same thing
+        for sql in ["2.75", "TRUE", "x'00ff'", "-9223372036854775808", "1e20"] {
+            let parsed = expr(sql).unwrap();
+            assert_eq!(expr(&parsed.to_string()), Ok(parsed), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn in_and_like_are_errors() {
+        for sql in [
+            "id IN (2)",
+            "id NOT IN (2)",
+            "name LIKE 'a%'",
+            "name NOT LIKE 'a%'",
+        ] {
+            assert!(matches!(expr(sql), Err(Error::Parse { .. })), "{}", sql);
+        }
+        assert!(matches!(
+            parse("delete from t where id in (2);"),
+            Err(Error::Parse { message, .. }) if message == "IN is not supported"
+        ));
+    }
 }